@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE user
+	ADD api_key TEXT DEFAULT 'local' NOT NULL;
+
+UPDATE user SET api_key = (
+    SELECT key_hash FROM api_key
+    WHERE api_key.user_id = user.id AND api_key.revoked = FALSE
+    ORDER BY api_key.created
+    LIMIT 1
+) WHERE EXISTS (SELECT 1 FROM api_key WHERE api_key.user_id = user.id AND api_key.revoked = FALSE);
+
+DROP TABLE api_key;
@@ -0,0 +1,29 @@
+-- Your SQL goes here
+
+-- Users can hold several named keys, so programs can roll keys without downtime.
+CREATE TABLE api_key (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL,
+    created DATETIME NOT NULL,
+    expires DATETIME,
+    revoked BOOLEAN DEFAULT FALSE NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES user(id) ON DELETE CASCADE
+);
+
+-- Existing keys become each user's "default" key. The local user's 'local' placeholder
+-- was never a real hash, so we skip it.
+INSERT INTO api_key (id, user_id, name, key_hash, created, expires, revoked)
+SELECT
+    lower(substr(h, 1, 8) || '-' || substr(h, 9, 4) || '-4' || substr(h, 14, 3) || '-a' || substr(h, 18, 3) || '-' || substr(h, 21, 12)),
+    id,
+    'default',
+    api_key,
+    CURRENT_TIMESTAMP,
+    NULL,
+    FALSE
+FROM (SELECT hex(randomblob(16)) AS h, id, api_key FROM user WHERE api_key != 'local');
+
+ALTER TABLE user
+	DROP COLUMN api_key;
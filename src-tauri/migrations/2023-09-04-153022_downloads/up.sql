@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+-- In-progress downloads, so they survive crashes and restarts.
+-- id is the uuid the finished LLM will get.
+CREATE TABLE download (
+    id TEXT PRIMARY KEY NOT NULL,
+    llm_registry_entry TEXT NOT NULL,
+    part_path TEXT NOT NULL,
+    downloaded_bytes BIGINT NOT NULL DEFAULT 0,
+    total_bytes BIGINT,
+    status TEXT NOT NULL DEFAULT 'downloading',
+    started DATETIME NOT NULL,
+    updated DATETIME NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE llm ADD sha256 TEXT;
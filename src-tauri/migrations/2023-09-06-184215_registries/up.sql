@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+-- Model registries. cached_index is the last index.json we fetched, so listing
+-- downloadable models keeps working offline.
+CREATE TABLE registry (
+    name TEXT PRIMARY KEY NOT NULL,
+    location TEXT NOT NULL,
+    trust TEXT NOT NULL DEFAULT 'untrusted',
+    cached_index TEXT,
+    last_refreshed DATETIME,
+    added DATETIME NOT NULL
+);
+
+INSERT INTO registry (name, location, trust, added) VALUES ('default', 'https://raw.githubusercontent.com/JuliaMerz/pantry/master/models/index.json', 'trusted', datetime('now'));
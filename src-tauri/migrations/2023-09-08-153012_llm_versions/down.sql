@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE download DROP COLUMN keep_old;
+ALTER TABLE download DROP COLUMN upgrade_of;
+ALTER TABLE llm DROP COLUMN version;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+ALTER TABLE llm ADD version TEXT;
+ALTER TABLE download ADD upgrade_of TEXT;
+ALTER TABLE download ADD keep_old BOOLEAN NOT NULL DEFAULT 0;
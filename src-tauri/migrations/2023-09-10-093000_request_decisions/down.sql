@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE requests DROP COLUMN granted_permissions;
+ALTER TABLE requests DROP COLUMN decision_note;
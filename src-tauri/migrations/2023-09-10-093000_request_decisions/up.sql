@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE requests ADD decision_note TEXT;
+ALTER TABLE requests ADD granted_permissions TEXT;
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP TRIGGER llm_history_fts_update;
+DROP TRIGGER llm_history_fts_delete;
+DROP TRIGGER llm_history_fts_insert;
+DROP TABLE llm_history_fts;
@@ -0,0 +1,29 @@
+-- Your SQL goes here
+
+-- Full text index over prompts and outputs, kept in sync by the triggers below.
+-- It refers to llm_history rows by rowid, which only a VACUUM would renumber; after one,
+-- run INSERT INTO llm_history_fts(llm_history_fts) VALUES('rebuild').
+CREATE VIRTUAL TABLE llm_history_fts USING fts5(
+    input,
+    output,
+    content='llm_history',
+    content_rowid='rowid'
+);
+
+INSERT INTO llm_history_fts(llm_history_fts) VALUES('rebuild');
+
+CREATE TRIGGER llm_history_fts_insert AFTER INSERT ON llm_history BEGIN
+    INSERT INTO llm_history_fts(rowid, input, output) VALUES (new.rowid, new.input, new.output);
+END;
+
+CREATE TRIGGER llm_history_fts_delete AFTER DELETE ON llm_history BEGIN
+    INSERT INTO llm_history_fts(llm_history_fts, rowid, input, output)
+        VALUES ('delete', old.rowid, old.input, old.output);
+END;
+
+-- Outputs are appended to a token at a time, so this fires a lot while prompting.
+CREATE TRIGGER llm_history_fts_update AFTER UPDATE OF input, output ON llm_history BEGIN
+    INSERT INTO llm_history_fts(llm_history_fts, rowid, input, output)
+        VALUES ('delete', old.rowid, old.input, old.output);
+    INSERT INTO llm_history_fts(rowid, input, output) VALUES (new.rowid, new.input, new.output);
+END;
@@ -0,0 +1,24 @@
+-- This file should undo anything in `up.sql`
+
+DROP TRIGGER llm_history_fts_update;
+DROP TRIGGER llm_history_fts_reopen;
+DROP TRIGGER llm_history_fts_complete;
+DROP TRIGGER llm_history_fts_delete;
+DROP TRIGGER llm_history_fts_insert;
+
+INSERT INTO llm_history_fts(llm_history_fts) VALUES('rebuild');
+
+CREATE TRIGGER llm_history_fts_insert AFTER INSERT ON llm_history BEGIN
+    INSERT INTO llm_history_fts(rowid, input, output) VALUES (new.rowid, new.input, new.output);
+END;
+
+CREATE TRIGGER llm_history_fts_delete AFTER DELETE ON llm_history BEGIN
+    INSERT INTO llm_history_fts(llm_history_fts, rowid, input, output)
+        VALUES ('delete', old.rowid, old.input, old.output);
+END;
+
+CREATE TRIGGER llm_history_fts_update AFTER UPDATE OF input, output ON llm_history BEGIN
+    INSERT INTO llm_history_fts(llm_history_fts, rowid, input, output)
+        VALUES ('delete', old.rowid, old.input, old.output);
+    INSERT INTO llm_history_fts(rowid, input, output) VALUES (new.rowid, new.input, new.output);
+END;
@@ -0,0 +1,41 @@
+-- Your SQL goes here
+
+-- Only finished rows are indexed. Outputs are appended a token at a time, and reindexing the
+-- whole output on every token made long generations quadratic. An unfinished row is added
+-- once complete is set, and edits to finished rows are kept in sync.
+-- 'rebuild' would index unfinished rows too, after a VACUUM run the delete-all and the
+-- INSERT ... SELECT below instead.
+DROP TRIGGER llm_history_fts_update;
+DROP TRIGGER llm_history_fts_delete;
+DROP TRIGGER llm_history_fts_insert;
+
+INSERT INTO llm_history_fts(llm_history_fts) VALUES('delete-all');
+INSERT INTO llm_history_fts(rowid, input, output)
+    SELECT rowid, input, output FROM llm_history WHERE complete;
+
+CREATE TRIGGER llm_history_fts_insert AFTER INSERT ON llm_history WHEN new.complete BEGIN
+    INSERT INTO llm_history_fts(rowid, input, output) VALUES (new.rowid, new.input, new.output);
+END;
+
+CREATE TRIGGER llm_history_fts_delete AFTER DELETE ON llm_history WHEN old.complete BEGIN
+    INSERT INTO llm_history_fts(llm_history_fts, rowid, input, output)
+        VALUES ('delete', old.rowid, old.input, old.output);
+END;
+
+CREATE TRIGGER llm_history_fts_complete AFTER UPDATE OF complete ON llm_history
+    WHEN new.complete AND NOT old.complete BEGIN
+    INSERT INTO llm_history_fts(rowid, input, output) VALUES (new.rowid, new.input, new.output);
+END;
+
+CREATE TRIGGER llm_history_fts_reopen AFTER UPDATE OF complete ON llm_history
+    WHEN old.complete AND NOT new.complete BEGIN
+    INSERT INTO llm_history_fts(llm_history_fts, rowid, input, output)
+        VALUES ('delete', old.rowid, old.input, old.output);
+END;
+
+CREATE TRIGGER llm_history_fts_update AFTER UPDATE OF input, output ON llm_history
+    WHEN old.complete AND new.complete BEGIN
+    INSERT INTO llm_history_fts(llm_history_fts, rowid, input, output)
+        VALUES ('delete', old.rowid, old.input, old.output);
+    INSERT INTO llm_history_fts(rowid, input, output) VALUES (new.rowid, new.input, new.output);
+END;
@@ -1,33 +1,326 @@
 use crate::database;
+use crate::disk;
+use crate::download_manager;
+use crate::huggingface;
+use crate::llm::HistorySearchHit;
 
-use crate::registry::{download_and_write_llm, LLMRegistryEntry};
+use crate::registry::{self, download_and_write_llm, hash_file, DownloadInfo, LLMRegistryEntry};
+use crate::registry_index;
+use crate::request::UserRequestType;
+use crate::server::{RequestAdminStatus, UserAdminStatus, UserDetailsStatus};
 use crate::state::GlobalStateWrapper;
 use crate::state::KeychainEntry;
+use crate::state::UserSettings;
+use crate::transfer::{ImportSummary, SessionExport};
+use crate::upgrade;
 use crate::user;
 
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
 use diesel::r2d2::{ConnectionManager, Pool};
 use diesel::sqlite::SqliteConnection;
 
+use futures_util::StreamExt;
+use indicatif::{ProgressBar, ProgressStyle};
 use log::{error, info, warn};
-use pantry_rs::PantryClient;
-use prettytable::{row, Table};
-use serde_json::{from_value, Value};
+use prettytable::{Cell, Row, Table};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::env;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::io::AsyncBufReadExt;
 
 use tauri::api::cli::Matches;
-use tauri::{AppHandle, PackageInfo, State};
+use tauri::{Config, PackageInfo, State};
 
 use uuid::uuid;
 use uuid::Uuid;
 
+// For endpoints pantry-rs doesn't wrap yet. Same credentials and target as the PantryClient.
+struct ApiClient {
+    target: String,
+    user_id: Uuid,
+    api_key: String,
+    // Set when pantry couldn't be reached or refused the credentials.
+    unavailable: AtomicBool,
+}
+
+impl ApiClient {
+    async fn post<T: DeserializeOwned>(&self, path: &str, body: Value) -> Result<T, String> {
+        self.send(path, body)
+            .await?
+            .json::<T>()
+            .await
+            .map_err(|e| format!("Failed to parse response: {:?}", e))
+    }
+
+    // The raw response, for the streaming endpoints.
+    async fn send(&self, path: &str, body: Value) -> Result<reqwest::Response, String> {
+        self.execute(self.request(path).json(&body)).await
+    }
+
+    // Sends body as is, for uploads too big to hold in memory.
+    async fn upload(&self, path: &str, body: reqwest::Body) -> Result<reqwest::Response, String> {
+        self.execute(self.request(path).body(body)).await
+    }
+
+    fn request(&self, path: &str) -> reqwest::RequestBuilder {
+        reqwest::Client::new()
+            .post(format!("{}{}", self.target.trim_end_matches('/'), path))
+            .bearer_auth(format!("{}:{}", self.user_id, self.api_key))
+    }
+
+    async fn execute(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| {
+                self.unavailable.store(true, Ordering::Relaxed);
+                if e.is_connect() {
+                    format!(
+                        "No pantry daemon is running at {}. Open the app, run `pantry serve --headless`, or pass --start.",
+                        self.target
+                    )
+                } else {
+                    format!("Failed to reach pantry: {:?}", e)
+                }
+            })?;
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            self.unavailable.store(true, Ordering::Relaxed);
+        }
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(format!("{}: {}", status, message));
+        }
+        Ok(response)
+    }
+}
+
+// Exit codes, so scripts can tell failures apart.
+pub const EXIT_FAILED: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+// Pantry isn't running, can't be reached, or didn't accept the credentials.
+pub const EXIT_UNAVAILABLE: i32 = 3;
+
+#[derive(Debug)]
+pub struct CliError {
+    pub code: i32,
+    pub message: String,
+}
+
+// Set with the global --output flag. Logs go to stderr, so stdout only carries this.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Json,
+    Table,
+    // Tab separated, no header, for cut and friends.
+    Plain,
+}
+
+impl OutputFormat {
+    fn from_matches(matches: &Matches) -> Result<Self, String> {
+        match matches.args.get("output").map(|arg| &arg.value) {
+            Some(Value::String(format)) => match format.as_str() {
+                "json" => Ok(OutputFormat::Json),
+                "table" => Ok(OutputFormat::Table),
+                "plain" => Ok(OutputFormat::Plain),
+                other => Err(format!(
+                    "Unknown output format {}, expected json, table or plain.",
+                    other
+                )),
+            },
+            _ => Ok(OutputFormat::Table),
+        }
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize output: {:?}", e))?;
+    println!("{}", text);
+    Ok(())
+}
+
+// Table or plain output, json callers print their own structured values.
+fn print_rows(output: OutputFormat, headers: &[&str], rows: Vec<Vec<String>>) {
+    if output == OutputFormat::Plain {
+        for row in rows.iter() {
+            println!("{}", row.join("\t"));
+        }
+        return;
+    }
+    let mut table = Table::new();
+    table.add_row(Row::new(
+        headers
+            .iter()
+            .map(|header| Cell::new(header).style_spec("b"))
+            .collect(),
+    ));
+    for row in rows.iter() {
+        table.add_row(Row::new(row.iter().map(|value| Cell::new(value)).collect()));
+    }
+    table.printstd();
+}
+
+// String fields of the loosely typed API responses.
+fn field(value: &Value, name: &str) -> String {
+    match &value[name] {
+        Value::String(text) => text.clone(),
+        Value::Null => "".into(),
+        other => other.to_string(),
+    }
+}
+
+// Commands that work on the local database and don't need a running daemon.
+const LOCAL_COMMANDS: [&str; 5] = [
+    "registry",
+    "new_cli_user",
+    "revoke_key",
+    "delete_user",
+    "verify",
+];
+
+// Only LOCAL_COMMANDS use this, the rest may be talking to a daemon on another machine.
+fn local_pool(db_path: &str) -> Result<Pool<ConnectionManager<SqliteConnection>>, CliError> {
+    crate::open_database(db_path).map_err(|message| CliError {
+        code: EXIT_FAILED,
+        message,
+    })
+}
+
+// How long --start waits for the daemon to come up.
+const DAEMON_STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+// cli.json, an alternative to the PANTRY_CLI_* env variables. Written by
+// `pantry new_cli_user --save`.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct ClientConfig {
+    pub target: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub api_key: Option<String>,
+    // Start a headless daemon when none is running, like always passing --start.
+    #[serde(default)]
+    pub auto_start: bool,
+}
+
+impl ClientConfig {
+    // PANTRY_CLI_CONFIG, or cli.json next to the user settings.
+    pub fn path(config: &Config) -> Option<PathBuf> {
+        match env::var("PANTRY_CLI_CONFIG") {
+            Ok(path) => Some(PathBuf::from(path)),
+            Err(_) => tauri::api::path::app_local_data_dir(config).map(|mut path| {
+                path.push("cli.json");
+                path
+            }),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(ClientConfig::default());
+        }
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {:?}", path.display(), e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {:?}", parent.display(), e))?;
+        }
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize config: {:?}", e))?;
+        std::fs::write(path, contents)
+            .map_err(|e| format!("Failed to write {}: {:?}", path.display(), e))?;
+        // It holds an API key.
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+                .map_err(|e| format!("Failed to set permissions on {}: {:?}", path.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+// Any HTTP answer means the daemon is up, only connection failures mean it isn't.
+async fn daemon_running(client: &ApiClient) -> bool {
+    match reqwest::Client::new()
+        .get(&client.target)
+        .timeout(Duration::from_secs(2))
+        .send()
+        .await
+    {
+        Ok(_) => true,
+        Err(e) => !e.is_connect() && !e.is_timeout(),
+    }
+}
+
+// Launches `pantry serve --headless` in the background and waits for it to listen.
+async fn start_daemon(client: &ApiClient) -> Result<(), String> {
+    let local = url::Url::parse(&client.target)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_string()))
+        .map(|host| ["localhost", "127.0.0.1", "[::1]"].contains(&host.as_str()))
+        .unwrap_or(false);
+    if !local {
+        return Err(format!(
+            "No pantry daemon is running at {}, and it isn't local so --start can't start one.",
+            client.target
+        ));
+    }
+    let exe = env::current_exe().map_err(|e| format!("Failed to find pantry: {:?}", e))?;
+    info!("No pantry daemon running, starting one.");
+    std::process::Command::new(exe)
+        .args(["serve", "--headless"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to start pantry: {:?}", e))?;
+
+    let started = tokio::time::Instant::now();
+    while started.elapsed() < DAEMON_STARTUP_TIMEOUT {
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        if daemon_running(client).await {
+            return Ok(());
+        }
+    }
+    Err(format!(
+        "Started pantry, but it isn't answering at {} yet.",
+        client.target
+    ))
+}
+
 // We currently handle the CLI entirely through the API, so this is a noop.
 pub fn main_command_response(_argv: Vec<String>, _state: State<GlobalStateWrapper>) {}
 
+// Runs on its own, without building the tauri app, and talks to the daemon over the API.
 pub async fn cli_command_interpreter(
-    app: AppHandle,
+    config: &Config,
     matches: Matches,
-    pool: Pool<ConnectionManager<SqliteConnection>>,
-) -> Result<(), String> {
+    db_path: &str,
+) -> Result<(), CliError> {
+    let output = OutputFormat::from_matches(&matches).map_err(|message| CliError {
+        code: EXIT_USAGE,
+        message,
+    })?;
+    let config_path = ClientConfig::path(config);
+    let client_config = match &config_path {
+        Some(path) => ClientConfig::load(path).map_err(|message| CliError {
+            code: EXIT_USAGE,
+            message,
+        })?,
+        None => ClientConfig::default(),
+    };
     let local_user = uuid!("00000000-0000-0000-0000-000000000000");
     let cli_user_evn = Uuid::parse_str(&(env::var("PANTRY_CLI_USER").unwrap_or("".into())));
     let cli_key_evn = env::var("PANTRY_CLI_KEY").unwrap_or("".into());
@@ -35,6 +328,9 @@ pub async fn cli_command_interpreter(
     // Set up local superuser for API integration
     let (cli_user, cli_key) = match (cli_user_evn, cli_key_evn) {
         (Ok(a), b) => (Some(a), Some(b)),
+        (_, _) if client_config.user_id.is_some() && client_config.api_key.is_some() => {
+            (client_config.user_id, client_config.api_key.clone())
+        }
         (_, _) => match KeychainEntry::new("superuser_key") {
             Ok(pw_entry) => match pw_entry.get_password() {
                 Ok(pw) => {
@@ -47,11 +343,15 @@ pub async fn cli_command_interpreter(
                     // We use the same local user, but give it an API key in the db.
                     // This has the advantage of giving us an easy way of finding the user
                     // so we only need to store the API key in the keystore.
-                    let mut superuser = user::get_local_user();
-                    let api_key = user::generate_api_key();
-                    superuser.api_key = api_key.clone();
-                    match database::save_new_user(superuser, pool.clone()) {
-                        Ok(_user) => match pw_entry.set_password(&api_key) {
+                    let (key, api_key) = user::ApiKey::new(
+                        user::get_local_user().id,
+                        "keychain".into(),
+                        None,
+                    );
+                    match local_pool(db_path).map_err(|e| e.message).and_then(|pool| {
+                        database::save_new_api_key(key, pool).map_err(|e| format!("{:?}", e))
+                    }) {
+                        Ok(_key) => match pw_entry.set_password(&api_key) {
                             Ok(_) => {
                                 info!("Created local superuser");
                                 (Some(local_user), Some(api_key))
@@ -84,314 +384,1899 @@ pub async fn cli_command_interpreter(
     };
 
     if cli_user.is_none() || cli_key.is_none() {
-        error!("Unable to detect cli user/password");
-        return Err("Unable to detect cli user/password".into());
+        return Err(CliError {
+            code: EXIT_UNAVAILABLE,
+            message: "Unable to detect cli user/password".into(),
+        });
     }
 
+    // Without an explicit target, follow the listener settings.
     let cli_target = match env::var("PANTRY_CLI_TARGET") {
         Ok(t) => Some(t),
-        Err(_) => None,
+        Err(_) if client_config.target.is_some() => client_config.target.clone(),
+        Err(_) => match tauri::api::path::app_local_data_dir(config) {
+            Some(path) => {
+                let settings = UserSettings::new(path);
+                if !settings.enable_tcp_listener {
+                    warn!("TCP listener is disabled, set PANTRY_CLI_TARGET to reach pantry.");
+                }
+                Some(settings.api_url())
+            }
+            None => None,
+        },
     };
 
-    let pantry_api = PantryClient::login(cli_user.unwrap(), cli_key.unwrap(), cli_target);
+    let api_client = ApiClient {
+        target: cli_target.unwrap_or("http://localhost:9404".into()),
+        user_id: cli_user.unwrap(),
+        api_key: cli_key.unwrap(),
+        unavailable: AtomicBool::new(false),
+    };
     if let Some(help_text) = matches.args.get("help") {
         println!("{}", help_text.value.as_str().unwrap_or(""));
     }
 
-    if let Some(subcommand) = &matches.subcommand {
-        match subcommand.name.as_str() {
-            "list" => handle_list_subcommand_cli(&subcommand.matches, pool, pantry_api).await,
-            "activate" => {
-                handle_activate_subcommand_cli(&subcommand.matches, pool, pantry_api).await;
-            }
-            "deactivate" => {
-                handle_deactivate_subcommand_cli(&subcommand.matches, pool, pantry_api).await;
-            }
-            "path" => {
-                handle_path_subcommand_cli(&subcommand.matches, pool, pantry_api).await;
-            }
-            "download" => {
-                match handle_download_subcommand_cli(&subcommand.matches, pool, pantry_api).await {
-                    Ok(_) => {}
-                    Err(e) => error!("Download failed: {:?}", e),
-                }
-            }
-            "status" => {
-                match handle_status_subcommand_cli(&subcommand.matches, pool, pantry_api).await {
-                    Ok(_) => {}
-                    Err(e) => error!("Status request failed: {:?}", e),
-                }
-            }
-            "new_cli_user" => {
-                match handle_new_cli_user_subcommand(&subcommand.matches, pool).await {
-                    Ok(_) => {}
-                    Err(e) => error!("New user request failed: {:?}", e),
-                }
-            }
-            _ => {
-                error!("Unrecognized command");
-            }
-        }
-    } else {
+    let subcommand = match &matches.subcommand {
+        Some(subcommand) => subcommand,
         // app.print_help();
+        None => return Ok(()),
     };
-    Ok(())
+    let sub = &subcommand.matches;
+    let auto_start = client_config.auto_start
+        || matches!(
+            matches.args.get("start").map(|arg| &arg.value),
+            Some(Value::Bool(true))
+        );
+    if auto_start
+        && !LOCAL_COMMANDS.contains(&subcommand.name.as_str())
+        && !daemon_running(&api_client).await
+    {
+        start_daemon(&api_client)
+            .await
+            .map_err(|message| CliError {
+                code: EXIT_UNAVAILABLE,
+                message,
+            })?;
+    }
+    let (action, result) = match subcommand.name.as_str() {
+        "list" => (
+            "List",
+            handle_list_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "activate" => (
+            "Activate",
+            handle_activate_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "deactivate" => (
+            "Deactivate",
+            handle_deactivate_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "path" => (
+            "Path request",
+            handle_path_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "download" => (
+            "Download",
+            handle_download_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "registry" => (
+            "Registry request",
+            handle_registry_subcommand(sub, local_pool(db_path)?, output).await,
+        ),
+        "downloads" => (
+            "Downloads request",
+            handle_downloads_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "status" => (
+            "Status request",
+            handle_status_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "new_cli_user" => (
+            "New user request",
+            handle_new_cli_user_subcommand(
+                sub,
+                local_pool(db_path)?,
+                config_path.as_deref(),
+                output,
+            )
+            .await,
+        ),
+        "revoke_key" => (
+            "Revoke request",
+            handle_revoke_key_subcommand(sub, local_pool(db_path)?, output).await,
+        ),
+        "delete_user" => (
+            "Delete request",
+            handle_delete_user_subcommand(sub, local_pool(db_path)?, output).await,
+        ),
+        "import" => (
+            "Import",
+            handle_import_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "verify" => (
+            "Verify",
+            handle_verify_subcommand(sub, local_pool(db_path)?, output).await,
+        ),
+        "delete_llm" => (
+            "Delete",
+            handle_delete_llm_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "disk" => (
+            "Disk request",
+            handle_disk_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "upgrade" => (
+            "Upgrade",
+            handle_upgrade_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "prompt" => (
+            "Prompt",
+            handle_prompt_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "chat" => (
+            "Chat",
+            handle_chat_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "requests" => (
+            "Requests command",
+            handle_requests_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "users" => (
+            "Users command",
+            handle_users_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "history" => (
+            "History search",
+            handle_history_subcommand_cli(sub, &api_client, output).await,
+        ),
+        "sessions" => (
+            "Sessions command",
+            handle_sessions_subcommand_cli(sub, &api_client, output).await,
+        ),
+        other => {
+            return Err(CliError {
+                code: EXIT_USAGE,
+                message: format!("Unrecognized command {}", other),
+            })
+        }
+    };
+    result.map_err(|e| CliError {
+        code: if api_client.unavailable.load(Ordering::Relaxed) {
+            EXIT_UNAVAILABLE
+        } else {
+            EXIT_FAILED
+        },
+        message: format!("{} failed: {}", action, e),
+    })
 }
 
 // Stub function to handle the 'list' subcommand
 async fn handle_list_subcommand_cli(
     matches: &Matches,
-    _pool: Pool<ConnectionManager<SqliteConnection>>,
-    client: PantryClient,
-) {
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
     if let Some(help_text) = matches.args.get("help") {
         println!("{}", help_text.value.as_str().unwrap_or(""));
     }
-    if let Some(subcommand) = &matches.subcommand {
-        match subcommand.name.as_str() {
-            "running" => match client.get_running_llms().await {
-                Ok(llms) => {
-                    let mut table = Table::new();
-                    table.add_row(row![b->"UUID", b->"ID", b->"Name"]);
-                    for entry in llms.iter() {
-                        table.add_row(row![entry.uuid, entry.id, entry.name]);
-                    }
-                    table.printstd();
-                }
-                Err(e) => {
-                    error!("Failed to get running LLMs: {:?}", e);
-                }
-            },
-            "available" => match client.get_available_llms().await {
-                Ok(llms) => {
-                    let mut table = Table::new();
-                    table.add_row(row![b->"UUID", b->"ID", b->"Name"]);
-                    for entry in llms.iter() {
-                        table.add_row(row![entry.uuid, entry.id, entry.name]);
-                    }
-                    table.printstd();
-                }
-                Err(e) => {
-                    error!("Failed to get running LLMs: {:?}", e);
-                }
-            },
-            "downloadable" => match downloadable_llms_default().await {
-                Ok(_) => {}
-                Err(e) => {
-                    error!("Failed to get downloadable LLMS: {:?}", e);
-                }
-            },
-            _ => {
-                warn!("No subcommand supplied, defaulting to 'running'");
-            }
+    let subcommand = matches
+        .subcommand
+        .as_ref()
+        .ok_or("Expected one of running, available or downloadable.")?;
+    let path = match subcommand.name.as_str() {
+        "running" => "/get_running_llms",
+        "available" => "/get_available_llms",
+        "downloadable" => {
+            return downloadable_llms(
+                matches!(
+                    subcommand.matches.args.get("refresh").map(|arg| &arg.value),
+                    Some(Value::Bool(true))
+                ),
+                client,
+                output,
+            )
+            .await
         }
-    } else {
-        error!("Handling 'list' with default behavior");
+        other => return Err(format!("Unrecognized list command {}", other)),
     };
+    let llms: Vec<Value> = client.post(path, json!({})).await?;
+    if output == OutputFormat::Json {
+        return print_json(&llms);
+    }
+    print_rows(
+        output,
+        &["UUID", "ID", "Name"],
+        llms.iter()
+            .map(|llm| vec![field(llm, "uuid"), field(llm, "id"), field(llm, "name")])
+            .collect(),
+    );
+    Ok(())
 }
 
 // Stub function to handle the 'activate' subcommand
 async fn handle_deactivate_subcommand_cli(
     matches: &Matches,
-    _pool: Pool<ConnectionManager<SqliteConnection>>,
-    client: PantryClient,
-) {
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
     if let Some(help_text) = matches.args.get("help") {
         println!("{}", help_text.value.as_str().unwrap_or(""));
     }
-    if let Some(arg_data) = matches.args.get("llm_id") {
-        if let Value::String(llm_id) = &arg_data.value {
-            info!("Handling 'deactivate' with llm_id: {}", llm_id);
-            match client.unload_llm(llm_id.clone()).await {
-                Ok(_) => {
-                    info!("Initiated LLM deactivate");
-                }
-                Err(e) => {
-                    error!("Failed to initate llm deactivate: {:?}", e);
-                }
-            }
-        }
+    let llm_id = match matches.args.get("llm_id").map(|arg| &arg.value) {
+        Some(Value::String(llm_id)) => llm_id.clone(),
+        _ => return Err("llm_id is mandatory".into()),
+    };
+    info!("Handling 'deactivate' with llm_id: {}", llm_id);
+    let status: Value = client
+        .post("/unload_llm", json!({ "llm_id": llm_id }))
+        .await
+        .map_err(|e| format!("Failed to initate llm deactivate: {}", e))?;
+    if output == OutputFormat::Json {
+        return print_json(&status);
     }
+    println!("Initiated LLM deactivate");
+    Ok(())
 }
 
-async fn downloadable_llms_default() -> Result<(), String> {
-    let response =
-        // TODO: implement the same registry add functionlity of the UI.
-        reqwest::get("https://raw.githubusercontent.com/JuliaMerz/pantry/master/models/index.json")
-            .await
-            .map_err(|e| format!("{:?}", e))?
-            .json::<serde_json::Value>()
-            .await
-            .map_err(|e| format!("{:?}", e))?;
-
-    // Navigate JSON and deserialize to Vec<LLMRegistryEntry>
-    if let Some(models) = response.get("models") {
-        if let serde_json::Value::Object(map) = models {
-            let mut llms: Vec<pantry_rs::interface::LLMRegistryEntry> = Vec::new();
-            for (k, v) in map {
-                match serde_json::from_value::<pantry_rs::interface::LLMRegistryEntry>(v.clone()) {
-                    Ok(entry) => llms.push(entry),
-                    Err(e) => error!("Failed to parse {}: {:?}", k, e),
-                }
-            }
+async fn downloadable_llms(
+    refresh: bool,
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let llms: Vec<registry::DownloadableLLM> = client
+        .post("/list_downloadable_llms", json!({ "refresh": refresh }))
+        .await?;
+    if output == OutputFormat::Json {
+        return print_json(&llms);
+    }
 
-            // Print using a table
-            let mut table = Table::new();
-            table.add_row(row![b->"ID", b->"Name", b->"Website"]);
-            for entry in llms.iter() {
-                table.add_row(row![entry.id, entry.name, entry.homepage]);
+    let mut rows = Vec::new();
+    for llm in llms.iter() {
+        match serde_json::from_value::<pantry_rs::interface::LLMRegistryEntry>(llm.entry.clone()) {
+            Ok(entry) => {
+                rows.push(vec![
+                    entry.id,
+                    entry.name,
+                    llm.registry.clone(),
+                    entry.homepage,
+                ]);
             }
-            table.printstd();
+            Err(e) => error!("Failed to parse {}: {:?}", llm.id, e),
         }
-    };
+    }
+    print_rows(output, &["ID", "Name", "Registry", "Website"], rows);
     Ok(())
 }
 
 // Stub function to handle the 'deactivate' subcommand
 async fn handle_activate_subcommand_cli(
     matches: &Matches,
-    _pool: Pool<ConnectionManager<SqliteConnection>>,
-    client: PantryClient,
-) {
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
     if let Some(help_text) = matches.args.get("help") {
         println!("{}", help_text.value.as_str().unwrap_or(""));
     }
-    if let Some(arg_data) = matches.args.get("llm_id") {
-        if let Value::String(llm_id) = &arg_data.value {
-            match client.load_llm(llm_id.clone()).await {
-                Ok(status) => {
-                    info!("Sent activation command for LLM: {}", status.llm_info.name);
-                }
-                Err(e) => {
-                    error!("Failed to activate, due to error: {:?}", e);
-                }
-            }
-        }
+    let llm_id = match matches.args.get("llm_id").map(|arg| &arg.value) {
+        Some(Value::String(llm_id)) => llm_id.clone(),
+        _ => return Err("llm_id is mandatory".into()),
+    };
+    let wait = matches!(
+        matches.args.get("wait").map(|arg| &arg.value),
+        Some(Value::Bool(true))
+    );
+    // Drawn on stderr, and only when that's a terminal.
+    let spinner = match wait {
+        true => ProgressBar::new_spinner().with_message(format!("Loading {}", llm_id)),
+        false => ProgressBar::hidden(),
+    };
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    // LLMRunningStatus
+    let status: Result<Value, String> = client
+        .post("/load_llm", json!({ "llm_id": llm_id, "wait": wait }))
+        .await;
+    spinner.finish_and_clear();
+    let status = status.map_err(|e| format!("Failed to activate, due to error: {}", e))?;
+    if output == OutputFormat::Json {
+        return print_json(&status);
+    }
+    let name = field(&status["llm_info"], "name");
+    if wait {
+        println!("{} is loaded.", name);
+    } else {
+        println!("Sent activation command for LLM: {}", name);
     }
+    Ok(())
 }
 
 async fn handle_path_subcommand_cli(
     matches: &Matches,
-    _pool: Pool<ConnectionManager<SqliteConnection>>,
-    client: PantryClient,
-) {
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
     if let Some(help_text) = matches.args.get("help") {
         println!("{}", help_text.value.as_str().unwrap_or(""));
     }
-    if let Some(arg_data) = matches.args.get("llm_id") {
-        if let Value::String(llm_id) = &arg_data.value {
-            match client
-                .bare_model_flex(
-                    Some(pantry_rs::LLMFilter {
-                        llm_id: Some(llm_id.to_owned()),
-                        llm_uuid: None,
-                        family_id: None,
-                        local: None,
-                        minimum_capabilities: None,
-                    }),
-                    None,
-                )
-                .await
-            {
-                Ok(status) => {
-                    info!("LLM with bare model! {:?}", status);
-                    let mut table = Table::new();
-                    table.add_row(row![b->"ID", b->"Name", b->"path"]);
-                    table.add_row(row![status.0.id, status.0.name, status.1]);
-                    table.printstd();
-                }
-                Err(e) => {
-                    error!("Failed to get bare model path, due to error: {:?}", e);
-                }
-            }
-        }
+    let llm_id = match matches.args.get("llm_id").map(|arg| &arg.value) {
+        Some(Value::String(llm_id)) => llm_id.clone(),
+        _ => return Err("llm_id is mandatory".into()),
+    };
+    // {model, path}
+    let bare: Value = client
+        .post(
+            "/bare_model_flex",
+            json!({ "filter": { "llm_id": llm_id } }),
+        )
+        .await
+        .map_err(|e| format!("Failed to get bare model path, due to error: {}", e))?;
+    if output == OutputFormat::Json {
+        return print_json(&bare);
     }
+    print_rows(
+        output,
+        &["ID", "Name", "path"],
+        vec![vec![
+            field(&bare["model"], "id"),
+            field(&bare["model"], "name"),
+            field(&bare, "path"),
+        ]],
+    );
+    Ok(())
 }
 
 async fn handle_download_subcommand_cli(
     matches: &Matches,
-    _pool: Pool<ConnectionManager<SqliteConnection>>,
-    client: PantryClient,
+    client: &ApiClient,
+    output: OutputFormat,
 ) -> Result<(), String> {
     if let Some(help_text) = matches.args.get("help") {
         println!("{}", help_text.value.as_str().unwrap_or(""));
     }
-    if let Some(arg_data) = matches.args.get("llm_id") {
-        if let Value::String(llm_id) = &arg_data.value {
-            let response =
-        // TODO: implement the same registry add functionlity of the UI.
-        reqwest::get("https://raw.githubusercontent.com/JuliaMerz/pantry/master/models/index.json")
-            .await
-            .map_err(|e| format!("{:?}", e))?
-            .json::<serde_json::Value>()
-            .await
-            .map_err(|e| format!("{:?}", e))?;
+    let llm_id = match matches.args.get("llm_id").map(|arg| &arg.value) {
+        Some(Value::String(llm_id)) => llm_id.clone(),
+        _ => return Err("llm_id is mandatory".into()),
+    };
+    let registry_name = match matches.args.get("registry").map(|arg| &arg.value) {
+        Some(Value::String(name)) => Some(name.clone()),
+        _ => None,
+    };
 
-            // Navigate JSON and deserialize to Vec<LLMRegistryEntry>
-            let models = response.get("models").ok_or("Failed to get registry.")?;
-            if let serde_json::Value::Object(map) = models {
-                let reg_ent_val = map.get(llm_id).ok_or("Model is not part of the default registry. For non-default registry or manual adds, please use the UI.")?;
+    // Merged listing already prefers the most trusted registry for each id.
+    let llms: Vec<registry::DownloadableLLM> = client
+        .post(
+            "/list_downloadable_llms",
+            json!({ "registry": registry_name }),
+        )
+        .await?;
+    let llm = llms
+        .into_iter()
+        .find(|llm| llm.id == llm_id)
+        .ok_or(format!(
+            "{} is not in any registry. See `pantry list downloadable`.",
+            llm_id
+        ))?;
 
-                let reg_ent =
-                    from_value::<pantry_rs::interface::LLMRegistryEntry>(reg_ent_val.clone())
-                        .map_err(|e| format!("Deserialization failure: {:?}", e))?;
+    if llm.trust != "trusted" {
+        warn!(
+            "{} comes from the untrusted registry {}.",
+            llm.id, llm.registry
+        );
+    }
+    // Sent as is, pantry-rs' entry type would drop fields like huggingface.
+    if let Some(Value::Bool(true)) = matches.args.get("list_quantizations").map(|arg| &arg.value) {
+        let files: Vec<huggingface::HuggingFaceFile> = client
+            .post(
+                "/list_quantizations",
+                json!({ "llm_registry_entry": llm.entry }),
+            )
+            .await?;
+        if output == OutputFormat::Json {
+            return print_json(&files);
+        }
+        print_rows(
+            output,
+            &["Quantization", "File", "Size"],
+            files
+                .iter()
+                .map(|file| {
+                    vec![
+                        file.quantization.clone().unwrap_or_default(),
+                        file.filename.clone(),
+                        file.size.map(|size| size.to_string()).unwrap_or_default(),
+                    ]
+                })
+                .collect(),
+        );
+        return Ok(());
+    }
+    let quantization = match matches.args.get("quantization").map(|arg| &arg.value) {
+        Some(Value::String(quantization)) => Some(quantization.clone()),
+        _ => None,
+    };
 
-                let uuid = client
-                    .download_llm(reg_ent)
-                    .await
-                    .map_err(|e| format!("Error initiating download: {:?}", e))?;
-                info!(
-                    "Download begun. You can check progress with `pantry status {}`",
-                    uuid
-                );
-            };
-        };
-    } else {
-        error!("llm_id is mandatory");
+    let uuid: String = client
+        .post(
+            "/download_llm",
+            json!({ "llm_registry_entry": llm.entry, "quantization": quantization }),
+        )
+        .await
+        .map_err(|e| format!("Error initiating download: {}", e))?;
+    if let Some(Value::Bool(true)) = matches.args.get("wait").map(|arg| &arg.value) {
+        wait_for_download(&uuid, client).await?;
+    }
+    match output {
+        OutputFormat::Json => print_json(&json!({
+            "uuid": uuid,
+            "llm_id": llm.id,
+            "registry": llm.registry,
+        }))?,
+        OutputFormat::Plain => println!("{}", uuid),
+        OutputFormat::Table => println!(
+            "Download begun. You can check progress with `pantry status {}`",
+            uuid
+        ),
     }
     Ok(())
 }
 
-async fn handle_status_subcommand_cli(
+// Follows the download through /list_downloads until it's gone, then makes sure it ended
+// up installed rather than cancelled or rejected.
+async fn wait_for_download(uuid: &str, client: &ApiClient) -> Result<(), String> {
+    let bar = ProgressBar::new_spinner();
+    bar.set_style(
+        ProgressStyle::with_template("{spinner} {bytes} {msg}").map_err(|e| e.to_string())?,
+    );
+    bar.enable_steady_tick(Duration::from_millis(100));
+    let mut sized = false;
+    let mut interval = tokio::time::interval(download_manager::REPORT_INTERVAL);
+    loop {
+        interval.tick().await;
+        let downloads: Vec<DownloadInfo> = client.post("/list_downloads", json!({})).await?;
+        let download = match downloads.into_iter().find(|download| download.id == uuid) {
+            Some(download) => download,
+            None => break,
+        };
+        match download.status.as_str() {
+            "failed" => {
+                bar.abandon_with_message("failed");
+                return Err(format!(
+                    "Download of {} failed, `pantry downloads resume {}` tries again.",
+                    download.llm_id, uuid
+                ));
+            }
+            "paused" => {
+                bar.abandon_with_message("paused");
+                return Err(format!(
+                    "Download of {} was paused, `pantry downloads resume {}` continues it.",
+                    download.llm_id, uuid
+                ));
+            }
+            _ => {}
+        }
+        if let (Some(total), false) = (download.total_bytes, sized) {
+            bar.set_length(total as u64);
+            bar.set_style(
+                ProgressStyle::with_template("[{bar:40}] {bytes}/{total_bytes} {msg}")
+                    .map_err(|e| e.to_string())?
+                    .progress_chars("=> "),
+            );
+            sized = true;
+        }
+        bar.set_position(download.downloaded_bytes as u64);
+        let mut message = format!("{} {}", download.llm_id, download.status);
+        if let Some(speed) = download.bytes_per_second {
+            message.push_str(&format!(" {}/s", format_bytes(speed)));
+        }
+        if let Some(eta) = download.eta_seconds {
+            message.push_str(&format!(" eta {}", format_eta(eta)));
+        }
+        bar.set_message(message);
+    }
+
+    // Finished downloads are saved under the download's uuid.
+    match client
+        .post::<Value>("/get_llm_status", json!({ "llm_id": uuid }))
+        .await
+    {
+        Ok(llm) => {
+            bar.finish_with_message(format!("{} downloaded", field(&llm, "id")));
+            Ok(())
+        }
+        Err(_) => {
+            bar.abandon_with_message("stopped");
+            Err(format!(
+                "Download {} ended without installing the model, it was cancelled or failed verification.",
+                uuid
+            ))
+        }
+    }
+}
+
+async fn handle_registry_subcommand(
     matches: &Matches,
-    _pool: Pool<ConnectionManager<SqliteConnection>>,
-    client: PantryClient,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+    output: OutputFormat,
 ) -> Result<(), String> {
-    if let Some(help_text) = matches.args.get("help") {
-        println!("{}", help_text.value.as_str().unwrap_or(""));
-    }
-    if let Some(arg_data) = matches.args.get("llm_id") {
-        if let Value::String(llm_id) = &arg_data.value {
-            let llm_uuid =
-                Uuid::parse_str(&llm_id).map_err(|e| format!("Argument must be a valid UUID."))?;
-            match client.llm_status(llm_uuid.clone()).await {
-                Ok(status) => {
-                    info!("Obtained status for LLM: {}", status.name);
-                    if (status.running) {
-                        info!("{} is currently running.", status.name);
+    let subcommand = matches
+        .subcommand
+        .as_ref()
+        .ok_or("Expected one of list, add, remove or refresh.")?;
+    let arg = |name: &str| match subcommand.matches.args.get(name).map(|arg| &arg.value) {
+        Some(Value::String(value)) => Some(value.clone()),
+        _ => None,
+    };
+
+    match subcommand.name.as_str() {
+        "list" => {
+            let registries: Vec<registry::RegistryInfo> = database::get_registries(pool)
+                .map_err(|e| format!("Failed to load registries: {:?}", e))?
+                .iter()
+                .map(|reg| reg.into())
+                .collect();
+            if output == OutputFormat::Json {
+                return print_json(&registries);
+            }
+            print_rows(
+                output,
+                &["Name", "Trust", "Models", "Refreshed", "Location"],
+                registries
+                    .into_iter()
+                    .map(|info| {
+                        vec![
+                            info.name,
+                            info.trust,
+                            info.model_count.to_string(),
+                            info.last_refreshed
+                                .map(|time| time.to_rfc3339())
+                                .unwrap_or("never".into()),
+                            info.location,
+                        ]
+                    })
+                    .collect(),
+            );
+        }
+        "add" => {
+            let name = arg("name").ok_or("name is mandatory")?;
+            let location = arg("location").ok_or("location is mandatory")?;
+            let trust = arg("trust").unwrap_or("untrusted".into());
+            let reg = registry::add_registry(name, location, trust, pool).await?;
+            if output == OutputFormat::Json {
+                return print_json(&registry::RegistryInfo::from(&reg));
+            }
+            println!(
+                "Added registry {} with {} models.",
+                reg.name,
+                reg.models().len()
+            );
+        }
+        "remove" => {
+            let name = arg("name").ok_or("name is mandatory")?;
+            match database::delete_registry(name.clone(), pool)
+                .map_err(|e| format!("Failed to remove registry: {:?}", e))?
+            {
+                0 => return Err(format!("No registry named {}", name)),
+                _ if output == OutputFormat::Json => {
+                    return print_json(&json!({ "removed": name }))
+                }
+                _ => println!("Removed registry {}.", name),
+            }
+        }
+        "refresh" => {
+            let registries = match arg("name") {
+                Some(name) => vec![database::get_registry(name.clone(), pool.clone())
+                    .map_err(|_e| format!("No registry named {}", name))?],
+                None => database::get_registries(pool.clone())
+                    .map_err(|e| format!("Failed to load registries: {:?}", e))?,
+            };
+            let mut refreshed = Vec::new();
+            for reg in registries.iter() {
+                match registry::refresh_registry(reg, pool.clone()).await {
+                    Ok(fresh) => {
+                        if output != OutputFormat::Json {
+                            println!("Refreshed {}, {} models.", fresh.name, fresh.models().len());
+                        }
+                        refreshed.push(registry::RegistryInfo::from(&fresh));
                     }
-                    info!("Full status info:");
-                    println!("{:#?}", status)
+                    Err(e) => error!("Failed to refresh {}: {}", reg.name, e),
                 }
-                Err(e) => {
-                    error!("Failed to obtain status, due to error: {:?}", e);
+            }
+            if output == OutputFormat::Json {
+                return print_json(&refreshed);
+            }
+        }
+        "lint" => {
+            let file = arg("file").ok_or("file is mandatory")?;
+            let raw = std::fs::read_to_string(&file)
+                .map_err(|e| format!("Failed to read {}: {:?}", file, e))?;
+            let index: Value =
+                serde_json::from_str(&raw).map_err(|e| format!("{} is not json: {}", file, e))?;
+            let result = registry_index::lint(index);
+            let migrate = matches!(
+                subcommand.matches.args.get("migrate").map(|arg| &arg.value),
+                Some(Value::Bool(true))
+            );
+            if output == OutputFormat::Json {
+                print_json(&json!({
+                    "valid": !result.has_errors(),
+                    "diagnostics": result.diagnostics,
+                    "index": if migrate { result.index.clone() } else { None },
+                }))?;
+            } else {
+                for diag in result.diagnostics.iter() {
+                    eprintln!("{}", diag);
                 }
             }
+            if let (true, Some(index), false) =
+                (migrate, &result.index, output == OutputFormat::Json)
+            {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(index).map_err(|e| e.to_string())?
+                );
+            }
+            if result.has_errors() {
+                return Err(format!(
+                    "{} has errors, only {} entries are usable.",
+                    file,
+                    result.valid_models().len()
+                ));
+            }
+            eprintln!(
+                "{} is a valid version {} index.",
+                file,
+                registry_index::INDEX_VERSION
+            );
+        }
+        other => return Err(format!("Unrecognized registry command {}", other)),
+    }
+    Ok(())
+}
+
+async fn handle_downloads_subcommand_cli(
+    matches: &Matches,
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let subcommand = matches
+        .subcommand
+        .as_ref()
+        .ok_or("Expected one of list, pause, resume or cancel.")?;
+    let path = match subcommand.name.as_str() {
+        "list" => {
+            let downloads: Vec<DownloadInfo> = client.post("/list_downloads", json!({})).await?;
+            if output == OutputFormat::Json {
+                return print_json(&downloads);
+            }
+            let mut rows = Vec::new();
+            for download in downloads.iter() {
+                let progress = match download.progress {
+                    Some(percent) => format!("{:.1}%", percent),
+                    None => format!("{} bytes", download.downloaded_bytes),
+                };
+                let speed = download
+                    .bytes_per_second
+                    .map(|speed| format!("{}/s", format_bytes(speed)))
+                    .unwrap_or_default();
+                let eta = download.eta_seconds.map(format_eta).unwrap_or_default();
+                rows.push(vec![
+                    download.id.clone(),
+                    download.llm_id.clone(),
+                    download.status.clone(),
+                    progress,
+                    speed,
+                    eta,
+                ]);
+            }
+            print_rows(
+                output,
+                &["UUID", "ID", "Status", "Progress", "Speed", "ETA"],
+                rows,
+            );
+            return Ok(());
         }
+        "pause" => "/pause_download",
+        "resume" => "/resume_download",
+        "cancel" => "/cancel_download",
+        other => return Err(format!("Unrecognized downloads command {}", other)),
+    };
+
+    let download_id = match subcommand
+        .matches
+        .args
+        .get("download_id")
+        .map(|arg| &arg.value)
+    {
+        Some(Value::String(download_id)) => download_id.clone(),
+        _ => return Err("download_id is mandatory".into()),
     };
+    let download: DownloadInfo = client
+        .post(path, json!({ "download_id": download_id }))
+        .await?;
+    if output == OutputFormat::Json {
+        return print_json(&download);
+    }
+    println!(
+        "{} {} is now {}.",
+        download.id, download.llm_id, download.status
+    );
+    Ok(())
+}
+
+async fn handle_status_subcommand_cli(
+    matches: &Matches,
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
+    if let Some(help_text) = matches.args.get("help") {
+        println!("{}", help_text.value.as_str().unwrap_or(""));
+    }
+    let llm_id = match matches.args.get("llm_id").map(|arg| &arg.value) {
+        Some(Value::String(llm_id)) => llm_id.clone(),
+        _ => return Err("llm_id is mandatory".into()),
+    };
+    let llm_uuid = Uuid::parse_str(&llm_id).map_err(|_e| "Argument must be a valid UUID.")?;
+    let status: Value = client
+        .post("/get_llm_status", json!({ "llm_id": llm_uuid.to_string() }))
+        .await
+        .map_err(|e| format!("Failed to obtain status, due to error: {}", e))?;
+    if output == OutputFormat::Json {
+        return print_json(&status);
+    }
+    let fields = [
+        "uuid",
+        "id",
+        "name",
+        "running",
+        "download_progress",
+        "local",
+        "connector_type",
+        "family_id",
+        "organization",
+        "license",
+        "homepage",
+    ];
+    print_rows(
+        output,
+        &["Field", "Value"],
+        fields
+            .iter()
+            .map(|name| vec![name.to_string(), field(&status, name)])
+            .collect(),
+    );
     Ok(())
 }
 
 async fn handle_new_cli_user_subcommand(
     matches: &Matches,
     pool: Pool<ConnectionManager<SqliteConnection>>,
+    config_path: Option<&Path>,
+    output: OutputFormat,
 ) -> Result<(), String> {
     let mut u = user::User::new("cli_user".into());
     u.perm_superuser = true;
-    let user_info: user::UserInfo = (&u).into();
+    let (key, api_key) = user::ApiKey::new(u.id.clone(), "default".into(), None);
+    let user_info = user::UserInfo::new(&u, api_key);
     database::save_new_user(u, pool.clone())
         .map_err(|e| format!("Failed to safe user: {:?}", e))?;
+    database::save_new_api_key(key, pool.clone())
+        .map_err(|e| format!("Failed to save key: {:?}", e))?;
+    if let Some(Value::Bool(true)) = matches.args.get("save").map(|arg| &arg.value) {
+        let path = config_path.ok_or("Unable to find the app data directory for cli.json")?;
+        let mut client_config = ClientConfig::load(path)?;
+        client_config.user_id = Some(user_info.id);
+        client_config.api_key = Some(user_info.api_key.clone());
+        client_config.save(path)?;
+        if output == OutputFormat::Json {
+            return print_json(&json!({ "id": user_info.id, "saved": path }));
+        }
+        println!("Saved credentials to {}.", path.display());
+        return Ok(());
+    }
+    if output == OutputFormat::Json {
+        return print_json(&user_info);
+    }
     println!("PANTRY_CLI_USER={}", user_info.id.to_string());
     println!("PANTRY_CLI_KEY={}", user_info.api_key);
     Ok(())
 }
+
+// Like new_cli_user, these run LOCALLY against the database.
+async fn handle_revoke_key_subcommand(
+    matches: &Matches,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let user_id = match matches.args.get("user_id").map(|arg| &arg.value) {
+        Some(Value::String(user_id)) => {
+            Uuid::parse_str(user_id).map_err(|_e| format!("user_id must be a valid UUID."))?
+        }
+        _ => return Err("user_id is mandatory".into()),
+    };
+
+    let revoked = match matches.args.get("key").map(|arg| &arg.value) {
+        Some(Value::String(key_id)) => {
+            let key_uuid =
+                Uuid::parse_str(key_id).map_err(|_e| format!("key_id must be a valid UUID."))?;
+            let key = database::get_api_key(key_uuid, pool.clone())
+                .map_err(|e| format!("Key not found: {:?}", e))?;
+            if key.user_id.0 != user_id {
+                return Err("Key does not belong to that user.".into());
+            }
+            database::revoke_api_key(key_uuid, pool.clone())
+                .map_err(|e| format!("Failed to revoke key: {:?}", e))?;
+            if output != OutputFormat::Json {
+                println!("Revoked key {} ({}).", key_uuid, key.name);
+            }
+            1
+        }
+        _ => {
+            let count = database::revoke_api_keys_for_user(user_id, pool.clone())
+                .map_err(|e| format!("Failed to revoke keys: {:?}", e))?;
+            if output != OutputFormat::Json {
+                println!("Revoked {} keys for {}.", count, user_id);
+            }
+            count
+        }
+    };
+    if output == OutputFormat::Json {
+        return print_json(&json!({ "user_id": user_id, "revoked": revoked }));
+    }
+    Ok(())
+}
+
+async fn handle_delete_user_subcommand(
+    matches: &Matches,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let user_id = match matches.args.get("user_id").map(|arg| &arg.value) {
+        Some(Value::String(user_id)) => {
+            Uuid::parse_str(user_id).map_err(|_e| format!("user_id must be a valid UUID."))?
+        }
+        _ => return Err("user_id is mandatory".into()),
+    };
+    if user_id == user::get_local_user().id.0 {
+        return Err("The local user cannot be deleted.".into());
+    }
+    match database::delete_user(user_id, pool.clone())
+        .map_err(|e| format!("Failed to delete user: {:?}", e))?
+    {
+        0 => Err(format!("No user with id {}", user_id)),
+        _ if output == OutputFormat::Json => print_json(&json!({ "deleted": user_id })),
+        _ => {
+            println!("Deleted user {}.", user_id);
+            Ok(())
+        }
+    }
+}
+
+async fn handle_import_subcommand_cli(
+    matches: &Matches,
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let arg = |name: &str| match matches.args.get(name).map(|arg| &arg.value) {
+        Some(Value::String(value)) => Some(value.clone()),
+        _ => None,
+    };
+    let path = arg("path").ok_or("path is mandatory")?;
+    // Relative paths mean nothing to the server.
+    let path = std::fs::canonicalize(&path).map_err(|e| format!("Can't find {}: {}", path, e))?;
+
+    let llm: Value = client
+        .post(
+            "/import_llm",
+            json!({
+                "path": path,
+                "id": arg("id"),
+                "name": arg("name"),
+                "architecture": arg("architecture"),
+                "mode": arg("mode").unwrap_or("reference".into()),
+            }),
+        )
+        .await?;
+    if output == OutputFormat::Json {
+        return print_json(&llm);
+    }
+    println!(
+        "Imported {} as {}.",
+        llm.get("id").and_then(|v| v.as_str()).unwrap_or(""),
+        llm.get("uuid").and_then(|v| v.as_str()).unwrap_or("")
+    );
+    Ok(())
+}
+
+async fn handle_verify_subcommand(
+    matches: &Matches,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let llm_id = match matches.args.get("llm_id").map(|arg| &arg.value) {
+        Some(Value::String(llm_id)) => llm_id.clone(),
+        _ => return Err("llm_id is mandatory".into()),
+    };
+    let llm = match Uuid::parse_str(&llm_id) {
+        Ok(uuid) => database::get_llm(uuid, pool.clone()),
+        Err(_) => database::get_llm_pub_id(llm_id.clone(), pool.clone()),
+    }
+    .map_err(|e| format!("No installed LLM {}: {:?}", llm_id, e))?;
+
+    let path = llm
+        .model_path
+        .0
+        .ok_or(format!("{} has no model file to verify.", llm.id))?;
+    let digest = hash_file(&path).map_err(|e| format!("Failed to read {:?}: {:?}", path, e))?;
+    let json = output == OutputFormat::Json;
+    if json {
+        print_json(&json!({
+            "id": llm.id,
+            "sha256": digest,
+            "expected": llm.sha256,
+            "ok": llm.sha256.as_ref().map(|expected| expected.eq_ignore_ascii_case(&digest)),
+        }))?;
+    }
+
+    match llm.sha256 {
+        Some(expected) if expected.eq_ignore_ascii_case(&digest) => {
+            if !json {
+                println!("OK {} {}", llm.id, digest);
+            }
+            Ok(())
+        }
+        Some(expected) => Err(format!(
+            "MISMATCH {}: expected {}, got {}",
+            llm.id, expected, digest
+        )),
+        None => {
+            if !json {
+                println!("{} has no recorded sha256, computed {}", llm.id, digest);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, units[unit])
+}
+
+fn format_eta(seconds: u64) -> String {
+    format!("{}m {}s", seconds / 60, seconds % 60)
+}
+
+async fn handle_delete_llm_subcommand_cli(
+    matches: &Matches,
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let llm_id = match matches.args.get("llm_id").map(|arg| &arg.value) {
+        Some(Value::String(llm_id)) => llm_id.clone(),
+        _ => return Err("llm_id is mandatory".into()),
+    };
+    let freed: disk::FreedSpace = client
+        .post("/delete_llm", json!({ "llm_id": llm_id }))
+        .await?;
+    if output == OutputFormat::Json {
+        return print_json(&freed);
+    }
+    println!("Deleted {}, freed {}.", llm_id, format_bytes(freed.bytes));
+    Ok(())
+}
+
+async fn handle_disk_subcommand_cli(
+    matches: &Matches,
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
+    if let Some(Value::Bool(true)) = matches.args.get("gc").map(|arg| &arg.value) {
+        let freed: disk::FreedSpace = client.post("/collect_garbage", json!({})).await?;
+        if output == OutputFormat::Json {
+            return print_json(&freed);
+        }
+        for path in freed.removed.iter() {
+            println!("Removed {}", path.to_string_lossy());
+        }
+        println!("Freed {}.", format_bytes(freed.bytes));
+        return Ok(());
+    }
+
+    let report: disk::DiskReport = client.post("/disk_usage", json!({})).await?;
+    if output == OutputFormat::Json {
+        return print_json(&report);
+    }
+    print_rows(
+        output,
+        &["UUID", "ID", "Size", "Shared", "Path"],
+        report
+            .models
+            .iter()
+            .map(|model| {
+                let path = if model.managed {
+                    model.path.to_string_lossy().into_owned()
+                } else {
+                    format!("{} (not managed)", model.path.to_string_lossy())
+                };
+                vec![
+                    model.uuid.clone(),
+                    model.id.clone(),
+                    format_bytes(model.size),
+                    model.references.to_string(),
+                    path,
+                ]
+            })
+            .collect(),
+    );
+    // Plain output is only the models, the other tables don't share their columns.
+    if output == OutputFormat::Plain {
+        return Ok(());
+    }
+
+    if !report.snapshots.is_empty() {
+        print_rows(
+            output,
+            &["Snapshots of", "Size", "Path"],
+            report
+                .snapshots
+                .iter()
+                .map(|snapshot| {
+                    vec![
+                        snapshot.llm_id.clone().unwrap_or("(deleted)".into()),
+                        format_bytes(snapshot.size),
+                        snapshot.path.to_string_lossy().into_owned(),
+                    ]
+                })
+                .collect(),
+        );
+    }
+
+    if !report.orphans.is_empty() {
+        print_rows(
+            output,
+            &["Orphaned", "Size"],
+            report
+                .orphans
+                .iter()
+                .map(|orphan| {
+                    vec![
+                        orphan.path.to_string_lossy().into_owned(),
+                        format_bytes(orphan.size),
+                    ]
+                })
+                .collect(),
+        );
+    }
+
+    println!(
+        "Total {}, {} orphaned{}.",
+        format_bytes(report.total_bytes),
+        format_bytes(report.orphaned_bytes),
+        if report.orphans.is_empty() {
+            ""
+        } else {
+            ", run `pantry disk --gc` to remove it"
+        }
+    );
+    Ok(())
+}
+
+async fn handle_upgrade_subcommand_cli(
+    matches: &Matches,
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let flag = |name: &str| {
+        matches!(
+            matches.args.get(name).map(|arg| &arg.value),
+            Some(Value::Bool(true))
+        )
+    };
+    let llm_id = match matches.args.get("llm_id").map(|arg| &arg.value) {
+        Some(Value::String(llm_id)) => llm_id.clone(),
+        _ => {
+            let upgrades: Vec<upgrade::UpgradeInfo> = client
+                .post("/list_upgrades", json!({ "refresh": flag("refresh") }))
+                .await?;
+            if output == OutputFormat::Json {
+                return print_json(&upgrades);
+            }
+            if upgrades.is_empty() && output == OutputFormat::Table {
+                println!("Everything is up to date.");
+                return Ok(());
+            }
+            print_rows(
+                output,
+                &["UUID", "ID", "Installed", "Available", "Registry"],
+                upgrades
+                    .iter()
+                    .map(|upgrade| {
+                        vec![
+                            upgrade.llm_uuid.clone(),
+                            upgrade.id.clone(),
+                            upgrade.installed_version.clone().unwrap_or("-".into()),
+                            upgrade.available_version.clone().unwrap_or("-".into()),
+                            upgrade.registry.clone(),
+                        ]
+                    })
+                    .collect(),
+            );
+            return Ok(());
+        }
+    };
+
+    let new_uuid: String = client
+        .post(
+            "/upgrade_llm",
+            json!({ "llm_id": llm_id, "keep_old": flag("keep_old") }),
+        )
+        .await?;
+    if output == OutputFormat::Json {
+        return print_json(&json!({ "uuid": new_uuid }));
+    }
+    println!(
+        "Upgrade started, the new version will be {}. Check progress with `pantry downloads list`.",
+        new_uuid
+    );
+    Ok(())
+}
+
+// Takes the uuid or id of a running LLM, returns its uuid.
+async fn find_running_llm(llm_id: &str, client: &ApiClient) -> Result<String, String> {
+    let llms: Vec<Value> = client.post("/get_running_llms", json!({})).await?;
+    llms.iter()
+        .find(|llm| llm["uuid"] == llm_id || llm["id"] == llm_id)
+        .and_then(|llm| llm["uuid"].as_str())
+        .map(|uuid| uuid.to_string())
+        .ok_or(format!(
+            "{} isn't running, start it with `pantry activate {}`.",
+            llm_id, llm_id
+        ))
+}
+
+async fn create_session(
+    llm_uuid: &str,
+    user_session_parameters: &HashMap<String, Value>,
+    client: &ApiClient,
+) -> Result<String, String> {
+    let response: Value = client
+        .post(
+            "/create_session_id",
+            json!({
+                "llm_id": llm_uuid,
+                "user_session_parameters": user_session_parameters,
+            }),
+        )
+        .await?;
+    response["session_id"]
+        .as_str()
+        .map(|session_id| session_id.to_string())
+        .ok_or("Pantry didn't return a session id.".into())
+}
+
+async fn interrupt_session(
+    llm_uuid: &str,
+    session_id: &str,
+    client: &ApiClient,
+) -> Result<(), String> {
+    let _status: Value = client
+        .post(
+            "/interrupt_session",
+            json!({ "llm_uuid": llm_uuid, "session_id": session_id }),
+        )
+        .await?;
+    Ok(())
+}
+
+// Prints the completion as it streams in, if echo is set, and returns it.
+// Ctrl-C interrupts the LLM rather than exiting.
+async fn stream_prompt(
+    llm_uuid: &str,
+    session_id: &str,
+    prompt: &str,
+    parameters: &HashMap<String, Value>,
+    echo: bool,
+    client: &ApiClient,
+) -> Result<String, String> {
+    let response = client
+        .send(
+            "/prompt_session_stream",
+            json!({
+                "llm_uuid": llm_uuid,
+                "session_id": session_id,
+                "prompt": prompt,
+                "parameters": parameters,
+            }),
+        )
+        .await?;
+    let mut stream = response.bytes_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut stdout = std::io::stdout();
+    let mut text = String::new();
+    loop {
+        let chunk = tokio::select! {
+            chunk = stream.next() => chunk,
+            _ = tokio::signal::ctrl_c() => {
+                if echo {
+                    println!();
+                }
+                return interrupt_session(llm_uuid, session_id, client).await.map(|_| text);
+            }
+        };
+        let chunk = match chunk {
+            Some(chunk) => chunk.map_err(|e| format!("Lost connection to pantry: {:?}", e))?,
+            None => break,
+        };
+        buffer.extend_from_slice(&chunk);
+        // SSE events end with a blank line, a token may be split across chunks.
+        while let Some(end) = buffer.windows(2).position(|window| window == b"\n\n") {
+            let event: Vec<u8> = buffer.drain(..end + 2).collect();
+            for line in String::from_utf8_lossy(&event).lines() {
+                let data = match line.strip_prefix("data:") {
+                    Some(data) => data.trim_start(),
+                    None => continue,
+                };
+                let event: Value = serde_json::from_str(data)
+                    .map_err(|e| format!("Failed to parse event: {:?}", e))?;
+                match event["event"]["type"].as_str() {
+                    Some("PromptProgress") => {
+                        let next = event["event"]["next"].as_str().unwrap_or("");
+                        text.push_str(next);
+                        if echo {
+                            print!("{}", next);
+                            let _ = stdout.flush();
+                        }
+                    }
+                    Some("PromptCompletion") => {
+                        if echo {
+                            println!();
+                        }
+                        return Ok(text);
+                    }
+                    Some("PromptError") => {
+                        if echo {
+                            println!();
+                        }
+                        return Err(format!(
+                            "LLM error: {}",
+                            event["event"]["message"].as_str().unwrap_or("unknown")
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    if echo {
+        println!();
+    }
+    Ok(text)
+}
+
+// --param key=value, values that parse as JSON (numbers, booleans) are sent as such.
+fn parse_params(matches: &Matches) -> Result<HashMap<String, Value>, String> {
+    let values = match matches.args.get("param").map(|arg| &arg.value) {
+        Some(Value::Array(values)) => values.clone(),
+        Some(Value::String(value)) => vec![Value::String(value.clone())],
+        _ => vec![],
+    };
+    let mut params = HashMap::new();
+    for value in values.iter().filter_map(|value| value.as_str()) {
+        let (key, value) = value
+            .split_once('=')
+            .ok_or(format!("Expected key=value, got {}", value))?;
+        let value = serde_json::from_str(value).unwrap_or(Value::String(value.into()));
+        params.insert(key.to_string(), value);
+    }
+    Ok(params)
+}
+
+fn session_params(matches: &Matches) -> HashMap<String, Value> {
+    let mut params = HashMap::new();
+    if let Some(Value::String(system)) = matches.args.get("system").map(|arg| &arg.value) {
+        params.insert("system_prompt".to_string(), Value::String(system.clone()));
+    }
+    params
+}
+
+async fn handle_prompt_subcommand_cli(
+    matches: &Matches,
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let llm_id = match matches.args.get("llm_id").map(|arg| &arg.value) {
+        Some(Value::String(llm_id)) => llm_id.clone(),
+        _ => return Err("llm_id is mandatory".into()),
+    };
+    let text = match matches.args.get("prompt").map(|arg| &arg.value) {
+        Some(Value::String(text)) => Some(text.clone()),
+        _ => None,
+    };
+    // Piped input goes after the prompt, so `cat notes.txt | pantry prompt llm "Summarize:"` works.
+    let piped = if std::io::stdin().is_terminal() {
+        None
+    } else {
+        let mut input = String::new();
+        std::io::stdin()
+            .read_to_string(&mut input)
+            .map_err(|e| format!("Failed to read stdin: {:?}", e))?;
+        Some(input)
+    };
+    let prompt = match (text, piped) {
+        (Some(text), Some(input)) => format!("{}\n\n{}", text, input),
+        (Some(text), None) => text,
+        (None, Some(input)) => input,
+        (None, None) => return Err("Give a prompt, or pipe one in.".into()),
+    };
+
+    let llm_uuid = find_running_llm(&llm_id, client).await?;
+    let parameters = parse_params(matches)?;
+    let session_id = match matches.args.get("session").map(|arg| &arg.value) {
+        Some(Value::String(session_id)) => session_id.clone(),
+        _ => create_session(&llm_uuid, &session_params(matches), client).await?,
+    };
+    let json = output == OutputFormat::Json;
+    let response =
+        stream_prompt(&llm_uuid, &session_id, &prompt, &parameters, !json, client).await?;
+    if json {
+        return print_json(&json!({
+            "llm_uuid": llm_uuid,
+            "session_id": session_id,
+            "response": response,
+        }));
+    }
+    Ok(())
+}
+
+const CHAT_HELP: &str = "Commands:
+  /reset           start a new session, keeping the system prompt
+  /system [text]   start a new session with this system prompt, or none
+  /interrupt       stop the LLM if it's still generating
+  /exit            leave the chat (or Ctrl-D)
+Ctrl-C stops the current answer.";
+
+// With --output json every answer is printed as one line of json, everything else goes to stderr.
+async fn handle_chat_subcommand_cli(
+    matches: &Matches,
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let json = output == OutputFormat::Json;
+    let say = |text: String| match json {
+        true => eprintln!("{}", text),
+        false => println!("{}", text),
+    };
+    let llm_id = match matches.args.get("llm_id").map(|arg| &arg.value) {
+        Some(Value::String(llm_id)) => llm_id.clone(),
+        _ => return Err("llm_id is mandatory".into()),
+    };
+    let llm_uuid = find_running_llm(&llm_id, client).await?;
+    let parameters = parse_params(matches)?;
+    let mut user_session_parameters = session_params(matches);
+    let mut session_id = match matches.args.get("session").map(|arg| &arg.value) {
+        Some(Value::String(session_id)) => session_id.clone(),
+        _ => create_session(&llm_uuid, &user_session_parameters, client).await?,
+    };
+    say(format!(
+        "Chatting with {} in session {}, /help lists commands.",
+        llm_id, session_id
+    ));
+
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        if !json {
+            print!("> ");
+            let _ = std::io::stdout().flush();
+        }
+        let line = tokio::select! {
+            line = lines.next_line() => line.map_err(|e| format!("Failed to read input: {:?}", e))?,
+            _ = tokio::signal::ctrl_c() => None,
+        };
+        let line = match line {
+            Some(line) => line,
+            None => {
+                if !json {
+                    println!();
+                }
+                break;
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let result = match command {
+            "/exit" | "/quit" => break,
+            "/help" => {
+                say(CHAT_HELP.into());
+                Ok(())
+            }
+            "/reset" | "/system" => {
+                if command == "/system" {
+                    match rest.trim() {
+                        "" => user_session_parameters.remove("system_prompt"),
+                        system => user_session_parameters
+                            .insert("system_prompt".into(), Value::String(system.into())),
+                    };
+                }
+                // The system prompt is fed when a session starts, so either way it's a new one.
+                create_session(&llm_uuid, &user_session_parameters, client)
+                    .await
+                    .map(|new_session| {
+                        session_id = new_session;
+                        say(format!("Started session {}.", session_id));
+                    })
+            }
+            "/interrupt" => interrupt_session(&llm_uuid, &session_id, client).await,
+            _ if command.starts_with('/') => {
+                say(format!("Unknown command {}, /help lists commands.", command));
+                Ok(())
+            }
+            _ => stream_prompt(&llm_uuid, &session_id, line, &parameters, !json, client)
+                .await
+                .map(|response| {
+                    if json {
+                        println!(
+                            "{}",
+                            json!({ "session_id": session_id, "prompt": line, "response": response })
+                        );
+                    }
+                }),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+        }
+    }
+    Ok(())
+}
+
+// The permissions that are set, without the perm_ prefix.
+fn permission_names(permissions: &user::Permissions) -> String {
+    match serde_json::to_value(permissions) {
+        Ok(Value::Object(perms)) => perms
+            .iter()
+            .filter(|(_, value)| **value == Value::Bool(true))
+            .map(|(name, _)| name.trim_start_matches("perm_").to_string())
+            .collect::<Vec<String>>()
+            .join(", "),
+        _ => String::new(),
+    }
+}
+
+fn describe_request(request: &UserRequestType) -> String {
+    match request {
+        UserRequestType::DownloadRequest(dr) => {
+            format!("download {}", dr.llm_registry_entry.id)
+        }
+        UserRequestType::PermissionRequest(pr) => {
+            format!(
+                "permissions: {}",
+                permission_names(&pr.requested_permissions)
+            )
+        }
+        UserRequestType::LoadRequest(lr) => format!("load {}", lr.llm_id),
+        UserRequestType::UnloadRequest(ur) => format!("unload {}", ur.llm_id),
+    }
+}
+
+fn request_state(request: &RequestAdminStatus) -> &'static str {
+    match (request.complete, request.accepted) {
+        (false, _) => "pending",
+        (true, true) => "accepted",
+        (true, false) => "rejected",
+    }
+}
+
+fn print_requests(output: OutputFormat, requests: &[RequestAdminStatus]) {
+    print_rows(
+        output,
+        &[
+            "ID",
+            "User",
+            "Originator",
+            "Request",
+            "Reason",
+            "Time",
+            "State",
+            "Note",
+        ],
+        requests
+            .iter()
+            .map(|request| {
+                vec![
+                    request.id.to_string(),
+                    request.user_id.to_string(),
+                    request.originator.clone(),
+                    describe_request(&request.request),
+                    request.reason.clone(),
+                    request.timestamp.format("%b %e %T %Y").to_string(),
+                    request_state(request).to_string(),
+                    request.decision_note.clone().unwrap_or_default(),
+                ]
+            })
+            .collect(),
+    );
+}
+
+async fn handle_requests_subcommand_cli(
+    matches: &Matches,
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let subcommand = matches
+        .subcommand
+        .as_ref()
+        .ok_or("Expected one of list, accept or reject.")?;
+    let path = match subcommand.name.as_str() {
+        "list" => {
+            let all = matches!(
+                subcommand.matches.args.get("all").map(|arg| &arg.value),
+                Some(Value::Bool(true))
+            );
+            let requests: Vec<RequestAdminStatus> =
+                client.post("/list_requests", json!({ "all": all })).await?;
+            if output == OutputFormat::Json {
+                return print_json(&requests);
+            }
+            if requests.is_empty() && output == OutputFormat::Table {
+                println!("No pending requests.");
+            } else {
+                print_requests(output, &requests);
+            }
+            return Ok(());
+        }
+        "accept" => "/accept_request",
+        "reject" => "/reject_request",
+        other => return Err(format!("Unrecognized requests command {}", other)),
+    };
+
+    let request_id = match subcommand
+        .matches
+        .args
+        .get("request_id")
+        .map(|arg| &arg.value)
+    {
+        Some(Value::String(request_id)) => request_id.clone(),
+        _ => return Err("request_id is mandatory".into()),
+    };
+    let args = &subcommand.matches.args;
+    let note = match args.get("note").map(|arg| &arg.value) {
+        Some(Value::String(note)) => Some(note.clone()),
+        _ => None,
+    };
+    // Everything not listed is left off.
+    let granted_permissions = match args.get("grant").map(|arg| &arg.value) {
+        Some(Value::Array(names)) => {
+            let mut granted = user::Permissions::default();
+            for name in names.iter().filter_map(|name| name.as_str()) {
+                granted.set(name, true)?;
+            }
+            Some(granted)
+        }
+        _ => None,
+    };
+    let request: RequestAdminStatus = client
+        .post(
+            path,
+            json!({
+                "request_id": request_id,
+                "note": note,
+                "granted_permissions": granted_permissions,
+            }),
+        )
+        .await?;
+    if output == OutputFormat::Json {
+        return print_json(&request);
+    }
+    println!(
+        "Request {} ({}) is now {}.",
+        request.id,
+        describe_request(&request.request),
+        request_state(&request)
+    );
+    if let Some(granted) = request.granted_permissions.as_ref() {
+        println!("Granted: {}", permission_names(granted));
+    }
+    Ok(())
+}
+
+// Plain output is just the keys.
+fn print_user(output: OutputFormat, user: &UserAdminStatus) {
+    if output == OutputFormat::Table {
+        println!("User {} ({})", user.id, user.name);
+        println!("Permissions: {}", permission_names(&user.permissions));
+    }
+    print_rows(
+        output,
+        &["Key", "Name", "Created", "Expires", "Revoked"],
+        user.keys
+            .iter()
+            .map(|key| {
+                vec![
+                    key.id.to_string(),
+                    key.name.clone(),
+                    key.created.format("%b %e %T %Y").to_string(),
+                    key.expires
+                        .map(|expires| expires.format("%b %e %T %Y").to_string())
+                        .unwrap_or("never".into()),
+                    key.revoked.to_string(),
+                ]
+            })
+            .collect(),
+    );
+}
+
+async fn handle_users_subcommand_cli(
+    matches: &Matches,
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let subcommand = matches
+        .subcommand
+        .as_ref()
+        .ok_or("Expected one of list, show, revoke or set_perms.")?;
+    let args = &subcommand.matches.args;
+    if subcommand.name == "list" {
+        let users: Vec<UserAdminStatus> = client.post("/list_users", json!({})).await?;
+        if output == OutputFormat::Json {
+            return print_json(&users);
+        }
+        print_rows(
+            output,
+            &["ID", "Name", "Permissions", "Active Keys"],
+            users
+                .iter()
+                .map(|user| {
+                    vec![
+                        user.id.to_string(),
+                        user.name.clone(),
+                        permission_names(&user.permissions),
+                        user.keys
+                            .iter()
+                            .filter(|key| !key.revoked)
+                            .count()
+                            .to_string(),
+                    ]
+                })
+                .collect(),
+        );
+        return Ok(());
+    }
+
+    let user_id = match args.get("user_id").map(|arg| &arg.value) {
+        Some(Value::String(user_id)) => user_id.clone(),
+        _ => return Err("user_id is mandatory".into()),
+    };
+    match subcommand.name.as_str() {
+        "show" => {
+            let details: UserDetailsStatus = client
+                .post("/show_user", json!({ "user_id": user_id }))
+                .await?;
+            if output == OutputFormat::Json {
+                return print_json(&details);
+            }
+            print_user(output, &details.user);
+            if !details.requests.is_empty() && output == OutputFormat::Table {
+                println!("Requests:");
+                print_requests(output, &details.requests);
+            }
+        }
+        "revoke" => {
+            let key_id = match args.get("key").map(|arg| &arg.value) {
+                Some(Value::String(key_id)) => Some(key_id.clone()),
+                _ => None,
+            };
+            let count: usize = client
+                .post(
+                    "/revoke_user_keys",
+                    json!({ "user_id": user_id, "key_id": key_id }),
+                )
+                .await?;
+            if output == OutputFormat::Json {
+                return print_json(&json!({ "user_id": user_id, "revoked": count }));
+            }
+            println!("Revoked {} keys for {}.", count, user_id);
+        }
+        "set_perms" => {
+            let values = match args.get("permissions").map(|arg| &arg.value) {
+                Some(Value::Array(values)) => values.clone(),
+                Some(Value::String(value)) => vec![Value::String(value.clone())],
+                _ => return Err("Give permissions as name=true or name=false.".into()),
+            };
+            let mut permissions = HashMap::new();
+            for value in values.iter().filter_map(|value| value.as_str()) {
+                let (name, value) = match value.split_once('=') {
+                    Some((name, "true")) => (name, true),
+                    Some((name, "false")) => (name, false),
+                    _ => return Err(format!("Expected name=true or name=false, got {}", value)),
+                };
+                permissions.insert(name.to_string(), value);
+            }
+            let user: UserAdminStatus = client
+                .post(
+                    "/set_user_permissions",
+                    json!({ "user_id": user_id, "permissions": permissions }),
+                )
+                .await?;
+            if output == OutputFormat::Json {
+                return print_json(&user);
+            }
+            print_user(output, &user);
+        }
+        other => return Err(format!("Unrecognized users command {}", other)),
+    }
+    Ok(())
+}
+
+// RFC 3339 times, or dates which mean midnight UTC.
+fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()))
+        .map_err(|_| {
+            format!(
+                "Expected a date like 2023-09-01 or an RFC 3339 time, got {}",
+                value
+            )
+        })
+}
+
+async fn handle_history_subcommand_cli(
+    matches: &Matches,
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let subcommand = matches.subcommand.as_ref().ok_or("Expected search.")?;
+    if subcommand.name != "search" {
+        return Err(format!("Unrecognized history command {}", subcommand.name));
+    }
+    let args = &subcommand.matches.args;
+    let arg = |name: &str| match args.get(name).map(|arg| &arg.value) {
+        Some(Value::String(value)) => Some(value.clone()),
+        _ => None,
+    };
+    let query = arg("query").ok_or("query is mandatory")?;
+    let limit = match arg("limit") {
+        Some(limit) => Some(
+            limit
+                .parse::<i64>()
+                .map_err(|_| format!("limit should be a number, got {}", limit))?,
+        ),
+        None => None,
+    };
+    let body = json!({
+        "query": query,
+        "user_id": arg("user"),
+        "llm": arg("llm"),
+        "session_id": arg("session"),
+        "since": arg("since").map(|since| parse_time(&since)).transpose()?,
+        "until": arg("until").map(|until| parse_time(&until)).transpose()?,
+        "limit": limit,
+    });
+    let hits: Vec<HistorySearchHit> = client.post("/search_history", body).await?;
+    if output == OutputFormat::Json {
+        return print_json(&hits);
+    }
+    if hits.is_empty() && output == OutputFormat::Table {
+        println!("Nothing found.");
+        return Ok(());
+    }
+    let rows = hits
+        .iter()
+        .map(|hit| {
+            vec![
+                hit.call_timestamp.format("%b %e %T %Y").to_string(),
+                hit.llm_id.clone(),
+                hit.llm_session_id.0.to_string(),
+                hit.id.0.to_string(),
+                hit.snippet.replace('\n', " "),
+            ]
+        })
+        .collect();
+    print_rows(output, &["Time", "LLM", "Session", "Item", "Match"], rows);
+    Ok(())
+}
+
+async fn handle_sessions_subcommand_cli(
+    matches: &Matches,
+    client: &ApiClient,
+    output: OutputFormat,
+) -> Result<(), String> {
+    let subcommand = matches
+        .subcommand
+        .as_ref()
+        .ok_or("Expected one of export or import.")?;
+    let args = &subcommand.matches.args;
+    let arg = |name: &str| match args.get(name).map(|arg| &arg.value) {
+        Some(Value::String(value)) => Some(value.clone()),
+        _ => None,
+    };
+    match subcommand.name.as_str() {
+        "export" => {
+            let session_ids = match args.get("session").map(|arg| &arg.value) {
+                Some(Value::Array(values)) => values.clone(),
+                Some(Value::String(value)) => vec![Value::String(value.clone())],
+                _ => vec![],
+            };
+            let include_snapshots = matches!(
+                args.get("snapshots").map(|arg| &arg.value),
+                Some(Value::Bool(true))
+            );
+            let exports: Vec<SessionExport> = client
+                .post(
+                    "/export_sessions",
+                    json!({
+                        "session_ids": session_ids,
+                        "llm": arg("llm"),
+                        "user_id": arg("user"),
+                        "include_snapshots": include_snapshots,
+                    }),
+                )
+                .await?;
+            let mut lines = String::new();
+            for export in exports.iter() {
+                let line = serde_json::to_string(export)
+                    .map_err(|e| format!("Failed to serialize session: {:?}", e))?;
+                lines.push_str(&line);
+                lines.push('\n');
+            }
+            match arg("file") {
+                Some(file) => std::fs::write(&file, lines)
+                    .map_err(|e| format!("Failed to write {}: {:?}", file, e))?,
+                None => print!("{}", lines),
+            }
+            // stdout may be the export itself.
+            eprintln!("Exported {} sessions.", exports.len());
+        }
+        "import" => {
+            let file = arg("file").ok_or("file is mandatory")?;
+            // Streamed as is, pantry reads it a session at a time.
+            let reader: Box<dyn tokio::io::AsyncRead + Send + Unpin> = if file == "-" {
+                Box::new(tokio::io::stdin())
+            } else {
+                Box::new(
+                    tokio::fs::File::open(&file)
+                        .await
+                        .map_err(|e| format!("Failed to read {}: {:?}", file, e))?,
+                )
+            };
+            let path = match arg("user") {
+                Some(user_id) => format!("/import_sessions?user_id={}", user_id),
+                None => "/import_sessions".to_string(),
+            };
+            let summary: ImportSummary = client
+                .upload(
+                    &path,
+                    reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(reader)),
+                )
+                .await?
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse response: {:?}", e))?;
+            if output == OutputFormat::Json {
+                return print_json(&summary);
+            }
+            if !summary.skipped.is_empty() {
+                let rows = summary
+                    .skipped
+                    .iter()
+                    .map(|(session_id, reason)| vec![session_id.to_string(), reason.clone()])
+                    .collect();
+                print_rows(output, &["Skipped", "Reason"], rows);
+            }
+            if output == OutputFormat::Table {
+                println!("Imported {} sessions.", summary.imported.len());
+            }
+        }
+        other => return Err(format!("Unrecognized sessions command {}", other)),
+    }
+    Ok(())
+}
@@ -47,6 +47,9 @@ pub fn factory_llms() -> Vec<llm::LLM> {
             session_parameters: DbHashMap(HashMap::from([])),
             user_session_parameters: DbVec(vec![]),
             model_path: DbOptionPathbuf(None),
+            sha256: None,
+            version: None,
+            registry: None,
         },
         llm::LLM {
             id: "openai_gpt4".to_string(),
@@ -81,6 +84,9 @@ pub fn factory_llms() -> Vec<llm::LLM> {
             parameters: DbHashMap(HashMap::from([])),
             user_parameters: DbVec(vec![]),
             model_path: DbOptionPathbuf(None),
+            sha256: None,
+            version: None,
+            registry: None,
         },
     ]
 }
@@ -8,7 +8,6 @@ use diesel::r2d2::{ConnectionManager, Pool};
 use log::{debug};
 use serde_json::Value;
 use std::{collections::HashMap, path::PathBuf};
-use tauri::AppHandle;
 use tiny_tokio_actor::*;
 use uuid::Uuid;
 
@@ -50,7 +49,7 @@ pub struct CreateLLMActorMessage {
     pub model_path: Option<PathBuf>,
     pub user_settings: state::UserSettings,
     pub pool: Pool<ConnectionManager<SqliteConnection>>,
-    pub app: AppHandle,
+    pub emitter: emitter::SharedEmitter,
 }
 // id, connector type, config[]
 
@@ -78,7 +77,7 @@ impl Handler<SysEvent, CreateLLMActorMessage> for LLMManagerActor {
             msg.user_settings.clone(),
             msg.pool.clone(),
             emitter::NotificationEmitter {
-                app: msg.app.clone(),
+                emitter: msg.emitter.clone(),
             },
         );
         let llm_act = LLMActor {
@@ -1,12 +1,13 @@
 use crate::database_types::*;
-use crate::llm::{LLMHistoryItem, LLMSession, LLM};
-use crate::registry::LLMRegistryEntry;
+use crate::llm::{HistoryFilter, HistorySearchHit, LLMHistoryItem, LLMSession, LLM};
+use crate::registry::{Download, LLMRegistryEntry, Registry};
 use crate::request::UserRequest;
 use crate::schema;
 use crate::user;
-use crate::user::User;
-use chrono::Utc;
+use crate::user::{ApiKey, User};
+use chrono::{DateTime, Utc};
 use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 
 use diesel::prelude::*;
 
@@ -14,8 +15,6 @@ use diesel::r2d2::{ConnectionManager, Pool};
 
 use log::debug;
 
-use sha2::{Digest, Sha256};
-
 use uuid::Uuid;
 // ON db migration generation:
 // %s/Timestamp/TimestamptzSqlite/g
@@ -179,6 +178,18 @@ pub fn get_requests(
     requests.select(UserRequest::as_select()).load(conn)
 }
 
+pub fn get_requests_for_user(
+    user_id_val: Uuid,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Vec<UserRequest>, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::requests::dsl::*;
+    requests
+        .filter(user_id.eq(DbUuid(user_id_val)))
+        .select(UserRequest::as_select())
+        .load(conn)
+}
+
 pub fn get_request(
     request_id: Uuid,
     pool: Pool<ConnectionManager<SqliteConnection>>,
@@ -202,6 +213,17 @@ pub fn get_user(
         .first(conn)
 }
 
+pub fn get_llm_sessions(
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Vec<LLMSession>, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::llm_session::dsl::*;
+    llm_session
+        .order(started.asc())
+        .select(LLMSession::as_select())
+        .load(conn)
+}
+
 pub fn get_sessions_for_llm(
     llm_id: Uuid,
     pool: Pool<ConnectionManager<SqliteConnection>>,
@@ -226,6 +248,60 @@ pub fn get_history_for_session(
         .load(conn)
 }
 
+// Best matches first. query uses FTS5 syntax: words, "phrases", AND/OR/NOT and prefix*.
+// Only finished prompts are indexed, one still generating won't turn up.
+pub fn search_history(
+    query: &str,
+    filter: &HistoryFilter,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Vec<HistorySearchHit>, diesel::result::Error> {
+    use diesel::sql_types::{BigInt, Text, TimestamptzSqlite};
+    let conn = &mut pool.get().unwrap();
+    let mut search = diesel::sql_query(
+        "SELECT h.id, h.llm_session_id, s.llm_uuid, l.id AS llm_id, s.user_id, \
+         h.call_timestamp, h.complete, h.input, h.output, \
+         snippet(llm_history_fts, -1, '[', ']', '...', 16) AS snippet \
+         FROM llm_history_fts \
+         JOIN llm_history h ON h.rowid = llm_history_fts.rowid \
+         JOIN llm_session s ON s.id = h.llm_session_id \
+         JOIN llm l ON l.uuid = s.llm_uuid \
+         WHERE llm_history_fts MATCH ?",
+    )
+    .into_boxed::<diesel::sqlite::Sqlite>()
+    .bind::<Text, _>(query.to_string());
+    if let Some(user_id) = filter.user_id {
+        search = search
+            .sql(" AND s.user_id = ?")
+            .bind::<Text, _>(DbUuid(user_id));
+    }
+    if let Some(llm) = filter.llm.as_ref() {
+        search = search
+            .sql(" AND (l.uuid = ? OR l.id = ?)")
+            .bind::<Text, _>(llm.clone())
+            .bind::<Text, _>(llm.clone());
+    }
+    if let Some(session_id) = filter.session_id {
+        search = search
+            .sql(" AND h.llm_session_id = ?")
+            .bind::<Text, _>(DbUuid(session_id));
+    }
+    if let Some(since) = filter.since {
+        search = search
+            .sql(" AND h.call_timestamp >= ?")
+            .bind::<TimestamptzSqlite, _>(since);
+    }
+    if let Some(until) = filter.until {
+        search = search
+            .sql(" AND h.call_timestamp < ?")
+            .bind::<TimestamptzSqlite, _>(until);
+    }
+    let limit = filter.limit.unwrap_or(50).clamp(1, 500);
+    search
+        .sql(" ORDER BY rank LIMIT ?")
+        .bind::<BigInt, _>(limit)
+        .load(conn)
+}
+
 // We should just do this when we update the session.
 // pub fn update_llm_last_called(
 //     llm: LLM,
@@ -313,6 +389,26 @@ pub fn save_new_llm_history(
     // Remember to write a smoooooooth update statement to update session last called
 }
 
+// A session and all of its history, or nothing if any of it fails.
+pub fn save_imported_session(
+    new_llm_session: LLMSession,
+    history: Vec<LLMHistoryItem>,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<(), diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    conn.transaction(|conn| {
+        diesel::insert_into(schema::llm_session::table)
+            .values(&new_llm_session)
+            .execute(conn)?;
+        for item in history.iter() {
+            diesel::insert_into(schema::llm_history::table)
+                .values(item)
+                .execute(conn)?;
+        }
+        Ok(())
+    })
+}
+
 pub fn save_new_request(
     new_request: UserRequest,
     pool: Pool<ConnectionManager<SqliteConnection>>,
@@ -331,17 +427,135 @@ pub fn save_new_user(
     pool: Pool<ConnectionManager<SqliteConnection>>,
 ) -> Result<User, diesel::result::Error> {
     let conn = &mut pool.get().unwrap();
-    let mut db_user = new_user.clone();
     use schema::user::dsl::*;
-    let mut hasher = Sha256::new();
-    hasher.update(new_user.api_key);
-    db_user.api_key = format!("{:X}", hasher.finalize());
-
     let user_id = new_user.id.0.clone();
-    diesel::insert_into(user).values(&db_user).execute(conn)?;
+    diesel::insert_into(user).values(&new_user).execute(conn)?;
     get_user(user_id, pool)
 }
 
+pub fn get_users(
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Vec<User>, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::user::dsl::*;
+    user.select(User::as_select()).load(conn)
+}
+
+// Sessions, history, requests and keys all cascade.
+pub fn delete_user(
+    user_id: Uuid,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<usize, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::user::dsl::*;
+    diesel::delete(user)
+        .filter(id.eq(DbUuid(user_id)))
+        .execute(conn)
+}
+
+pub fn get_api_key(
+    key_id: Uuid,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<ApiKey, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::api_key::dsl::*;
+    api_key
+        .filter(id.eq(DbUuid(key_id)))
+        .select(ApiKey::as_select())
+        .first(conn)
+}
+
+pub fn save_new_api_key(
+    new_key: ApiKey,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<ApiKey, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::api_key::dsl::*;
+    let key_id = new_key.id.0.clone();
+    diesel::insert_into(api_key)
+        .values(&new_key)
+        .execute(conn)?;
+    get_api_key(key_id, pool)
+}
+
+pub fn get_api_keys_for_user(
+    user_id_val: Uuid,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Vec<ApiKey>, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::api_key::dsl::*;
+    api_key
+        .filter(user_id.eq(DbUuid(user_id_val)))
+        .order(created.asc())
+        .select(ApiKey::as_select())
+        .load(conn)
+}
+
+// Keys that can still authenticate: not revoked, not expired.
+pub fn get_valid_api_keys(
+    user_id_val: Uuid,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Vec<ApiKey>, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::api_key::dsl::*;
+    api_key
+        .filter(user_id.eq(DbUuid(user_id_val)))
+        .filter(revoked.eq(false))
+        .filter(expires.is_null().or(expires.gt(Utc::now())))
+        .select(ApiKey::as_select())
+        .load(conn)
+}
+
+pub fn revoke_api_key(
+    key_id: Uuid,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<usize, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::api_key::dsl::*;
+    diesel::update(api_key)
+        .filter(id.eq(DbUuid(key_id)))
+        .set(revoked.eq(true))
+        .execute(conn)
+}
+
+pub fn revoke_api_keys_for_user(
+    user_id_val: Uuid,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<usize, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::api_key::dsl::*;
+    diesel::update(api_key)
+        .filter(user_id.eq(DbUuid(user_id_val)))
+        .set(revoked.eq(true))
+        .execute(conn)
+}
+
+pub fn set_api_key_hash(
+    key_id: Uuid,
+    new_hash: String,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<usize, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::api_key::dsl::*;
+    diesel::update(api_key)
+        .filter(id.eq(DbUuid(key_id)))
+        .set(key_hash.eq(new_hash))
+        .execute(conn)
+}
+
+pub fn set_api_key_expiry(
+    key_id: Uuid,
+    new_expiry: Option<DateTime<Utc>>,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<usize, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::api_key::dsl::*;
+    diesel::update(api_key)
+        .filter(id.eq(DbUuid(key_id)))
+        .set(expires.eq(new_expiry))
+        .execute(conn)
+}
+
 pub fn get_llm_sessions_user(
     user: User,
     llm_id: DbUuid,
@@ -369,6 +583,25 @@ pub fn get_llm_sessions_user(
         .collect::<Vec<(LLMSession, Vec<LLMHistoryItem>)>>())
 }
 
+// The connector and parameter settings, everything else comes from the registry entry.
+pub fn update_llm_settings(
+    updated: &LLM,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<usize, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::llm::dsl::*;
+    diesel::update(llm)
+        .filter(uuid.eq(updated.uuid.clone()))
+        .set((
+            config.eq(updated.config.clone()),
+            parameters.eq(updated.parameters.clone()),
+            user_parameters.eq(updated.user_parameters.clone()),
+            session_parameters.eq(updated.session_parameters.clone()),
+            user_session_parameters.eq(updated.user_session_parameters.clone()),
+        ))
+        .execute(conn)
+}
+
 pub fn delete_llm(
     llm_id: Uuid,
     pool: Pool<ConnectionManager<SqliteConnection>>,
@@ -380,6 +613,18 @@ pub fn delete_llm(
         .execute(conn)
 }
 
+// Several LLMs share a file when downloads are deduplicated.
+pub fn get_llms_by_model_path(
+    path: PathBuf,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Vec<LLM>, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::llm::dsl::*;
+    llm.filter(model_path.eq(path.to_string_lossy().to_string()))
+        .select(LLM::as_select())
+        .load(conn)
+}
+
 pub fn get_llm_by_url(
     reg_url: String,
     pool: Pool<ConnectionManager<SqliteConnection>>,
@@ -391,16 +636,199 @@ pub fn get_llm_by_url(
         .first(conn)
 }
 
+pub fn save_new_download(
+    new_download: Download,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Download, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::download::dsl::*;
+    let download_id = new_download.id.0.clone();
+    diesel::insert_into(download)
+        .values(&new_download)
+        .execute(conn)?;
+    get_download(download_id, pool)
+}
+
+pub fn get_download(
+    download_id: Uuid,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Download, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::download::dsl::*;
+    download
+        .filter(id.eq(DbUuid(download_id)))
+        .select(Download::as_select())
+        .first(conn)
+}
+
+pub fn get_downloads(
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Vec<Download>, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::download::dsl::*;
+    download
+        .order(started.asc())
+        .select(Download::as_select())
+        .load(conn)
+}
+
+pub fn update_download_progress(
+    download_id: Uuid,
+    downloaded: i64,
+    total: Option<i64>,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<usize, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::download::dsl::*;
+    diesel::update(download)
+        .filter(id.eq(DbUuid(download_id)))
+        .set((
+            downloaded_bytes.eq(downloaded),
+            total_bytes.eq(total),
+            updated.eq(Utc::now()),
+        ))
+        .execute(conn)
+}
+
+pub fn set_download_status(
+    download_id: Uuid,
+    new_status: &str,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<usize, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::download::dsl::*;
+    diesel::update(download)
+        .filter(id.eq(DbUuid(download_id)))
+        .set((status.eq(new_status), updated.eq(Utc::now())))
+        .execute(conn)
+}
+
+pub fn delete_download(
+    download_id: Uuid,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<usize, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::download::dsl::*;
+    diesel::delete(download.filter(id.eq(DbUuid(download_id)))).execute(conn)
+}
+
+pub fn get_registries(
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Vec<Registry>, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::registry::dsl::*;
+    registry
+        .order(added.asc())
+        .select(Registry::as_select())
+        .load(conn)
+}
+
+pub fn get_registry(
+    registry_name: String,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Registry, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::registry::dsl::*;
+    registry
+        .filter(name.eq(registry_name))
+        .select(Registry::as_select())
+        .first(conn)
+}
+
+pub fn save_new_registry(
+    new_registry: Registry,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Registry, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::registry::dsl::*;
+    let registry_name = new_registry.name.clone();
+    diesel::insert_into(registry)
+        .values(&new_registry)
+        .execute(conn)?;
+    get_registry(registry_name, pool)
+}
+
+pub fn set_registry_cache(
+    registry_name: String,
+    index: String,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<usize, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::registry::dsl::*;
+    diesel::update(registry)
+        .filter(name.eq(registry_name))
+        .set((
+            cached_index.eq(Some(index)),
+            last_refreshed.eq(Some(Utc::now())),
+        ))
+        .execute(conn)
+}
+
+pub fn delete_registry(
+    registry_name: String,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<usize, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::registry::dsl::*;
+    diesel::delete(registry.filter(name.eq(registry_name))).execute(conn)
+}
+
 pub fn mark_request_complete(
     req_id: Uuid,
     accepted: bool,
+    note: Option<String>,
+    granted: Option<user::Permissions>,
     pool: Pool<ConnectionManager<SqliteConnection>>,
 ) -> Result<usize, diesel::result::Error> {
     let conn = &mut pool.get().unwrap();
     use schema::requests::dsl;
+    // Only undecided requests, 0 means someone else decided first.
     diesel::update(dsl::requests)
         .filter(dsl::id.eq(DbUuid(req_id)))
-        .set((dsl::accepted.eq(accepted), dsl::complete.eq(true)))
+        .filter(dsl::complete.eq(false))
+        .set((
+            dsl::accepted.eq(accepted),
+            dsl::complete.eq(true),
+            dsl::decision_note.eq(note),
+            dsl::granted_permissions.eq(granted),
+        ))
+        .execute(conn)
+}
+
+// Rejects the requests nobody decided on before the cutoff.
+// Returns the requests it rejected.
+pub fn expire_requests(
+    cutoff: DateTime<Utc>,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Vec<UserRequest>, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::requests::dsl;
+    let expired: Vec<UserRequest> = dsl::requests
+        .filter(dsl::complete.eq(false))
+        .filter(dsl::timestamp.lt(cutoff))
+        .select(UserRequest::as_select())
+        .load(conn)?;
+    diesel::update(dsl::requests)
+        .filter(dsl::id.eq_any(expired.iter().map(|req| req.id.clone())))
+        .filter(dsl::complete.eq(false))
+        .set((
+            dsl::accepted.eq(false),
+            dsl::complete.eq(true),
+            dsl::decision_note.eq("Expired before anyone decided on it"),
+        ))
+        .execute(conn)?;
+    Ok(expired)
+}
+
+pub fn delete_completed_requests(
+    cutoff: DateTime<Utc>,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<usize, diesel::result::Error> {
+    let conn = &mut pool.get().unwrap();
+    use schema::requests::dsl;
+    diesel::delete(dsl::requests)
+        .filter(dsl::complete.eq(true))
+        .filter(dsl::timestamp.lt(cutoff))
         .execute(conn)
 }
 
@@ -0,0 +1,254 @@
+// disk.rs
+//
+// Model files under llm_path can be shared by several LLMs (dedup_downloads), and every
+// llmrs LLM keeps session snapshots in local_path/llmrs-<uuid>. This keeps track of both,
+// so deleting an LLM frees what only it used, and leftovers can be found and removed.
+//
+// Files outside llm_path (imported by reference) are never deleted.
+
+use crate::database;
+use crate::llm;
+use crate::state;
+use log::{info, warn};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const SNAPSHOT_PREFIX: &str = "llmrs-";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ModelUsage {
+    pub uuid: String,
+    pub id: String,
+    pub path: PathBuf,
+    pub size: u64,
+    // How many LLMs use this file, including this one.
+    pub references: usize,
+    // Whether pantry owns the file, i.e. it lives under llm_path.
+    pub managed: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SnapshotUsage {
+    pub llm_uuid: String,
+    // None once the LLM is gone.
+    pub llm_id: Option<String>,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct OrphanedFile {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DiskReport {
+    pub models: Vec<ModelUsage>,
+    pub snapshots: Vec<SnapshotUsage>,
+    // Files and snapshot directories no LLM or download refers to.
+    pub orphans: Vec<OrphanedFile>,
+    // Counts shared files once.
+    pub total_bytes: u64,
+    pub orphaned_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct FreedSpace {
+    pub removed: Vec<PathBuf>,
+    pub bytes: u64,
+}
+
+impl FreedSpace {
+    fn remove(&mut self, path: &Path) {
+        let size = path_size(path);
+        let result = if path.is_dir() {
+            std::fs::remove_dir_all(path)
+        } else {
+            std::fs::remove_file(path)
+        };
+        match result {
+            Ok(_) => {
+                info!("Removed {:?}", path);
+                self.removed.push(path.to_path_buf());
+                self.bytes += size;
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => warn!("Failed to remove {:?}: {}", path, err),
+        }
+    }
+}
+
+// Recursive for snapshot directories.
+fn path_size(path: &Path) -> u64 {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| path_size(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+pub fn snapshot_dir(llm_uuid: Uuid, state: &state::GlobalStateWrapper) -> PathBuf {
+    state
+        .local_path
+        .join(format!("{}{}", SNAPSHOT_PREFIX, llm_uuid))
+}
+
+// Reference imports point their url at the very file they use.
+fn is_managed(llm: &llm::LLM, path: &Path, state: &state::GlobalStateWrapper) -> bool {
+    path.starts_with(&state.llm_path) && llm.url != format!("file://{}", path.to_string_lossy())
+}
+
+// Removes the LLM, its snapshots and its model file unless another LLM still uses it.
+pub fn delete_llm(uuid: Uuid, state: &state::GlobalStateWrapper) -> Result<FreedSpace, String> {
+    if state.activated_llms.contains_key(&uuid) {
+        return Err("LLM is running, deactivate it before deleting it.".into());
+    }
+    let llm = database::get_llm(uuid, state.pool.clone())
+        .map_err(|_err| format!("No LLM with uuid {}", uuid))?;
+
+    database::delete_llm(uuid, state.pool.clone())
+        .map_err(|err| format!("Failed to delete llm: {:?}", err))?;
+
+    let mut freed = FreedSpace::default();
+    if let Some(path) = llm.model_path.0.clone() {
+        let remaining = database::get_llms_by_model_path(path.clone(), state.pool.clone())
+            .map_err(|err| format!("Database failure: {:?}", err))?;
+        if !remaining.is_empty() {
+            info!(
+                "Keeping {:?}, still used by {} other LLMs",
+                path,
+                remaining.len()
+            );
+        } else if is_managed(&llm, &path, state) {
+            freed.remove(&path);
+        }
+    }
+    freed.remove(&snapshot_dir(uuid, state));
+    Ok(freed)
+}
+
+pub fn disk_usage(state: &state::GlobalStateWrapper) -> Result<DiskReport, String> {
+    let llms = database::get_available_llms(state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+    let downloads = database::get_downloads(state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+
+    let models: Vec<ModelUsage> = llms
+        .iter()
+        .filter_map(|llm| {
+            let path = llm.model_path.0.clone()?;
+            Some(ModelUsage {
+                uuid: llm.uuid.0.to_string(),
+                id: llm.id.clone(),
+                size: path_size(&path),
+                references: llms
+                    .iter()
+                    .filter(|other| other.model_path.0.as_ref() == Some(&path))
+                    .count(),
+                managed: is_managed(llm, &path, state),
+                path,
+            })
+        })
+        .collect();
+
+    let mut orphans = Vec::new();
+    let mut snapshots = Vec::new();
+
+    // Anything in llm_path that isn't a model or an unfinished download.
+    let mut referenced: HashSet<PathBuf> = models.iter().map(|m| m.path.clone()).collect();
+    referenced.extend(downloads.iter().map(|d| PathBuf::from(&d.part_path)));
+    // A download that just finished is renamed before its LLM row exists.
+    let in_flight: Vec<String> = state
+        .downloading_llms
+        .iter()
+        .map(|entry| entry.key().to_string())
+        .collect();
+    if let Ok(entries) = std::fs::read_dir(&state.llm_path) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if referenced.contains(&path) || in_flight.iter().any(|uuid| name.contains(uuid)) {
+                continue;
+            }
+            orphans.push(OrphanedFile {
+                size: path_size(&path),
+                path,
+            });
+        }
+    }
+
+    if let Ok(entries) = std::fs::read_dir(&state.local_path) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let llm_uuid = match name
+                .strip_prefix(SNAPSHOT_PREFIX)
+                .and_then(|rest| Uuid::parse_str(rest).ok())
+            {
+                Some(llm_uuid) => llm_uuid,
+                None => continue,
+            };
+            let path = entry.path();
+            let size = path_size(&path);
+            let llm_id = llms
+                .iter()
+                .find(|llm| llm.uuid.0 == llm_uuid)
+                .map(|llm| llm.id.clone());
+            if llm_id.is_none() {
+                orphans.push(OrphanedFile {
+                    path: path.clone(),
+                    size,
+                });
+            }
+            snapshots.push(SnapshotUsage {
+                llm_uuid: llm_uuid.to_string(),
+                llm_id,
+                path,
+                size,
+            });
+        }
+    }
+
+    let unique_models: HashSet<(&PathBuf, u64)> =
+        models.iter().map(|m| (&m.path, m.size)).collect();
+    let orphaned_bytes = orphans.iter().map(|orphan| orphan.size).sum();
+    let total_bytes = unique_models.iter().map(|(_, size)| size).sum::<u64>()
+        + snapshots.iter().map(|snapshot| snapshot.size).sum::<u64>()
+        + orphans
+            .iter()
+            .filter(|orphan| orphan.path.starts_with(&state.llm_path))
+            .map(|orphan| orphan.size)
+            .sum::<u64>();
+
+    Ok(DiskReport {
+        models,
+        snapshots,
+        orphans,
+        total_bytes,
+        orphaned_bytes,
+    })
+}
+
+// Deletes everything disk_usage reports as orphaned.
+pub fn collect_garbage(state: &state::GlobalStateWrapper) -> Result<FreedSpace, String> {
+    let report = disk_usage(state)?;
+    let mut freed = FreedSpace::default();
+    for orphan in report.orphans.iter() {
+        // Only ever inside our own directories, disk_usage doesn't look anywhere else.
+        if orphan.path.starts_with(&state.llm_path) || orphan.path.starts_with(&state.local_path) {
+            freed.remove(&orphan.path);
+        }
+    }
+    Ok(freed)
+}
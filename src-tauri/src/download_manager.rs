@@ -0,0 +1,183 @@
+// download_manager.rs
+//
+// Downloads wait their turn here. At most max_concurrent_downloads run at once, the rest
+// start first come first served, and together they stay under download_bytes_per_second.
+
+use crate::state;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+// How often running downloads report progress.
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+struct Slots {
+    queue: VecDeque<Uuid>,
+    running: usize,
+}
+
+pub struct DownloadManager {
+    slots: Mutex<Slots>,
+    // Poked whenever a slot frees up, the queue changes or the limit is raised.
+    changed: Notify,
+    // When the shared bandwidth budget allows the next bytes to go through.
+    next_send: Mutex<Instant>,
+}
+
+impl DownloadManager {
+    pub fn new() -> Self {
+        DownloadManager {
+            slots: Mutex::new(Slots::default()),
+            changed: Notify::new(),
+            next_send: Mutex::new(Instant::now()),
+        }
+    }
+
+    // For set_user_setting, a higher limit may let queued downloads start.
+    pub fn limits_changed(&self) {
+        self.changed.notify_waiters();
+    }
+
+    pub fn is_queued(&self, uuid: Uuid) -> bool {
+        self.slots.lock().unwrap().queue.contains(&uuid)
+    }
+}
+
+// Held while a download runs, frees the slot for the next one when dropped.
+pub struct DownloadSlot<'a> {
+    manager: &'a DownloadManager,
+}
+
+impl Drop for DownloadSlot<'_> {
+    fn drop(&mut self) {
+        self.manager.slots.lock().unwrap().running -= 1;
+        self.manager.changed.notify_waiters();
+    }
+}
+
+// Leaves the queue if the wait gets cancelled (pause, cancel) before it's our turn.
+struct Waiting<'a> {
+    manager: &'a DownloadManager,
+    uuid: Uuid,
+    started: bool,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        if self.started {
+            return;
+        }
+        self.manager
+            .slots
+            .lock()
+            .unwrap()
+            .queue
+            .retain(|queued| *queued != self.uuid);
+        self.manager.changed.notify_waiters();
+    }
+}
+
+// Resolves once this download is at the front of the queue and a slot is free.
+pub async fn wait_turn(uuid: Uuid, state: &state::GlobalStateWrapper) -> DownloadSlot<'_> {
+    let manager = &state.download_manager;
+    manager.slots.lock().unwrap().queue.push_back(uuid);
+    let mut waiting = Waiting {
+        manager,
+        uuid,
+        started: false,
+    };
+    loop {
+        // Registered before checking, so a slot freed in between isn't missed.
+        let changed = manager.changed.notified();
+        tokio::pin!(changed);
+        changed.as_mut().enable();
+
+        let max = std::cmp::max(
+            state.user_settings.read().unwrap().max_concurrent_downloads,
+            1,
+        );
+        {
+            let mut slots = manager.slots.lock().unwrap();
+            if slots.running < max && slots.queue.front() == Some(&uuid) {
+                slots.queue.pop_front();
+                slots.running += 1;
+                waiting.started = true;
+                drop(slots);
+                // The next one in line may fit as well.
+                manager.changed.notify_waiters();
+                return DownloadSlot { manager };
+            }
+        }
+        changed.await;
+    }
+}
+
+// Call after receiving bytes, sleeps long enough to keep all downloads together under
+// the limit.
+pub async fn throttle(bytes: usize, state: &state::GlobalStateWrapper) {
+    let limit = match state
+        .user_settings
+        .read()
+        .unwrap()
+        .download_bytes_per_second
+    {
+        Some(limit) if limit > 0 => limit,
+        _ => return,
+    };
+    let delay = {
+        let mut next_send = state.download_manager.next_send.lock().unwrap();
+        let now = Instant::now();
+        let start = std::cmp::max(*next_send, now);
+        *next_send = start + Duration::from_secs_f64(bytes as f64 / limit as f64);
+        start - now
+    };
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+}
+
+// Download speed over the last report interval, smoothed so the ETA doesn't jump around.
+pub struct RateMeter {
+    last_report: Instant,
+    last_bytes: u64,
+    bytes_per_second: Option<f64>,
+}
+
+impl RateMeter {
+    pub fn new(bytes: u64) -> Self {
+        RateMeter {
+            last_report: Instant::now(),
+            last_bytes: bytes,
+            bytes_per_second: None,
+        }
+    }
+
+    // Returns the speed once REPORT_INTERVAL has passed since the last report.
+    pub fn tick(&mut self, bytes: u64) -> Option<u64> {
+        let elapsed = self.last_report.elapsed();
+        if elapsed < REPORT_INTERVAL {
+            return None;
+        }
+        let current = bytes.saturating_sub(self.last_bytes) as f64 / elapsed.as_secs_f64();
+        let speed = match self.bytes_per_second {
+            Some(previous) => previous * 0.7 + current * 0.3,
+            None => current,
+        };
+        self.bytes_per_second = Some(speed);
+        self.last_report = Instant::now();
+        self.last_bytes = bytes;
+        Some(speed as u64)
+    }
+}
+
+pub fn eta_seconds(downloaded: u64, total: Option<u64>, bytes_per_second: u64) -> Option<u64> {
+    let total = total?;
+    if bytes_per_second == 0 {
+        return None;
+    }
+    Some(total.saturating_sub(downloaded) / bytes_per_second)
+}
@@ -1,5 +1,6 @@
 use crate::connectors::LLMEvent;
-use log::{debug};
+use log::{debug, info};
+use std::sync::Arc;
 use tauri::{AppHandle, Manager};
 use tokio::sync::mpsc;
 
@@ -7,10 +8,20 @@ use tokio::sync::mpsc;
 #[serde(tag = "type")]
 pub enum EmitterEventPayload {
     LLMResponse(LLMEvent),
-    Notification { message: String },
-    DownloadProgress { progress: String },
+    Notification {
+        message: String,
+    },
+    // progress is a percentage, or the bytes so far if the size is unknown.
+    DownloadProgress {
+        progress: String,
+        bytes_per_second: u64,
+        eta_seconds: Option<u64>,
+        queued: bool,
+    },
     DownloadCompletion,
-    DownloadError { message: String },
+    DownloadError {
+        message: String,
+    },
     ChannelClose, //Universally at the end of a channel
     Other,
 }
@@ -21,6 +32,40 @@ pub struct EmitterEvent {
     pub event: EmitterEventPayload,
 }
 
+// Where events for the UI go. With the app that's the webview, headless there's nobody
+// listening, so they end up in the log.
+pub trait Emitter: Send + Sync {
+    fn emit(&self, channel: &str, payload: &EmitterEvent) -> Result<(), String>;
+}
+
+pub type SharedEmitter = Arc<dyn Emitter>;
+
+pub struct TauriEmitter {
+    pub app: AppHandle,
+}
+
+impl Emitter for TauriEmitter {
+    fn emit(&self, channel: &str, payload: &EmitterEvent) -> Result<(), String> {
+        self.app
+            .emit_all(channel, payload)
+            .map_err(|err| err.to_string())
+    }
+}
+
+pub struct LogEmitter {}
+
+impl Emitter for LogEmitter {
+    fn emit(&self, channel: &str, payload: &EmitterEvent) -> Result<(), String> {
+        match &payload.event {
+            EmitterEventPayload::LLMResponse(_) => {
+                debug!("[{}] {}: {:?}", channel, payload.stream_id, payload.event)
+            }
+            _ => info!("[{}] {}: {:?}", channel, payload.stream_id, payload.event),
+        }
+        Ok(())
+    }
+}
+
 // Defines the conversion function type
 type ConversionFunc<T> = fn(String, T) -> Result<EmitterEvent, String>;
 
@@ -28,7 +73,7 @@ pub async fn send_events<T: 'static>(
     channel: String,
     stream_id: String,
     mut rx: mpsc::Receiver<T>,
-    app: AppHandle,
+    emitter: SharedEmitter,
     convert: ConversionFunc<T>,
 ) {
     debug!("STARTING RECEIVER");
@@ -36,7 +81,7 @@ pub async fn send_events<T: 'static>(
         match convert(stream_id.to_string(), payload_inner) {
             Ok(payload) => {
                 // println!("Emitting event {:?} on {:?}", payload, channel);
-                app.emit_all(&channel, &payload).unwrap()
+                emitter.emit(&channel, &payload).unwrap()
             }
             Err(_) => (),
         }
@@ -48,12 +93,12 @@ pub async fn send_events<T: 'static>(
         event: EmitterEventPayload::ChannelClose,
     };
     // println!("Emitting event {:?} on {:?}", payload, channel);
-    app.emit_all(&channel, &payload).unwrap();
+    emitter.emit(&channel, &payload).unwrap();
 }
 
 #[derive(Clone)]
 pub struct NotificationEmitter {
-    pub app: AppHandle,
+    pub emitter: SharedEmitter,
 }
 
 impl NotificationEmitter {
@@ -62,7 +107,7 @@ impl NotificationEmitter {
             stream_id: stream_id.to_string(),
             event: EmitterEventPayload::Notification { message },
         };
-        self.app.emit_all("notification", &payload).unwrap();
+        self.emitter.emit("notification", &payload)?;
         Ok(())
     }
 }
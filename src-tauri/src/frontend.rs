@@ -1,11 +1,15 @@
 use crate::connectors::llm_manager;
 use crate::database;
+use crate::disk;
 use crate::emitter;
+use crate::huggingface;
+use crate::import;
 use crate::llm;
 use crate::llm::LLMWrapper;
 use crate::registry;
 use crate::request;
 use crate::state;
+use crate::upgrade;
 use crate::user;
 use chrono::serde::ts_seconds_option;
 use chrono::DateTime;
@@ -13,6 +17,7 @@ use chrono::Utc;
 use log::{debug, info};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use tauri::AppHandle;
 use tauri::Manager;
 
@@ -89,6 +94,8 @@ pub struct LLMRequestInfo {
     pub request: request::UserRequestType,
     pub complete: bool,
     pub accepted: bool,
+    pub decision_note: Option<String>,
+    pub granted_permissions: Option<user::Permissions>,
 }
 
 // so far, we allow three conversions:
@@ -167,6 +174,8 @@ impl From<&request::UserRequest> for LLMRequestInfo {
             request: value.request.clone(),
             complete: value.complete.clone(),
             accepted: value.accepted.clone(),
+            decision_note: value.decision_note.clone(),
+            granted_permissions: value.granted_permissions.clone(),
         }
     }
 }
@@ -177,6 +186,7 @@ pub async fn get_requests(
 ) -> Result<CommandResponse<Vec<LLMRequestInfo>>, String> {
     // let requests = state.get_requests().await;
     info!("received command get_reqs");
+    request::expire_requests(state.inner())?;
     let reqs = database::get_requests(state.pool.clone())
         .map_err(|err| format!("Database failure: {:?}", err))?;
     // let mut available_llms: Vec<LLMAvailable> = Vec::new();
@@ -192,67 +202,23 @@ pub async fn get_requests(
 #[tauri::command]
 pub async fn accept_request(
     request_id: String,
+    note: Option<String>,
+    granted_permissions: Option<user::Permissions>,
     state: tauri::State<'_, state::GlobalStateWrapper>,
-    app: tauri::AppHandle,
 ) -> Result<CommandResponse<()>, String> {
     let req_uuid = Uuid::parse_str(&request_id).map_err(|e| e.to_string())?;
-
-    let req = database::get_request(req_uuid, state.pool.clone())
-        .map_err(|err| format!("Request not found: {:?}", err))?;
-
-    match req.request {
-        request::UserRequestType::DownloadRequest(dlr) => {
-            let uuid = Uuid::new_v4();
-            let llm_reg = dlr.llm_registry_entry;
-
-            let _id = llm_reg.id.clone();
-
-            tokio::spawn(async move {
-                registry::download_and_write_llm(llm_reg, uuid, app.clone()).await;
-            });
-
-            database::mark_request_complete(req_uuid, true, state.pool.clone())
-                .map_err(|err| format!("Databse failure: {:?}", err))?;
-
-            Ok(CommandResponse { data: () })
-        }
-        request::UserRequestType::PermissionRequest(pr) => {
-            database::update_permissions(
-                req.user_id.0,
-                pr.requested_permissions,
-                state.pool.clone(),
-            )
-            .map_err(|err| format!("Databse failure: {:?}", err))?;
-
-            database::mark_request_complete(req_uuid, true, state.pool.clone())
-                .map_err(|err| format!("Databse failure: {:?}", err))?;
-
-            Ok(CommandResponse { data: () })
-        }
-        request::UserRequestType::LoadRequest(lr) => {
-            load_llm(lr.llm_id, app, state.clone()).await?;
-            database::mark_request_complete(req_uuid, true, state.pool.clone())
-                .map_err(|err| format!("Databse failure: {:?}", err))?;
-            Ok(CommandResponse { data: () })
-        }
-        request::UserRequestType::UnloadRequest(ur) => {
-            unload_llm(ur.llm_id, app, state.clone()).await?;
-            database::mark_request_complete(req_uuid, true, state.pool.clone())
-                .map_err(|err| format!("Databse failure: {:?}", err))?;
-            Ok(CommandResponse { data: () })
-        }
-    }
+    request::accept_request(req_uuid, note, granted_permissions, state.inner()).await?;
+    Ok(CommandResponse { data: () })
 }
 
 #[tauri::command]
 pub async fn reject_request(
     request_id: String,
+    note: Option<String>,
     state: tauri::State<'_, state::GlobalStateWrapper>,
 ) -> Result<CommandResponse<()>, String> {
     let req_uuid = Uuid::parse_str(&request_id).map_err(|e| e.to_string())?;
-    database::mark_request_complete(req_uuid, false, state.pool.clone())
-        .map_err(|err| format!("Databse failure: {:?}", err))?;
-
+    request::reject_request(req_uuid, note, state.inner())?;
     Ok(CommandResponse { data: () })
 }
 
@@ -297,15 +263,17 @@ pub struct DownloadResponse {
 #[tauri::command]
 pub fn download_llm(
     llm_reg: registry::LLMRegistryEntry,
-    app: tauri::AppHandle,
-    _state: tauri::State<'_, state::GlobalStateWrapper>,
+    quantization: Option<String>,
+    state: tauri::State<'_, state::GlobalStateWrapper>,
 ) -> Result<CommandResponse<DownloadResponse>, String> {
     let uuid = Uuid::new_v4();
+    let llm_reg = huggingface::with_quantization(llm_reg, quantization);
 
     let id = llm_reg.id.clone();
 
+    let global_state = state.inner().clone();
     tokio::spawn(async move {
-        registry::download_and_write_llm(llm_reg, uuid, app.clone()).await;
+        registry::download_and_write_llm(llm_reg, uuid, global_state).await;
     });
 
     Ok(CommandResponse {
@@ -361,10 +329,106 @@ pub fn set_user_setting(
                 .set_password(new_password)
                 .map_err(|e| e.to_string())?
         }
+        "api_bind_address" => {
+            let addr = value
+                .as_str()
+                .ok_or("Invalid value for 'api_bind_address'")?;
+            addr.parse::<std::net::IpAddr>()
+                .map_err(|_| "Invalid value for 'api_bind_address'")?;
+            user_settings.api_bind_address = addr.into()
+        }
+        "api_port" => {
+            user_settings.api_port = value
+                .as_u64()
+                .and_then(|port| u16::try_from(port).ok())
+                .ok_or("Invalid value for 'api_port'")?
+        }
+        "enable_tcp_listener" => {
+            user_settings.enable_tcp_listener = value
+                .as_bool()
+                .ok_or("Invalid value for 'enable_tcp_listener'")?
+        }
+        "enable_socket_listener" => {
+            user_settings.enable_socket_listener = value
+                .as_bool()
+                .ok_or("Invalid value for 'enable_socket_listener'")?
+        }
+        // Null resets these to their defaults.
+        "socket_path" => user_settings.socket_path = optional_path(&value, "socket_path")?,
+        "tls_cert_path" => user_settings.tls_cert_path = optional_path(&value, "tls_cert_path")?,
+        "tls_key_path" => user_settings.tls_key_path = optional_path(&value, "tls_key_path")?,
+        "huggingface_endpoint" => {
+            user_settings.huggingface_endpoint = value
+                .as_str()
+                .filter(|endpoint| !endpoint.is_empty())
+                .ok_or("Invalid value for 'huggingface_endpoint'")?
+                .into()
+        }
+        "max_concurrent_downloads" => {
+            user_settings.max_concurrent_downloads = value
+                .as_u64()
+                .filter(|max| *max > 0)
+                .ok_or("Invalid value for 'max_concurrent_downloads'")?
+                as usize
+        }
+        // Null or 0 lifts the limit.
+        "download_bytes_per_second" => {
+            user_settings.download_bytes_per_second = match value {
+                serde_json::Value::Null => None,
+                _ => value
+                    .as_u64()
+                    .map(|limit| Some(limit).filter(|limit| *limit > 0))
+                    .ok_or("Invalid value for 'download_bytes_per_second'")?,
+            }
+        }
+        // Null keeps requests until they're decided.
+        "request_ttl_seconds" => {
+            user_settings.request_ttl_seconds = match value {
+                serde_json::Value::Null => None,
+                _ => Some(
+                    value
+                        .as_u64()
+                        .filter(|ttl| *ttl > 0)
+                        .ok_or("Invalid value for 'request_ttl_seconds'")?,
+                ),
+            }
+        }
+        "auto_approval_rules" => {
+            let rules: Vec<request::AutoApprovalRule> = serde_json::from_value(value)
+                .map_err(|e| format!("Invalid value for 'auto_approval_rules': {}", e))?;
+            if let Some(rule) = rules.iter().find(|rule| {
+                !["download", "permission", "load", "unload"].contains(&rule.request_type.as_str())
+            }) {
+                return Err(format!("Unknown request type '{}'", rule.request_type));
+            }
+            user_settings.auto_approval_rules = rules
+        }
         _ => return Err(format!("Unknown setting '{}'", key)),
     }
 
-    user_settings.save()
+    user_settings.save()?;
+    drop(user_settings);
+
+    // Listener settings only take effect on restart_listeners, so a save touching several of
+    // them restarts once.
+    if key == "max_concurrent_downloads" {
+        state.download_manager.limits_changed();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn restart_listeners(state: tauri::State<'_, state::GlobalStateWrapper>) -> Result<(), String> {
+    state.listener_restart.notify_one();
+    Ok(())
+}
+
+fn optional_path(value: &serde_json::Value, key: &str) -> Result<Option<PathBuf>, String> {
+    match value {
+        serde_json::Value::Null => Ok(None),
+        serde_json::Value::String(path) => Ok(Some(PathBuf::from(path))),
+        _ => Err(format!("Invalid value for '{}'", key)),
+    }
 }
 
 #[tauri::command]
@@ -386,45 +450,14 @@ pub async fn ping(
 #[tauri::command]
 pub async fn load_llm(
     uuid: String,
-    app: tauri::AppHandle,
+    _app: tauri::AppHandle,
     state: tauri::State<'_, state::GlobalStateWrapper>,
 ) -> Result<(), String> {
     // let uuid = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
     let uuid = Uuid::parse_str(&uuid).map_err(|e| e.to_string())?;
 
     info!("Attempting to load an LLM");
-    if state.activated_llms.contains_key(&uuid) {
-        return Err("llm already loaded".into());
-    }
-
-    let manager_addr_copy = state.manager_addr.clone();
-
-    let new_llm = database::get_llm(uuid, state.pool.clone())
-        .map_err(|err| format!("Database failure: {:?}", err))?;
-
-    let path = app
-        .path_resolver()
-        .app_local_data_dir()
-        .ok_or("no path no llms")?;
-    let settings = state.user_settings.read().unwrap().clone();
-    let result = llm::LLMActivated::activate_llm(
-        new_llm.clone(),
-        manager_addr_copy,
-        path,
-        settings,
-        state.pool.clone(),
-        app.clone(),
-    )
-    .await;
-    // new_llm.load();
-    match result {
-        Ok(running) => {
-            debug!("Inserting {uuid} into running LLMs");
-            state.activated_llms.insert(uuid, running);
-            Ok(())
-        }
-        Err(_err) => Err("failed to launch {id} skipping".into()),
-    }
+    state::activate_llm(uuid, state.inner()).await
 
     //if let Some(llm) = state.available_llms.get(&id) {
     //    match llm::LLMActivated::activate_llm(llm.value().clone(), state.manager_addr.clone()).await {
@@ -542,7 +575,6 @@ pub async fn prompt_session(
     session_id: Uuid,
     prompt: String,
     parameters: HashMap<String, Value>,
-    app: AppHandle,
     state: tauri::State<'_, state::GlobalStateWrapper>,
 ) -> Result<CommandResponse<PromptSessionResponse>, String> {
     info!(
@@ -559,12 +591,13 @@ pub async fn prompt_session(
             .await
         {
             Ok(prompt_response) => {
+                let emitter = state.emitter.clone();
                 tokio::spawn(async move {
                     emitter::send_events(
                         "llm_response".into(),
                         session_id.to_string(),
                         prompt_response.stream,
-                        app,
+                        emitter,
                         |stream_id, event| {
                             let event = emitter::EmitterEventPayload::LLMResponse(event);
 
@@ -594,7 +627,6 @@ pub async fn call_llm(
     prompt: String,
     user_session_parameters: HashMap<String, Value>,
     user_parameters: HashMap<String, Value>,
-    app: AppHandle,
     state: tauri::State<'_, state::GlobalStateWrapper>,
 ) -> Result<CommandResponse<CallLLMResponse>, String> {
     let uuid = Uuid::parse_str(&llm_uuid).map_err(|e| e.to_string())?;
@@ -622,12 +654,13 @@ pub async fn call_llm(
             .await
         {
             Ok(llm_resp) => {
+                let emitter = state.emitter.clone();
                 tokio::spawn(async move {
                     emitter::send_events(
                         "llm_response".into(),
                         llm_resp.session_id.to_string(),
                         llm_resp.stream,
-                        app,
+                        emitter,
                         |stream_id, blah| {
                             let event = emitter::EmitterEventPayload::LLMResponse(blah);
 
@@ -667,20 +700,7 @@ pub async fn unload_llm(
 ) -> Result<(), String> {
     let uuid = Uuid::parse_str(&uuid).map_err(|e| e.to_string())?;
     info!("Attempting to unload an LLM");
-
-    if let Some(_running_llm) = state.activated_llms.remove(&uuid) {
-        let unload_message = llm_manager::UnloadLLMActorMessage { uuid };
-        let manager_addr = state.manager_addr.clone();
-
-        let result = manager_addr.ask(unload_message).await;
-
-        match result {
-            Ok(_) => Ok(()),
-            Err(_) => Err("Failed to send unload message to LLMManagerActor".into()),
-        }
-    } else {
-        Err("LLM not found or already unloaded".into())
-    }
+    state::deactivate_llm(uuid, state.inner()).await
 }
 
 #[tauri::command]
@@ -692,25 +712,9 @@ pub async fn delete_llm(
     let uuid = Uuid::parse_str(&uuid).map_err(|e| e.to_string())?;
     info!("Attempting to delete an LLM");
 
-    if let Some(_running_llm) = state.activated_llms.remove(&uuid) {
-        let unload_message = llm_manager::UnloadLLMActorMessage { uuid };
-        let manager_addr = state.manager_addr.clone();
-
-        manager_addr
-            .ask(unload_message)
-            .await
-            .map_err(|err| {
-                format!(
-                    "Failed to send unload message to LLMManagerActor: {:?}",
-                    err
-                )
-            })?
-            .map_err(|err| format!("Failed to unload: {:?}", err))?;
-    }
-    match database::delete_llm(uuid, state.pool.clone()) {
-        Ok(_) => Ok(()),
-        Err(_err) => Err("Unable to find and delete llm".into()),
-    }
+    let freed = disk::delete_llm(uuid, state.inner())?;
+    info!("Deleted LLM {}, freed {} bytes", uuid, freed.bytes);
+    Ok(())
 }
 
 #[tauri::command]
@@ -759,7 +763,189 @@ pub async fn new_cli_user(
 ) -> Result<CommandResponse<user::UserInfo>, String> {
     let mut u = user::User::new("cli_user".into());
     u.perm_superuser = true;
-    let user_info = (&u).into();
-    database::save_new_user(u, state.pool.clone());
+    let (key, api_key) = user::ApiKey::new(u.id.clone(), "default".into(), None);
+    let user_info = user::UserInfo::new(&u, api_key);
+    database::save_new_user(u, state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+    database::save_new_api_key(key, state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
     Ok(CommandResponse { data: user_info })
 }
+
+#[derive(serde::Serialize)]
+pub struct UserAdminInfo {
+    pub id: String,
+    pub name: String,
+    pub permissions: user::Permissions,
+    pub keys: Vec<user::ApiKeyInfo>,
+}
+
+#[tauri::command]
+pub async fn get_users(
+    state: tauri::State<'_, state::GlobalStateWrapper>,
+) -> Result<CommandResponse<Vec<UserAdminInfo>>, String> {
+    info!("received command get_users");
+    let users = database::get_users(state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+    let mut infos = Vec::new();
+    for u in users.iter() {
+        let keys = database::get_api_keys_for_user(u.id.0, state.pool.clone())
+            .map_err(|err| format!("Database failure: {:?}", err))?;
+        infos.push(UserAdminInfo {
+            id: u.id.0.to_string(),
+            name: u.name.clone(),
+            permissions: u.into(),
+            keys: keys.iter().map(|key| key.into()).collect(),
+        });
+    }
+    Ok(CommandResponse { data: infos })
+}
+
+#[tauri::command]
+pub async fn revoke_api_key(
+    key_id: String,
+    state: tauri::State<'_, state::GlobalStateWrapper>,
+) -> Result<CommandResponse<()>, String> {
+    let key_uuid = Uuid::parse_str(&key_id).map_err(|e| e.to_string())?;
+    database::revoke_api_key(key_uuid, state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+    Ok(CommandResponse { data: () })
+}
+
+#[tauri::command]
+pub async fn delete_user(
+    user_id: String,
+    state: tauri::State<'_, state::GlobalStateWrapper>,
+) -> Result<CommandResponse<()>, String> {
+    let user_uuid = Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
+    if user_uuid == user::get_local_user().id.0 {
+        return Err("The local user cannot be deleted.".into());
+    }
+    database::delete_user(user_uuid, state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+    Ok(CommandResponse { data: () })
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct RegistryListing {
+    #[serde(flatten)]
+    pub info: registry::RegistryInfo,
+    pub models: serde_json::Map<String, Value>,
+}
+
+#[tauri::command]
+pub async fn list_registries(
+    refresh: bool,
+    state: tauri::State<'_, state::GlobalStateWrapper>,
+) -> Result<CommandResponse<Vec<RegistryListing>>, String> {
+    info!("received command list_registries");
+    let registries = registry::get_registries(refresh, state.pool.clone()).await?;
+    Ok(CommandResponse {
+        data: registries
+            .iter()
+            .map(|reg| RegistryListing {
+                info: reg.into(),
+                models: reg.models(),
+            })
+            .collect(),
+    })
+}
+
+#[tauri::command]
+pub async fn add_registry(
+    name: String,
+    location: String,
+    trust: String,
+    state: tauri::State<'_, state::GlobalStateWrapper>,
+) -> Result<CommandResponse<registry::RegistryInfo>, String> {
+    let reg = registry::add_registry(name, location, trust, state.pool.clone()).await?;
+    Ok(CommandResponse {
+        data: (&reg).into(),
+    })
+}
+
+#[tauri::command]
+pub async fn remove_registry(
+    name: String,
+    state: tauri::State<'_, state::GlobalStateWrapper>,
+) -> Result<CommandResponse<()>, String> {
+    database::delete_registry(name, state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+    Ok(CommandResponse { data: () })
+}
+
+#[tauri::command]
+pub async fn import_llm(
+    path: String,
+    id: Option<String>,
+    name: Option<String>,
+    architecture: Option<String>,
+    mode: Option<String>,
+    state: tauri::State<'_, state::GlobalStateWrapper>,
+) -> Result<CommandResponse<LLMAvailableInfo>, String> {
+    info!("received command import_llm");
+    let request = import::ImportRequest {
+        path: PathBuf::from(path),
+        id,
+        name,
+        architecture,
+        mode: match mode {
+            Some(mode) => mode.parse()?,
+            None => import::ImportMode::default(),
+        },
+    };
+    let global_state = state.inner().clone();
+    let llm = tokio::task::spawn_blocking(move || import::import_llm(request, &global_state))
+        .await
+        .map_err(|err| err.to_string())??;
+    Ok(CommandResponse {
+        data: (&llm).into(),
+    })
+}
+
+#[tauri::command]
+pub async fn list_quantizations(
+    llm_reg: registry::LLMRegistryEntry,
+    state: tauri::State<'_, state::GlobalStateWrapper>,
+) -> Result<CommandResponse<Vec<huggingface::HuggingFaceFile>>, String> {
+    info!("received command list_quantizations");
+    let endpoint = state
+        .user_settings
+        .read()
+        .unwrap()
+        .huggingface_endpoint
+        .clone();
+    Ok(CommandResponse {
+        data: huggingface::list_quantizations(&llm_reg, &endpoint).await?,
+    })
+}
+
+#[tauri::command]
+pub async fn list_upgrades(
+    refresh: bool,
+    state: tauri::State<'_, state::GlobalStateWrapper>,
+) -> Result<CommandResponse<Vec<upgrade::UpgradeInfo>>, String> {
+    info!("received command list_upgrades");
+    Ok(CommandResponse {
+        data: upgrade::available_upgrades(refresh, state.inner()).await?,
+    })
+}
+
+#[tauri::command]
+pub async fn upgrade_llm(
+    uuid: String,
+    keep_old: bool,
+    state: tauri::State<'_, state::GlobalStateWrapper>,
+) -> Result<CommandResponse<DownloadResponse>, String> {
+    info!("received command upgrade_llm");
+    let old_uuid = Uuid::parse_str(&uuid).map_err(|e| e.to_string())?;
+    let llm = database::get_llm(old_uuid, state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+    let new_uuid = upgrade::upgrade_llm(old_uuid, keep_old, state.inner()).await?;
+    Ok(CommandResponse {
+        data: DownloadResponse {
+            uuid: new_uuid.to_string(),
+            stream: format!("{}-{}", llm.id, new_uuid),
+        },
+    })
+}
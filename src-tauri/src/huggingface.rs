@@ -0,0 +1,297 @@
+// huggingface.rs
+//
+// Registry entries can name a Hugging Face repo instead of a url. Before downloading we
+// resolve the repo, revision and filename (or quantization) into a url, size and the
+// LFS sha256.
+//
+// The endpoint is either a hub (https://huggingface.co, or a stub server with the same
+// api) or a local mirror directory laid out like the hub's resolve urls:
+// <dir>/<org>/<repo>/resolve/<revision>/<filename>
+
+use crate::registry::LLMRegistryEntry;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_ENDPOINT: &str = "https://huggingface.co";
+
+const MODEL_EXTENSIONS: [&str; 2] = ["gguf", "bin"];
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HuggingFaceSource {
+    // org/name
+    pub repo: String,
+    // Branch, tag or commit. Defaults to main.
+    #[serde(default)]
+    pub revision: Option<String>,
+    // An exact file wins over the quantization.
+    #[serde(default)]
+    pub filename: Option<String>,
+    // Matched case insensitively against file names, like q4_0 or Q5_K_M.
+    #[serde(default)]
+    pub quantization: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct HuggingFaceFile {
+    pub filename: String,
+    pub url: String,
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+    pub quantization: Option<String>,
+}
+
+// Just the parts of /api/models/<repo>/revision/<revision>?blobs=true we use.
+#[derive(Debug, serde::Deserialize)]
+struct HubModelInfo {
+    siblings: Vec<HubSibling>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct HubSibling {
+    rfilename: String,
+    size: Option<u64>,
+    lfs: Option<HubLfs>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct HubLfs {
+    sha256: String,
+    size: Option<u64>,
+}
+
+impl HuggingFaceSource {
+    fn revision(&self) -> &str {
+        self.revision.as_deref().unwrap_or("main")
+    }
+}
+
+fn is_model_file(filename: &str) -> bool {
+    Path::new(filename)
+        .extension()
+        .map(|ext| MODEL_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+// llama-2-7b.Q4_K_M.gguf -> Q4_K_M, open_llama_7b-q5_1-ggjt.bin -> q5_1
+pub fn quantization_of(filename: &str) -> Option<String> {
+    let stem = Path::new(filename)
+        .file_stem()?
+        .to_string_lossy()
+        .into_owned();
+    stem.split(|c| c == '.' || c == '-')
+        .find(|part| {
+            let lower = part.to_lowercase();
+            let mut chars = lower.chars();
+            match (chars.next(), chars.next()) {
+                (Some('q'), Some(digit)) => digit.is_ascii_digit(),
+                _ => lower == "f16" || lower == "f32",
+            }
+        })
+        .map(|part| part.to_string())
+}
+
+fn is_remote(endpoint: &str) -> bool {
+    endpoint.starts_with("http://") || endpoint.starts_with("https://")
+}
+
+async fn list_hub_files(
+    source: &HuggingFaceSource,
+    endpoint: &str,
+) -> Result<Vec<HuggingFaceFile>, String> {
+    let endpoint = endpoint.trim_end_matches('/');
+    let api_url = format!(
+        "{}/api/models/{}/revision/{}?blobs=true",
+        endpoint,
+        source.repo,
+        source.revision()
+    );
+    let info: HubModelInfo = reqwest::get(&api_url)
+        .await
+        .map_err(|err| format!("Failed to reach {}: {:?}", endpoint, err))?
+        .error_for_status()
+        .map_err(|err| format!("Failed to look up {}: {:?}", source.repo, err))?
+        .json()
+        .await
+        .map_err(|err| format!("Unexpected response for {}: {:?}", source.repo, err))?;
+
+    Ok(info
+        .siblings
+        .into_iter()
+        .filter(|sibling| is_model_file(&sibling.rfilename))
+        .map(|sibling| HuggingFaceFile {
+            url: format!(
+                "{}/{}/resolve/{}/{}",
+                endpoint,
+                source.repo,
+                source.revision(),
+                sibling.rfilename
+            ),
+            size: sibling
+                .lfs
+                .as_ref()
+                .and_then(|lfs| lfs.size)
+                .or(sibling.size),
+            sha256: sibling.lfs.map(|lfs| lfs.sha256.to_lowercase()),
+            quantization: quantization_of(&sibling.rfilename),
+            filename: sibling.rfilename,
+        })
+        .collect())
+}
+
+fn walk(dir: &Path, prefix: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_dir() {
+            walk(&path, &prefix.join(entry.file_name()), files)?;
+        } else {
+            files.push(prefix.join(entry.file_name()));
+        }
+    }
+    Ok(())
+}
+
+// Mirrors have no LFS metadata, the download still gets hashed and recorded.
+fn list_mirror_files(
+    source: &HuggingFaceSource,
+    endpoint: &str,
+) -> Result<Vec<HuggingFaceFile>, String> {
+    // Entries can come from a request, they don't get to leave the mirror.
+    for part in [source.repo.as_str(), source.revision()] {
+        let escapes = Path::new(part)
+            .components()
+            .any(|component| !matches!(component, std::path::Component::Normal(_)));
+        if escapes {
+            return Err(format!("{} is not a valid repo or revision", part));
+        }
+    }
+    let root = PathBuf::from(endpoint.strip_prefix("file://").unwrap_or(endpoint))
+        .join(&source.repo)
+        .join("resolve")
+        .join(source.revision());
+    let mut relative = Vec::new();
+    walk(&root, Path::new(""), &mut relative)
+        .map_err(|err| format!("Failed to read mirror {:?}: {}", root, err))?;
+
+    Ok(relative
+        .into_iter()
+        .map(|rel| rel.to_string_lossy().replace('\\', "/"))
+        .filter(|filename| is_model_file(filename))
+        .map(|filename| {
+            let path = root.join(&filename);
+            HuggingFaceFile {
+                url: format!("file://{}", path.to_string_lossy()),
+                size: std::fs::metadata(&path).map(|m| m.len()).ok(),
+                sha256: None,
+                quantization: quantization_of(&filename),
+                filename,
+            }
+        })
+        .collect())
+}
+
+// Every model file in the repo at that revision.
+pub async fn list_files(
+    source: &HuggingFaceSource,
+    endpoint: &str,
+) -> Result<Vec<HuggingFaceFile>, String> {
+    let mut files = if is_remote(endpoint) {
+        list_hub_files(source, endpoint).await?
+    } else {
+        list_mirror_files(source, endpoint)?
+    };
+    files.sort_by(|a, b| a.filename.cmp(&b.filename));
+    Ok(files)
+}
+
+fn pick(
+    source: &HuggingFaceSource,
+    files: Vec<HuggingFaceFile>,
+) -> Result<HuggingFaceFile, String> {
+    if let Some(filename) = &source.filename {
+        return files
+            .into_iter()
+            .find(|file| &file.filename == filename)
+            .ok_or(format!("{} has no file {}", source.repo, filename));
+    }
+
+    let available: Vec<String> = files
+        .iter()
+        .map(|file| file.quantization.clone().unwrap_or(file.filename.clone()))
+        .collect();
+    let mut matching: Vec<HuggingFaceFile> = match &source.quantization {
+        Some(quantization) => {
+            let wanted = quantization.to_lowercase();
+            files
+                .into_iter()
+                .filter(|file| {
+                    file.quantization
+                        .as_ref()
+                        .map(|q| q.to_lowercase() == wanted)
+                        .unwrap_or(false)
+                })
+                .collect()
+        }
+        None => files,
+    };
+    match matching.len() {
+        1 => Ok(matching.remove(0)),
+        0 => Err(format!(
+            "{} has no matching model file, available: {:?}",
+            source.repo, available
+        )),
+        _ => Err(format!(
+            "{} has several model files, pick a quantization from {:?}",
+            source.repo, available
+        )),
+    }
+}
+
+// Fills in url, size and sha256 for entries with a huggingface source. Others pass through.
+pub async fn resolve(
+    mut llm_reg: LLMRegistryEntry,
+    endpoint: &str,
+) -> Result<LLMRegistryEntry, String> {
+    let source = match &llm_reg.huggingface {
+        Some(source) => source.clone(),
+        None => return Ok(llm_reg),
+    };
+    let file = pick(&source, list_files(&source, endpoint).await?)?;
+
+    llm_reg.url = file.url;
+    if let Some(size) = file.size {
+        llm_reg.size = Some(size);
+    }
+    // A hash pinned in the registry takes precedence over what the hub says.
+    if llm_reg.sha256.is_none() {
+        llm_reg.sha256 = file.sha256;
+    }
+    // Pin the choice, so resuming later fetches the same file.
+    if let Some(hf) = llm_reg.huggingface.as_mut() {
+        hf.filename = Some(file.filename);
+    }
+    Ok(llm_reg)
+}
+
+// Download time choice, overrides whatever the registry entry picked.
+pub fn with_quantization(
+    mut llm_reg: LLMRegistryEntry,
+    quantization: Option<String>,
+) -> LLMRegistryEntry {
+    if let (Some(quantization), Some(source)) = (quantization, llm_reg.huggingface.as_mut()) {
+        source.quantization = Some(quantization);
+        source.filename = None;
+    }
+    llm_reg
+}
+
+// What a user can pick from for an entry.
+pub async fn list_quantizations(
+    llm_reg: &LLMRegistryEntry,
+    endpoint: &str,
+) -> Result<Vec<HuggingFaceFile>, String> {
+    match &llm_reg.huggingface {
+        Some(source) => list_files(source, endpoint).await,
+        None => Err(format!("{} is not a Hugging Face entry", llm_reg.id)),
+    }
+}
@@ -0,0 +1,448 @@
+// import.rs
+//
+// Registers model files that are already on disk, e.g. from other tooling, without
+// going through a registry download.
+
+use crate::connectors;
+use crate::database;
+use crate::database_types::*;
+use crate::llm;
+use crate::registry;
+use crate::registry_index;
+use crate::state;
+use chrono::Utc;
+use log::info;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    // Use the file where it is. Pantry never deletes it.
+    Reference,
+    Copy,
+    Hardlink,
+}
+
+impl Default for ImportMode {
+    fn default() -> Self {
+        ImportMode::Reference
+    }
+}
+
+impl std::str::FromStr for ImportMode {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<ImportMode, Self::Err> {
+        match input {
+            "reference" => Ok(ImportMode::Reference),
+            "copy" => Ok(ImportMode::Copy),
+            "hardlink" => Ok(ImportMode::Hardlink),
+            _ => Err(format!(
+                "unknown import mode {}, expected reference, copy or hardlink",
+                input
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ImportRequest {
+    pub path: PathBuf,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub architecture: Option<String>,
+    #[serde(default)]
+    pub mode: ImportMode,
+}
+
+// What we could read from the start of the file.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ModelHeader {
+    pub format: String,
+    pub version: u32,
+    pub architecture: Option<String>,
+    pub name: Option<String>,
+}
+
+const GGUF_MAGIC: u32 = 0x46554747;
+const GGML_MAGIC: u32 = 0x67676d6c;
+const GGMF_MAGIC: u32 = 0x67676d66;
+const GGJT_MAGIC: u32 = 0x67676a74;
+
+// general.* keys come first in practice, anything past this is tokenizer data.
+const GGUF_MAX_KV: u64 = 64;
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Lengths and counts are u32 in gguf v1 and u64 from v2 on.
+fn read_len<R: Read>(reader: &mut R, version: u32) -> std::io::Result<u64> {
+    if version == 1 {
+        read_u32(reader).map(|len| len as u64)
+    } else {
+        read_u64(reader)
+    }
+}
+
+fn read_gguf_string<R: Read>(reader: &mut R, version: u32) -> std::io::Result<String> {
+    let len = read_len(reader, version)?;
+    if len > 1024 * 1024 {
+        return Err(invalid("gguf string too long"));
+    }
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+fn skip<R: Read>(reader: &mut R, bytes: u64) -> std::io::Result<()> {
+    let copied = std::io::copy(&mut reader.by_ref().take(bytes), &mut std::io::sink())?;
+    if copied != bytes {
+        return Err(invalid("unexpected end of file"));
+    }
+    Ok(())
+}
+
+// Arrays of arrays are legal, but real files don't go anywhere near this deep. Stops a
+// crafted header from recursing until the stack runs out.
+const MAX_GGUF_NESTING: u32 = 8;
+
+// Reads (or skips) one gguf value, only strings are kept. depth counts enclosing arrays.
+fn read_gguf_value<R: Read>(
+    reader: &mut R,
+    version: u32,
+    value_type: u32,
+    depth: u32,
+) -> std::io::Result<Option<String>> {
+    match value_type {
+        0 | 1 | 7 => skip(reader, 1)?,
+        2 | 3 => skip(reader, 2)?,
+        4 | 5 | 6 => skip(reader, 4)?,
+        10 | 11 | 12 => skip(reader, 8)?,
+        8 => return read_gguf_string(reader, version).map(Some),
+        9 => {
+            if depth >= MAX_GGUF_NESTING {
+                return Err(invalid("gguf arrays nested too deep"));
+            }
+            let item_type = read_u32(reader)?;
+            let count = read_len(reader, version)?;
+            for _ in 0..count {
+                read_gguf_value(reader, version, item_type, depth + 1)?;
+            }
+        }
+        _ => return Err(invalid("unknown gguf value type")),
+    }
+    Ok(None)
+}
+
+pub fn read_header(path: &Path) -> std::io::Result<ModelHeader> {
+    parse_header(BufReader::new(File::open(path)?))
+}
+
+fn parse_header<R: Read>(mut reader: R) -> std::io::Result<ModelHeader> {
+    let magic = read_u32(&mut reader)?;
+    let (format, version) = match magic {
+        GGUF_MAGIC => ("gguf", read_u32(&mut reader)?),
+        // Old ggml files have no metadata we could use, just hyperparameters.
+        GGML_MAGIC => ("ggml", 0),
+        GGMF_MAGIC => ("ggmf", read_u32(&mut reader)?),
+        GGJT_MAGIC => ("ggjt", read_u32(&mut reader)?),
+        _ => return Err(invalid("not a ggml or gguf model file")),
+    };
+    let mut header = ModelHeader {
+        format: format.into(),
+        version,
+        architecture: None,
+        name: None,
+    };
+    if magic != GGUF_MAGIC {
+        return Ok(header);
+    }
+
+    let _tensor_count = read_len(&mut reader, version)?;
+    let kv_count = read_len(&mut reader, version)?;
+    for _ in 0..std::cmp::min(kv_count, GGUF_MAX_KV) {
+        let key = read_gguf_string(&mut reader, version)?;
+        let value_type = read_u32(&mut reader)?;
+        let value = read_gguf_value(&mut reader, version, value_type, 0)?;
+        match key.as_str() {
+            "general.architecture" => header.architecture = value,
+            "general.name" => header.name = value,
+            _ => {}
+        }
+        if header.architecture.is_some() && header.name.is_some() {
+            break;
+        }
+    }
+    Ok(header)
+}
+
+pub fn import_llm(
+    request: ImportRequest,
+    state: &state::GlobalStateWrapper,
+) -> Result<llm::LLM, String> {
+    let source = request
+        .path
+        .canonicalize()
+        .map_err(|err| format!("Can't find {:?}: {}", request.path, err))?;
+    if !source.is_file() {
+        return Err(format!("{:?} is not a file", source));
+    }
+    let header =
+        read_header(&source).map_err(|err| format!("Can't import {:?}: {}", source, err))?;
+
+    let architecture = request
+        .architecture
+        .or(header.architecture.clone())
+        .ok_or(format!(
+            "Couldn't infer the architecture of this {} file, please pass one.",
+            header.format
+        ))?;
+    if !registry_index::MODEL_ARCHITECTURES.contains(&architecture.as_str()) {
+        return Err(format!(
+            "Unsupported architecture {}, expected one of {:?}",
+            architecture,
+            registry_index::MODEL_ARCHITECTURES
+        ));
+    }
+
+    let id = match request.id {
+        Some(id) => id,
+        None => source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .ok_or("Can't derive an id from the file name, please pass one.")?,
+    };
+    // The id ends up in a file name under llm_path.
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+    {
+        return Err(format!("Invalid id {}, use letters, digits, - _ and .", id));
+    }
+    let url = format!("file://{}", source.to_string_lossy());
+    if database::get_llm_by_url(url.clone(), state.pool.clone()).is_ok() {
+        return Err(format!("{:?} has already been imported.", source));
+    }
+
+    let uuid = Uuid::new_v4();
+    let model_path = match request.mode {
+        ImportMode::Reference => source.clone(),
+        ImportMode::Copy | ImportMode::Hardlink => {
+            let mut dest = state.llm_path.clone();
+            dest.push(format!("{}-{}", id, uuid.to_string()));
+            if request.mode == ImportMode::Copy {
+                std::fs::copy(&source, &dest)
+                    .map_err(|err| format!("Failed to copy {:?}: {}", source, err))?;
+            } else {
+                // Fails across filesystems, copy is the fallback the user can pick.
+                std::fs::hard_link(&source, &dest)
+                    .map_err(|err| format!("Failed to link {:?}: {}", source, err))?;
+            }
+            dest
+        }
+    };
+    let sha256 = registry::hash_file(&model_path)
+        .map_err(|err| format!("Failed to hash {:?}: {}", model_path, err))?;
+
+    info!("Importing {:?} as {} ({})", source, id, architecture);
+    let new_llm = llm::LLM {
+        id: id.clone(),
+        family_id: architecture.clone(),
+        organization: "".into(),
+        name: request.name.or(header.name).unwrap_or(id.clone()),
+        license: "".into(),
+        description: format!("Imported {} model.", header.format),
+        downloaded_reason: format!("Imported from {}", source.to_string_lossy()),
+        downloaded_date: Utc::now(),
+        last_called: None,
+        url,
+        homepage: "".into(),
+
+        uuid: DbUuid(uuid),
+
+        capabilities: DbHashMapInt(HashMap::from([
+            ("general".into(), -1),
+            ("assistant".into(), -1),
+            ("coding".into(), -1),
+            ("writing".into(), -1),
+        ])),
+        tags: DbVec(vec!["imported".into()]),
+
+        requirements: "".into(),
+
+        local: true,
+        connector_type: connectors::LLMConnectorType::LLMrs,
+        config: DbHashMap(HashMap::from([(
+            "model_architecture".to_string(),
+            json!(architecture),
+        )])),
+        parameters: DbHashMap(HashMap::<String, Value>::new()),
+        user_parameters: DbVec(vec![
+            "sampler_string".into(),
+            "pre_prompt".into(),
+            "post_prompt".into(),
+        ]),
+        session_parameters: DbHashMap(HashMap::new()),
+        user_session_parameters: DbVec(vec!["system_prompt".into()]),
+        model_path: DbOptionPathbuf(Some(model_path.clone())),
+        sha256: Some(sha256),
+        version: None,
+        registry: None,
+    };
+
+    database::save_new_llm(new_llm, state.pool.clone()).map_err(|err| {
+        // Don't leave a copy behind for a model we couldn't register.
+        if request.mode != ImportMode::Reference {
+            let _ = std::fs::remove_file(&model_path);
+        }
+        format!("Failed to save llm: {:?}", err)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gguf_string(buf: &mut Vec<u8>, version: u32, value: &str) {
+        if version == 1 {
+            buf.extend((value.len() as u32).to_le_bytes());
+        } else {
+            buf.extend((value.len() as u64).to_le_bytes());
+        }
+        buf.extend(value.as_bytes());
+    }
+
+    fn gguf_len(buf: &mut Vec<u8>, version: u32, len: u64) {
+        if version == 1 {
+            buf.extend((len as u32).to_le_bytes());
+        } else {
+            buf.extend(len.to_le_bytes());
+        }
+    }
+
+    // A header with a u32, an array of strings, then the two keys we read.
+    fn gguf(version: u32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend(GGUF_MAGIC.to_le_bytes());
+        buf.extend(version.to_le_bytes());
+        gguf_len(&mut buf, version, 291);
+        gguf_len(&mut buf, version, 4);
+
+        gguf_string(&mut buf, version, "general.quantization_version");
+        buf.extend(4u32.to_le_bytes());
+        buf.extend(2u32.to_le_bytes());
+
+        gguf_string(&mut buf, version, "general.tags");
+        buf.extend(9u32.to_le_bytes());
+        buf.extend(8u32.to_le_bytes());
+        gguf_len(&mut buf, version, 2);
+        gguf_string(&mut buf, version, "chat");
+        gguf_string(&mut buf, version, "general.name");
+
+        gguf_string(&mut buf, version, "general.architecture");
+        buf.extend(8u32.to_le_bytes());
+        gguf_string(&mut buf, version, "llama");
+
+        gguf_string(&mut buf, version, "general.name");
+        buf.extend(8u32.to_le_bytes());
+        gguf_string(&mut buf, version, "Test Llama");
+        buf
+    }
+
+    #[test]
+    fn reads_gguf_metadata() {
+        for version in [1, 2, 3] {
+            let header = parse_header(&gguf(version)[..]).unwrap();
+            assert_eq!(header.format, "gguf");
+            assert_eq!(header.version, version);
+            assert_eq!(header.architecture.as_deref(), Some("llama"));
+            assert_eq!(header.name.as_deref(), Some("Test Llama"));
+        }
+    }
+
+    #[test]
+    fn reads_older_formats() {
+        let mut ggjt = GGJT_MAGIC.to_le_bytes().to_vec();
+        ggjt.extend(3u32.to_le_bytes());
+        let header = parse_header(&ggjt[..]).unwrap();
+        assert_eq!((header.format.as_str(), header.version), ("ggjt", 3));
+        assert!(header.architecture.is_none());
+
+        let header = parse_header(&GGML_MAGIC.to_le_bytes()[..]).unwrap();
+        assert_eq!((header.format.as_str(), header.version), ("ggml", 0));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let err = parse_header(&b"PK\x03\x04 not a model"[..]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(parse_header(&b"GG"[..]).is_err());
+    }
+
+    #[test]
+    fn rejects_broken_gguf() {
+        let full = gguf(3);
+        assert!(parse_header(&full[..full.len() - 3]).is_err());
+
+        let mut unknown_type = Vec::new();
+        unknown_type.extend(GGUF_MAGIC.to_le_bytes());
+        unknown_type.extend(3u32.to_le_bytes());
+        gguf_len(&mut unknown_type, 3, 0);
+        gguf_len(&mut unknown_type, 3, 1);
+        gguf_string(&mut unknown_type, 3, "general.name");
+        unknown_type.extend(99u32.to_le_bytes());
+        assert!(parse_header(&unknown_type[..]).is_err());
+
+        let mut huge_string = Vec::new();
+        huge_string.extend(GGUF_MAGIC.to_le_bytes());
+        huge_string.extend(3u32.to_le_bytes());
+        gguf_len(&mut huge_string, 3, 0);
+        gguf_len(&mut huge_string, 3, 1);
+        gguf_len(&mut huge_string, 3, u64::MAX);
+        assert!(parse_header(&huge_string[..]).is_err());
+    }
+
+    #[test]
+    fn rejects_deeply_nested_arrays() {
+        let nested = |levels: usize| {
+            let mut buf = Vec::new();
+            buf.extend(GGUF_MAGIC.to_le_bytes());
+            buf.extend(3u32.to_le_bytes());
+            gguf_len(&mut buf, 3, 0);
+            gguf_len(&mut buf, 3, 1);
+            gguf_string(&mut buf, 3, "general.nested");
+            buf.extend(9u32.to_le_bytes());
+            // Each array holds one array, the innermost one a single u8.
+            for _ in 1..levels {
+                buf.extend(9u32.to_le_bytes());
+                gguf_len(&mut buf, 3, 1);
+            }
+            buf.extend(0u32.to_le_bytes());
+            gguf_len(&mut buf, 3, 1);
+            buf.push(1);
+            buf
+        };
+        assert!(parse_header(&nested(MAX_GGUF_NESTING as usize)[..]).is_ok());
+        let err = parse_header(&nested(10_000)[..]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -1,117 +1,284 @@
 //listener.rs
 
+use crate::state::UserSettings;
 use axum;
 use axum::routing::Router;
+use futures::future::join_all;
+use futures::StreamExt;
 use hyper::Server;
-use log::{error, info};
+use log::{error, info, warn};
 use std::fs;
-use tokio::sync::oneshot;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio_native_tls::{native_tls, TlsAcceptor};
+use tokio_stream::wrappers::ReceiverStream;
 
-// Bind the Unix socket
+type ListenerFuture = Pin<Box<dyn Future<Output = Result<(), String>> + Send>>;
 
-async fn flatten<T>(handle: tokio::task::JoinHandle<Result<T, hyper::Error>>) -> Result<T, String> {
-    match handle.await {
-        Ok(Ok(result)) => Ok(result),
-        Ok(Err(err)) => Err(err.to_string()),
-        Err(_err) => Err("handling failed".into()),
+// Clients that don't finish the TLS handshake by then are dropped.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Snapshot of the listener related user settings, taken each time we (re)start.
+#[derive(Debug, Clone)]
+pub struct ListenerConfig {
+    pub bind_address: String,
+    pub port: u16,
+    pub enable_tcp: bool,
+    pub enable_socket: bool,
+    pub socket_path: PathBuf,
+    pub tls: Option<(PathBuf, PathBuf)>,
+}
+
+impl From<&UserSettings> for ListenerConfig {
+    fn from(settings: &UserSettings) -> Self {
+        ListenerConfig {
+            bind_address: settings.api_bind_address.clone(),
+            port: settings.api_port.clone(),
+            enable_tcp: settings.enable_tcp_listener.clone(),
+            enable_socket: settings.enable_socket_listener.clone(),
+            socket_path: settings.get_socket_path(),
+            tls: match (&settings.tls_cert_path, &settings.tls_key_path) {
+                (Some(cert), Some(key)) => Some((cert.clone(), key.clone())),
+                _ => None,
+            },
+        }
     }
 }
 
+fn tcp_listener(
+    app: Router<(), axum::body::Body>,
+    config: &ListenerConfig,
+    rx: oneshot::Receiver<()>,
+) -> Result<ListenerFuture, String> {
+    let ip: IpAddr = config
+        .bind_address
+        .parse()
+        .map_err(|err| format!("invalid bind address {}: {:?}", config.bind_address, err))?;
+    let addr = SocketAddr::new(ip, config.port);
+
+    if let Some((cert_path, key_path)) = &config.tls {
+        return tls_listener(app, addr, cert_path, key_path, rx);
+    }
+
+    let tcp_fut = axum::Server::try_bind(&addr)
+        .map_err(|err| format!("failed to bind to {}: {:?}", addr, err))?
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(async {
+            rx.await.ok();
+        });
+    info!("API listening on http://{}", addr);
+
+    // Served from the listeners' own task rather than a spawned one, so aborting that task
+    // really closes the port.
+    Ok(Box::pin(async move {
+        tcp_fut.await.map_err(|err| err.to_string())
+    }))
+}
+
+fn tls_listener(
+    app: Router<(), axum::body::Body>,
+    addr: SocketAddr,
+    cert_path: &PathBuf,
+    key_path: &PathBuf,
+    rx: oneshot::Receiver<()>,
+) -> Result<ListenerFuture, String> {
+    let cert = fs::read(cert_path).map_err(|err| format!("failed to read TLS cert: {:?}", err))?;
+    let key = fs::read(key_path).map_err(|err| format!("failed to read TLS key: {:?}", err))?;
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key)
+        .map_err(|err| format!("invalid TLS cert/key: {:?}", err))?;
+    let acceptor: TlsAcceptor = native_tls::TlsAcceptor::new(identity)
+        .map_err(|err| format!("failed to build TLS acceptor: {:?}", err))?
+        .into();
+
+    let std_listener = std::net::TcpListener::bind(addr)
+        .map_err(|err| format!("failed to bind to {}: {:?}", addr, err))?;
+    std_listener
+        .set_nonblocking(true)
+        .map_err(|err| err.to_string())?;
+    let listener =
+        tokio::net::TcpListener::from_std(std_listener).map_err(|err| err.to_string())?;
+
+    // Each handshake runs in its own task, so a slow or stalled client can't hold up the
+    // accept loop. Only finished handshakes reach hyper, a failed one just drops that connection.
+    let (tx, handshakes) = mpsc::channel(64);
+    let accept_loop = async move {
+        loop {
+            let stream = tokio::select! {
+                // hyper dropped the receiver, so the server has shut down.
+                _ = tx.closed() => break,
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _addr)) => stream,
+                    Err(err) => {
+                        warn!("Failed to accept connection: {:?}", err);
+                        continue;
+                    }
+                },
+            };
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(tls_stream)) => {
+                        let _ = tx.send(tls_stream).await;
+                    }
+                    Ok(Err(err)) => warn!("TLS handshake failed: {:?}", err),
+                    Err(_elapsed) => warn!("TLS handshake timed out"),
+                }
+            });
+        }
+    };
+    let incoming = ReceiverStream::new(handshakes).map(Ok::<_, std::io::Error>);
+
+    let tls_fut = Server::builder(hyper::server::accept::from_stream(incoming))
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(async {
+            rx.await.ok();
+        });
+    info!("API listening on https://{}", addr);
+
+    // The accept loop owns the port, so it's joined with the server instead of spawned.
+    Ok(Box::pin(async move {
+        let (result, ()) = tokio::join!(tls_fut, accept_loop);
+        result.map_err(|err| err.to_string())
+    }))
+}
+
 #[cfg(target_family = "unix")]
 use hyperlocal::UnixServerExt;
 
 #[cfg(target_family = "unix")]
-pub async fn create_listeners(
+fn socket_listener(
     app: Router<(), axum::body::Body>,
+    config: &ListenerConfig,
     rx: oneshot::Receiver<()>,
-) -> Result<(), String> {
-    // Create an Axum `Server` using the Unix socket listener
-    let (tx1, rx1) = oneshot::channel::<()>();
-    let (tx2, rx2) = oneshot::channel::<()>();
-
-    fs::remove_file("/tmp/pantrylocal.sock");
-    let socket_fut = Server::bind_unix("/tmp/pantrylocal.sock")
-        .map_err(|err| format!("failed to bind to socket: {:?}", err))?
-        .serve(app.clone().into_make_service())
-        .with_graceful_shutdown(async {
-            rx1.await.ok();
-        });
+) -> Result<ListenerFuture, String> {
+    use std::os::unix::fs::PermissionsExt;
 
-    let unix_handle = tokio::spawn(socket_fut);
+    let socket_path = config.socket_path.clone();
+    if let Some(parent) = socket_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        check_socket_dir(parent)?;
+    }
 
-    let tcp_fut = axum::Server::bind(&"0.0.0.0:9404".parse().unwrap())
+    let _ = fs::remove_file(&socket_path);
+    let bound = Server::bind_unix(&socket_path)
+        .map_err(|err| format!("failed to bind to socket: {:?}", err))?;
+    // check_socket_dir already keeps other users out of the dir, this is for good measure.
+    fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600))
+        .map_err(|err| format!("failed to set socket permissions: {:?}", err))?;
+    let socket_fut = bound
         .serve(app.into_make_service())
         .with_graceful_shutdown(async {
-            rx2.await.ok();
+            rx.await.ok();
         });
+    info!("API listening on {:?}", socket_path);
 
-    let tcp_handle = tokio::spawn(tcp_fut);
+    Ok(Box::pin(async move {
+        let result = socket_fut.await.map_err(|err| err.to_string());
+        fs::remove_file(&socket_path).map_err(|err| format!("Error removing file: {:?}", err))?;
+        result
+    }))
+}
 
-    tokio::spawn(async {
-        rx.await;
-        tx1.send(());
-        tx2.send(());
-    });
+// The socket's dir has to be ours alone, anyone who can write to it can swap the socket out
+// from under us. A missing dir is created 0700, an existing one is refused unless it's owned
+// by us with no group or other bits set.
+#[cfg(target_family = "unix")]
+fn check_socket_dir(dir: &std::path::Path) -> Result<(), String> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
 
-    match tokio::join!(flatten(tcp_handle), flatten(unix_handle)) {
-        (Ok(_val), Ok(_val2)) => {
-            info!("Successful shutdown.");
-            fs::remove_file("/tmp/pantrylocal.sock")
-                .map_err(|err| format!("Error removing file: {:?}", err))
-        }
-        (Err(err), Ok(_)) => {
-            error!("Failed with {}.", err);
-            Err(err)
-        }
-        (Ok(_), Err(err)) => {
-            error!("Failed 2 with {}.", err);
-            Err(err)
-        }
-        (Err(err), Err(err2)) => {
-            error!("Failed double with {} and {}.", err, err2);
-            Err(err)
-        }
+    if !dir.exists() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .map_err(|err| format!("failed to create socket dir: {:?}", err))?;
+    }
+    // symlink_metadata so a link to someone else's dir doesn't pass.
+    let meta =
+        fs::symlink_metadata(dir).map_err(|err| format!("failed to read socket dir: {:?}", err))?;
+    if !meta.is_dir() {
+        return Err(format!("socket dir {:?} is not a directory", dir));
+    }
+    if meta.uid() != unsafe { libc::geteuid() } {
+        return Err(format!("socket dir {:?} is owned by another user", dir));
     }
-    // if one crashes we DONT wanna die
-    // match tokio::try_join!(flatten(tcp_handle), flatten(unix_handle)) {
-    //     Ok(_val) => {
-    //         println!("Success?!");
-    //         fs::remove_file("/tmp/pantrylocal.sock")
-    //             .map_err(|err| format!("Error removing file: {:?}", err))
-    //     }
-    //     Err(err) => {
-    //         println!("Failed with {}.", err);
-    //         Err(err)
-    //     }
-    // }
+    if meta.mode() & 0o077 != 0 {
+        return Err(format!(
+            "socket dir {:?} is accessible to other users (mode {:o})",
+            dir,
+            meta.mode() & 0o777
+        ));
+    }
+    Ok(())
 }
 
 // On windows we only run the TCP one.
 // There's an ongoing effort to implement window's new UDS support into tokio, but it's not ready
 // yet and fixes currently out aren't super mature yet.
 #[cfg(target_family = "windows")]
+fn socket_listener(
+    _app: Router<(), axum::body::Body>,
+    _config: &ListenerConfig,
+    _rx: oneshot::Receiver<()>,
+) -> Result<ListenerFuture, String> {
+    Err("unix sockets are not supported on windows".into())
+}
+
 pub async fn create_listeners(
     app: Router<(), axum::body::Body>,
+    config: ListenerConfig,
     rx: oneshot::Receiver<()>,
 ) -> Result<(), String> {
-    // Create an Axum `Server` using the Unix socket listener
+    let mut listeners: Vec<ListenerFuture> = Vec::new();
+    let mut shutdown_txs: Vec<oneshot::Sender<()>> = Vec::new();
 
-    let tcp_fut = axum::Server::bind(&"0.0.0.0:9404".parse().unwrap())
-        .serve(app.into_make_service())
-        .with_graceful_shutdown(async {
-            rx.await.ok();
-        });
+    if config.enable_socket {
+        let (tx, socket_rx) = oneshot::channel::<()>();
+        match socket_listener(app.clone(), &config, socket_rx) {
+            Ok(fut) => {
+                listeners.push(fut);
+                shutdown_txs.push(tx);
+            }
+            // if one fails to start we DONT wanna die
+            Err(err) => error!("Socket listener failed to start: {}", err),
+        }
+    }
+
+    if config.enable_tcp {
+        let (tx, tcp_rx) = oneshot::channel::<()>();
+        match tcp_listener(app, &config, tcp_rx) {
+            Ok(fut) => {
+                listeners.push(fut);
+                shutdown_txs.push(tx);
+            }
+            Err(err) => error!("TCP listener failed to start: {}", err),
+        }
+    }
 
-    let tcp_handle = tokio::spawn(tcp_fut);
+    if listeners.is_empty() {
+        warn!("No API listeners running.");
+    }
 
-    // if one crashes we wanna die
-    match tokio::try_join!(flatten(tcp_handle)) {
-        Ok(val) => {
-            info!("Successful Shutdown");
+    tokio::spawn(async move {
+        let _ = rx.await;
+        for tx in shutdown_txs {
+            let _ = tx.send(());
         }
-        Err(err) => {
-            error!("Failed with {}.", err);
+    });
+
+    let mut result = Ok(());
+    for res in join_all(listeners).await {
+        if let Err(err) = res {
+            error!("Listener failed with {}.", err);
+            result = Err(err);
         }
-    };
-    Ok(())
+    }
+    if result.is_ok() {
+        info!("Successful shutdown.");
+    }
+    result
 }
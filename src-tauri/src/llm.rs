@@ -4,6 +4,7 @@ use crate::connectors::llm_manager;
 
 use crate::database;
 use crate::database_types::*;
+use crate::emitter;
 use crate::error::PantryError;
 use crate::frontend;
 use crate::state;
@@ -21,9 +22,9 @@ use serde_json::json;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::fs::File;
+use std::future::Future;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tauri::AppHandle;
 use tiny_tokio_actor::*;
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
@@ -154,6 +155,13 @@ pub struct LLM {
     //But we'll need ot eventually.
     pub session_parameters: DbHashMap, // Hardcoded Parameters
     pub user_session_parameters: DbVec<String>,
+
+    // Lowercase hex digest of model_path, recorded when the download was verified.
+    pub sha256: Option<String>,
+    // The registry entry's version at install time, see upgrade.rs.
+    pub version: Option<String>,
+    // The registry it was installed from, upgrades only come from there.
+    pub registry: Option<String>,
 }
 
 #[derive(Debug)]
@@ -222,6 +230,45 @@ pub struct LLMSession {
     pub session_parameters: DbHashMap,
 }
 
+// Narrows a history search, everything is optional.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct HistoryFilter {
+    pub user_id: Option<Uuid>,
+    // The LLM's uuid or id.
+    pub llm: Option<String>,
+    pub session_id: Option<Uuid>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    // Defaults to 50, at most 500.
+    pub limit: Option<i64>,
+}
+
+// A history item that matched a search, along with where it came from.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, QueryableByName)]
+pub struct HistorySearchHit {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub id: DbUuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub llm_session_id: DbUuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub llm_uuid: DbUuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub llm_id: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub user_id: DbUuid,
+    #[diesel(sql_type = diesel::sql_types::TimestamptzSqlite)]
+    pub call_timestamp: DateTime<Utc>,
+    #[diesel(sql_type = diesel::sql_types::Bool)]
+    pub complete: bool,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub input: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub output: String,
+    // The best matching bit of input or output, with the matches in [brackets].
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub snippet: String,
+}
+
 impl Clone for LLM {
     fn clone(&self) -> Self {
         Self {
@@ -253,6 +300,9 @@ impl Clone for LLM {
             user_parameters: self.user_parameters.clone(),
             session_parameters: self.session_parameters.clone(),
             user_session_parameters: self.user_session_parameters.clone(),
+            sha256: self.sha256.clone(),
+            version: self.version.clone(),
+            registry: self.registry.clone(),
         }
     }
 }
@@ -275,13 +325,28 @@ pub fn deserialize_llms(path: PathBuf) -> Result<Vec<LLM>, Box<dyn std::error::E
 }
 
 impl LLMActivated {
+    // The actor only answers once pre_start has loaded the model, so this resolves when the
+    // model is ready and fails if loading did. Doesn't borrow self, so callers can let go of
+    // activated_llms while waiting.
+    pub fn wait_loaded(&self) -> impl Future<Output = Result<(), String>> {
+        let actor = self.actor.clone();
+        let name = self.llm.name.clone();
+        async move {
+            actor
+                .ask(llm_actor::IDMessage())
+                .await
+                .map(|_| ())
+                .map_err(|err| format!("Failed to load {}: {:?}", name, err))
+        }
+    }
+
     pub async fn activate_llm(
         llm: LLM,
         manager_addr: ActorRef<connectors::SysEvent, llm_manager::LLMManagerActor>,
         data_path: PathBuf,
         user_settings: state::UserSettings,
         pool: Pool<ConnectionManager<SqliteConnection>>,
-        app: AppHandle,
+        emitter: emitter::SharedEmitter,
     ) -> Result<LLMActivated, PantryError> {
         match manager_addr
             .ask(llm_manager::CreateLLMActorMessage {
@@ -293,7 +358,7 @@ impl LLMActivated {
                 model_path: llm.model_path.0.clone(),
                 user_settings,
                 pool: pool.clone(),
-                app,
+                emitter,
             })
             .await
         {
@@ -45,16 +45,23 @@ mod cli;
 mod connectors;
 mod database;
 mod database_types;
+mod disk;
+mod download_manager;
 mod emitter;
 mod error;
 mod frontend;
+mod huggingface;
+mod import;
 mod listeners;
 mod llm;
 mod registry;
+mod registry_index;
 mod request;
 mod schema;
 mod server;
 mod state;
+mod transfer;
+mod upgrade;
 mod user;
 
 #[derive(Debug)]
@@ -121,6 +128,24 @@ fn run_migrations(
     Ok(())
 }
 
+// Creates the database if it doesn't exist yet and brings it up to date.
+fn open_database(db_path: &str) -> Result<Pool<ConnectionManager<SqliteConnection>>, String> {
+    if let Some(parent) = std::path::Path::new(db_path).parent() {
+        fs::create_dir_all(parent)
+            .map_err(|err| format!("Failed to create {:?}: {:?}", parent, err))?;
+    }
+    // we need to do this to ensure the database exists.
+    let _ = diesel::sqlite::SqliteConnection::establish(db_path);
+
+    let pool = get_connection_pool(db_path.to_string());
+    let mut connection = pool
+        .get()
+        .map_err(|err| format!("Failed to open {}: {:?}", db_path, err))?;
+    run_migrations(&mut connection)
+        .map_err(|err| format!("Failed to migrate {}: {:?}", db_path, err))?;
+    Ok(pool)
+}
+
 #[derive(Clone, serde::Serialize, Debug)]
 #[serde(tag = "type")]
 pub enum DeepLinkEventPayload {
@@ -135,6 +160,78 @@ pub struct DeepLinkEvent {
     pub payload: DeepLinkEventPayload,
 }
 
+async fn unload_all_llms(stat: &state::GlobalStateWrapper) {
+    let manager_addr = stat.manager_addr.clone();
+    let uuids: Vec<Uuid> = stat
+        .activated_llms
+        .iter()
+        .map(|pair| pair.key().clone())
+        .collect();
+    let mut futs = Vec::new();
+    for uuid in uuids {
+        futs.push(
+            stat.activated_llms
+                .remove(&uuid)
+                .expect("beep")
+                .1
+                .unload_llm(manager_addr.clone()),
+        );
+    }
+    join_all(futs).await;
+}
+
+async fn shutdown_signal() {
+    #[cfg(target_family = "unix")]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut term = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = term.recv() => {},
+        }
+    }
+    #[cfg(not(target_family = "unix"))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+// Runs the API server without the app, for servers and containers.
+async fn run_headless(
+    manager_addr: ActorRef<connectors::SysEvent, llm_manager::LLMManagerActor>,
+    local_path: std::path::PathBuf,
+    llm_path: std::path::PathBuf,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) {
+    // open_database already created local_path, it's where the database lives.
+    let global_state = state::create_global_state(
+        manager_addr,
+        DashMap::new(),
+        Arc::new(emitter::LogEmitter {}),
+        local_path,
+        llm_path,
+        pool,
+    );
+
+    tokio::spawn(registry::resume_downloads(global_state.clone()));
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let server = tokio::spawn(server::build_server(global_state.clone(), shutdown_rx));
+    info!("Pantry running headless. Ctrl-C to stop.");
+
+    shutdown_signal().await;
+    info!("Shutting down.");
+    let _ = shutdown_tx.send(());
+    let unloads = unload_all_llms(&global_state);
+    match server.await {
+        Ok(Err(err)) => error!("API server failure: {:?}", err),
+        Err(err) => error!("API server failure: {:?}", err),
+        Ok(Ok(_)) => info!("API server closed with okay."),
+    }
+    unloads.await;
+    info!("completed shutdown");
+}
+
 #[tokio::main]
 async fn main() {
     tauri_plugin_deep_link::prepare("com.jmerz.pantry");
@@ -146,6 +243,74 @@ async fn main() {
 
     // let _tray_menu = SystemTrayMenu::new().add_item(CustomMenuItem::new("toggle", "Toggle"));
 
+    let context = tauri::generate_context!();
+
+    let mut db_path = tauri::api::path::app_local_data_dir(context.config()).unwrap();
+    let mut llm_path = tauri::api::path::local_data_dir().unwrap();
+    llm_path.push("pantry");
+
+    if !llm_path.exists() {
+        fs::create_dir_all(&llm_path).unwrap();
+    }
+
+    let config = context.config().clone();
+
+    db_path.push("local2.sqlite");
+    let db_path = db_path.into_os_string().into_string().unwrap();
+
+    let args: Vec<String> = env::args().collect();
+    // Deep links start the app with the url as its argument, that's not a CLI command.
+    let deep_link = args.iter().skip(1).any(|arg| arg.starts_with("pantry://"));
+
+    // CLI commands run as a plain client of the daemon: no app, actors or webview.
+    // `serve` is the daemon itself, so it carries on below.
+    let mut headless = false;
+    if let Some(cli_config) = &config.tauri.cli {
+        match tauri::api::cli::get_matches(cli_config, context.package_info()) {
+            Ok(matches) => {
+                for name in ["help", "version"] {
+                    if let Some(text) = matches.args.get(name) {
+                        println!("{}", text.value.as_str().unwrap_or(""));
+                        return;
+                    }
+                }
+                if let Some(subcommand) = &matches.subcommand {
+                    if subcommand.name != "serve" {
+                        if let Err(e) =
+                            cli::cli_command_interpreter(&config, matches, &db_path).await
+                        {
+                            eprintln!("{}", e.message);
+                            std::process::exit(e.code);
+                        }
+                        return;
+                    }
+                    let serve_args = &subcommand.matches.args;
+                    headless = matches!(
+                        serve_args.get("headless").map(|arg| &arg.value),
+                        Some(serde_json::Value::Bool(true))
+                    );
+                }
+            }
+            Err(tauri::api::Error::ParseCliArguments(_)) if deep_link => {}
+            Err(tauri::api::Error::ParseCliArguments(s)) => {
+                eprintln!("{}", s);
+                std::process::exit(cli::EXIT_USAGE);
+            }
+            Err(e) => {
+                error!("Other API Error: {:?}", e);
+            }
+        }
+    }
+
+    // Only the daemon and the app get here, CLI commands open the database if they need it.
+    let pool = match open_database(&db_path) {
+        Ok(pool) => pool,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
     let bus = EventBus::<connectors::SysEvent>::new(1000);
 
     let system = ActorSystem::new("pantry", bus);
@@ -168,32 +333,13 @@ async fn main() {
         }
     });
 
-    let context = tauri::generate_context!();
-
-    let mut db_path = tauri::api::path::app_local_data_dir(context.config()).unwrap();
-    let mut llm_path = tauri::api::path::local_data_dir().unwrap();
-    llm_path.push("pantry");
-
-    if !llm_path.exists() {
-        fs::create_dir_all(&llm_path).unwrap();
-    }
-
-    if !db_path.exists() {
-        fs::create_dir_all(&llm_path).unwrap();
+    // `pantry serve --headless` never touches tauri: no webview, tray, or deep links.
+    if headless {
+        let local_path = tauri::api::path::app_local_data_dir(context.config()).unwrap();
+        run_headless(manager_addr, local_path, llm_path, pool).await;
+        return;
     }
 
-    let config = context.config().clone();
-
-    db_path.push("local2.sqlite");
-
-    let _ = diesel::sqlite::SqliteConnection::establish(
-        &db_path.clone().into_os_string().into_string().unwrap(),
-    );
-    // we need to do this to ensure the database exists.
-
-    let pool = get_connection_pool(db_path.into_os_string().into_string().unwrap());
-    run_migrations(&mut pool.get().unwrap()).unwrap();
-
     //channels for shutting down the web servers
     let (server_shutdown_tx, server_shutdown_rx) = oneshot::channel();
     let server_shutdown_tx = Arc::new(Mutex::new(Some(server_shutdown_tx)));
@@ -203,34 +349,7 @@ async fn main() {
     let server_shutdown_confirm_rx1 = server_shutdown_confirm_rx.clone();
     // let server_shutdown_confirm_tx = Option(server_shutdown_confirm_tx)
 
-    // Run early CLI commands—any that need to run before we shut down from dupes.
-
     let builder = tauri::Builder::default().setup(move |app| {
-        // TODO: break this out
-        //CLI Then kill
-        match app.get_cli_matches() {
-            Ok(matches) => {
-                tokio::task::block_in_place(|| {
-                    tauri::async_runtime::block_on(cli::cli_command_interpreter(
-                        app.handle(),
-                        matches,
-                        pool.clone(),
-                    ))
-                });
-            }
-            Err(tauri::Error::FailedToExecuteApi(e)) => match e {
-                tauri::api::Error::ParseCliArguments(s) => {
-                    println!("{}", s);
-                }
-                e => {
-                    error!("Other API Error: {:?}", e);
-                }
-            },
-
-            Err(e) => {
-                info!("No matches: {:?}", e);
-            }
-        };
         app.handle()
             .plugin(tauri_plugin_single_instance::init(|app, argv, cwd| {
                 debug!("{}, {argv:?}, {cwd}", app.package_info().name);
@@ -287,13 +406,14 @@ async fn main() {
         let global_state = state::create_global_state(
             manager_addr,
             DashMap::new(),
-            app.handle(),
+            Arc::new(emitter::TauriEmitter { app: app.handle() }),
             tauri::api::path::app_local_data_dir(&config).unwrap(),
             llm_path,
             pool,
         );
 
         app.manage(global_state.clone());
+        tokio::spawn(registry::resume_downloads(global_state.clone()));
         tokio::spawn(async move {
             server_shutdown_confirm_tx.send(
                 match server::build_server(global_state, server_shutdown_rx).await {
@@ -335,26 +455,10 @@ async fn main() {
         confirm_rx: oneshot::Receiver<Result<(), String>>,
     ) {
         let stat: tauri::State<state::GlobalStateWrapper> = app.state();
-        let manager_addr = stat.manager_addr.clone();
         shutdown_tx.send(());
-        let uuids: Vec<Uuid> = stat
-            .activated_llms
-            .iter()
-            .map(|pair| pair.key().clone())
-            .collect();
-        let mut futs = Vec::new();
-        for uuid in uuids {
-            futs.push(
-                stat.activated_llms
-                    .remove(&uuid)
-                    .expect("beep")
-                    .1
-                    .unload_llm(manager_addr.clone()),
-            );
-        }
-        let _borrow_man = manager_addr.clone();
+        let unloads = unload_all_llms(&stat);
         confirm_rx.await;
-        join_all(futs).await;
+        unloads.await;
         info!("completed shutdown");
         std::process::exit(0);
     }
@@ -402,11 +506,22 @@ async fn main() {
             frontend::delete_llm,
             frontend::set_user_setting,
             frontend::get_user_settings,
+            frontend::restart_listeners,
             frontend::interrupt_session,
             frontend::accept_request,
             frontend::reject_request,
             frontend::exec_path,
             frontend::new_cli_user,
+            frontend::get_users,
+            frontend::revoke_api_key,
+            frontend::delete_user,
+            frontend::list_registries,
+            frontend::add_registry,
+            frontend::remove_registry,
+            frontend::import_llm,
+            frontend::list_quantizations,
+            frontend::list_upgrades,
+            frontend::upgrade_llm,
         ]);
 
     // build_server()
@@ -1,19 +1,32 @@
 use crate::connectors;
 use crate::database;
 use crate::database_types::*;
+use crate::download_manager;
 use crate::emitter;
+use crate::huggingface;
 use crate::llm;
+use crate::registry_index;
 use crate::state;
+use crate::upgrade;
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
+use diesel::deserialize::FromSql;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::*;
 use futures_util::StreamExt;
-use log::info;
+use log::{error, info, warn};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use tauri::Manager;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
 use uuid::Uuid;
 
@@ -35,8 +48,9 @@ impl FromStr for LLMRegistryEntryInstallStep {
     }
 }
 
-//We don't store these locally.
-#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+//We don't store these locally, except as part of requests and downloads.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, FromSqlRow, AsExpression)]
+#[diesel(sql_type = diesel::sql_types::Text)]
 #[serde(rename_all = "camelCase")]
 pub struct LLMRegistryEntry {
     pub id: String,
@@ -52,7 +66,10 @@ pub struct LLMRegistryEntry {
     pub tags: Vec<String>,
     pub requirements: String,
 
+    #[serde(default)]
     pub backend_uuid: String,
+    // Empty for huggingface entries until they are resolved.
+    #[serde(default)]
     pub url: String,
 
     pub config: HashMap<String, Value>,
@@ -64,90 +81,823 @@ pub struct LLMRegistryEntry {
 
     pub session_parameters: HashMap<String, Value>,
     pub user_session_parameters: Vec<String>,
+
+    // Optional integrity info, checked once the download finishes.
+    #[serde(default)]
+    pub sha256: Option<String>,
+    #[serde(default)]
+    pub size: Option<u64>,
+
+    // Resolved into url, size and sha256 right before downloading.
+    #[serde(default)]
+    pub huggingface: Option<huggingface::HuggingFaceSource>,
+
+    // Bumped by the registry when it publishes a new file for the same id.
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+impl FromSql<diesel::sql_types::Text, Sqlite> for LLMRegistryEntry {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> diesel::deserialize::Result<Self> {
+        let str = <String as FromSql<diesel::sql_types::Text, Sqlite>>::from_sql(bytes)?;
+        let value: LLMRegistryEntry = serde_json::from_str(&str)?;
+        Ok(value)
+    }
+}
+
+impl ToSql<diesel::sql_types::Text, Sqlite> for LLMRegistryEntry {
+    fn to_sql<'W>(&'W self, out: &mut Output<'W, '_, Sqlite>) -> serialize::Result {
+        let str = serde_json::to_string(self)?;
+        out.set_value(str);
+        Ok(serialize::IsNull::No)
+    }
+}
+
+// An llmrs entry with only the required fields, for tests to adjust.
+#[cfg(test)]
+pub fn test_entry(id: &str, url: &str) -> LLMRegistryEntry {
+    serde_json::from_value(serde_json::json!({
+        "id": id,
+        "familyId": "llama",
+        "organization": "meta",
+        "name": "Llama",
+        "license": "",
+        "description": "",
+        "homepage": "",
+        "capabilities": {},
+        "tags": [],
+        "requirements": "",
+        "url": url,
+        "config": {"model_architecture": "llama"},
+        "local": true,
+        "connectorType": "llmrs",
+        "parameters": {},
+        "userParameters": [],
+        "sessionParameters": {},
+        "userSessionParameters": [],
+    }))
+    .unwrap()
 }
 
 pub struct DownloadingLLM {
     pub llm_reg: LLMRegistryEntry,
     pub progress: f32,
+    pub bytes_per_second: u64,
+    pub eta_seconds: Option<u64>,
     pub uuid: Uuid,
+    // Stops the running download. Whether that's a pause or a cancel is
+    // decided by the status written to the download row beforehand.
+    pub cancel: CancellationToken,
+}
+
+// The persisted side of a download. The row lives until the LLM is saved, so
+// anything left over at startup gets resumed from its .part file.
+#[derive(Debug, Clone, Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::download)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Download {
+    pub id: DbUuid,
+    pub llm_registry_entry: LLMRegistryEntry,
+    pub part_path: String,
+    pub downloaded_bytes: i64,
+    pub total_bytes: Option<i64>,
+    pub status: String,
+    pub started: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    // Set when this download replaces an installed LLM.
+    pub upgrade_of: Option<String>,
+    pub keep_old: bool,
+}
+
+// What /list_downloads hands out.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DownloadInfo {
+    pub id: String,
+    pub llm_id: String,
+    pub name: String,
+    pub status: String,
+    pub downloaded_bytes: i64,
+    pub total_bytes: Option<i64>,
+    pub progress: Option<f32>,
+    // Only known while the download is running.
+    pub bytes_per_second: Option<u64>,
+    pub eta_seconds: Option<u64>,
+    pub started: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+}
+
+impl From<&Download> for DownloadInfo {
+    fn from(download: &Download) -> Self {
+        DownloadInfo {
+            id: download.id.0.to_string(),
+            llm_id: download.llm_registry_entry.id.clone(),
+            name: download.llm_registry_entry.name.clone(),
+            status: download.status.clone(),
+            downloaded_bytes: download.downloaded_bytes,
+            total_bytes: download.total_bytes,
+            progress: download
+                .total_bytes
+                .filter(|total| *total > 0)
+                .map(|total| download.downloaded_bytes as f32 / total as f32 * 100.0),
+            bytes_per_second: None,
+            eta_seconds: None,
+            started: download.started,
+            updated: download.updated,
+        }
+    }
 }
 
+const DOWNLOAD_RETRIES: u32 = 8;
+const MAX_BACKOFF_SECS: u64 = 120;
+
+type DownloadResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
 pub async fn download_and_write_llm(
     llm_reg: LLMRegistryEntry,
     uuid: Uuid,
-    app: tauri::AppHandle,
+    state: state::GlobalStateWrapper,
+) -> Result<(), Box<dyn std::error::Error>> {
+    start_download(llm_reg, uuid, None, state).await
+}
+
+// Same as download_and_write_llm, with upgrade set the new LLM replaces an installed one
+// once it's saved.
+pub async fn start_download(
+    llm_reg: LLMRegistryEntry,
+    uuid: Uuid,
+    upgrade: Option<upgrade::Upgrade>,
+    state: state::GlobalStateWrapper,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let state: tauri::State<'_, state::GlobalStateWrapper> = app.state();
     let stream_id = format!("{}-{}", llm_reg.id, uuid.to_string());
+    let endpoint = state
+        .user_settings
+        .read()
+        .unwrap()
+        .huggingface_endpoint
+        .clone();
+    let resolved = match check_local_source(&llm_reg, &state) {
+        Ok(()) => huggingface::resolve(llm_reg, &endpoint).await,
+        Err(message) => Err(message),
+    };
+    let llm_reg = match resolved {
+        Ok(llm_reg) => llm_reg,
+        Err(message) => {
+            error!("Couldn't resolve download: {}", message);
+            state.emitter.emit(
+                "downloads",
+                &emitter::EmitterEvent {
+                    stream_id: stream_id.clone(),
+                    event: emitter::EmitterEventPayload::DownloadError {
+                        message: message.clone(),
+                    },
+                },
+            )?;
+            state.emitter.emit(
+                "downloads",
+                &emitter::EmitterEvent {
+                    stream_id: stream_id.clone(),
+                    event: emitter::EmitterEventPayload::ChannelClose {},
+                },
+            )?;
+            return Err(message.into());
+        }
+    };
     if state.user_settings.read().unwrap().dedup_downloads {
         if let Ok(llm) = database::get_llm_by_url(llm_reg.url.clone(), state.pool.clone()) {
-            return save_new_llm(uuid, llm.model_path.0.unwrap(), stream_id, llm_reg, app);
+            save_new_llm(
+                uuid,
+                llm.model_path.0.unwrap(),
+                llm.sha256,
+                stream_id,
+                llm_reg,
+                state.clone(),
+            )?;
+            upgrade::finish_upgrade(upgrade, uuid, &state);
+            return Ok(());
+        }
+    }
+
+    let mut part_path = state.llm_path.clone();
+    part_path.push(format!("{}-{}.part", llm_reg.id, uuid.to_string()));
+
+    let download = database::save_new_download(
+        Download {
+            id: DbUuid(uuid),
+            llm_registry_entry: llm_reg,
+            part_path: part_path.to_string_lossy().into(),
+            downloaded_bytes: 0,
+            total_bytes: None,
+            status: "downloading".into(),
+            started: Utc::now(),
+            updated: Utc::now(),
+            upgrade_of: upgrade.as_ref().map(|upgrade| upgrade.of.to_string()),
+            keep_old: upgrade.as_ref().map_or(false, |upgrade| upgrade.keep_old),
+        },
+        state.pool.clone(),
+    )?;
+
+    let cancel = register_download(&download, &state)?;
+    run_download(download, cancel, state).await
+}
+
+// file:// urls are read straight off the disk, so they're only honoured for entries a local
+// registry lists with that url, not for whatever a request body says. Hugging Face entries
+// get their url from the configured endpoint when they're resolved.
+fn check_local_source(
+    llm_reg: &LLMRegistryEntry,
+    state: &state::GlobalStateWrapper,
+) -> Result<(), String> {
+    if llm_reg.huggingface.is_some() || !llm_reg.url.starts_with("file://") {
+        return Ok(());
+    }
+    let registries = database::get_registries(state.pool.clone())
+        .map_err(|err| format!("Failed to load registries: {:?}", err))?;
+    let listed = registries
+        .iter()
+        .filter(|reg| !is_remote(&reg.location))
+        .any(|reg| {
+            reg.models()
+                .get(&llm_reg.id)
+                .and_then(|entry| entry.get("url"))
+                .and_then(|url| url.as_str())
+                == Some(llm_reg.url.as_str())
+        });
+    match listed {
+        true => Ok(()),
+        false => Err(format!(
+            "{} points at a local file, which only entries of local registries may do",
+            llm_reg.id
+        )),
+    }
+}
+
+// Picks up whatever was still downloading when we last shut down.
+pub async fn resume_downloads(state: state::GlobalStateWrapper) {
+    let downloads = match database::get_downloads(state.pool.clone()) {
+        Ok(downloads) => downloads,
+        Err(err) => {
+            error!("Failed to load pending downloads: {:?}", err);
+            return;
+        }
+    };
+    for download in downloads {
+        match download.status.as_str() {
+            "downloading" | "queued" | "failed" => {}
+            // Cancelled while running, but we shut down before it could clean up.
+            "cancelled" => {
+                let _ = std::fs::remove_file(&download.part_path);
+                if let Err(err) = database::delete_download(download.id.0, state.pool.clone()) {
+                    error!("Failed to remove cancelled download: {:?}", err);
+                }
+                continue;
+            }
+            // Paused downloads wait for an explicit resume.
+            _ => continue,
+        }
+        let cancel = match register_download(&download, &state) {
+            Ok(cancel) => cancel,
+            Err(err) => {
+                warn!("Not resuming: {}", err);
+                continue;
+            }
+        };
+        info!(
+            "Resuming download of {} at {} bytes",
+            download.llm_registry_entry.id, download.downloaded_bytes
+        );
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(err) = run_download(download, cancel, state).await {
+                error!("Resumed download failed: {:?}", err);
+            }
+        });
+    }
+}
+
+// Claims the download in downloading_llms, so only one task ever runs it. Done before the task
+// is spawned, checking and inserting separately would let two resumes both get through.
+fn register_download(
+    download: &Download,
+    state: &state::GlobalStateWrapper,
+) -> Result<CancellationToken, String> {
+    let uuid = download.id.0.clone();
+    match state.downloading_llms.entry(uuid) {
+        dashmap::mapref::entry::Entry::Occupied(_) => {
+            Err(format!("Download {} is already running", uuid))
+        }
+        dashmap::mapref::entry::Entry::Vacant(entry) => {
+            let cancel = CancellationToken::new();
+            entry.insert(DownloadingLLM {
+                llm_reg: download.llm_registry_entry.clone(),
+                progress: download
+                    .total_bytes
+                    .map(|total| download.downloaded_bytes as f32 / total as f32 * 100.0)
+                    .unwrap_or(0.0),
+                bytes_per_second: 0,
+                eta_seconds: None,
+                uuid: uuid.clone(),
+                cancel: cancel.clone(),
+            });
+            Ok(cancel)
         }
     }
+}
+
+// Expects the download to be registered already, see register_download.
+async fn run_download(
+    download: Download,
+    cancel: CancellationToken,
+    state: state::GlobalStateWrapper,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let uuid = download.id.0.clone();
+    let llm_reg = download.llm_registry_entry.clone();
+    let stream_id = format!("{}-{}", llm_reg.id, uuid.to_string());
 
     // This ensures that we cleanup the downloading in case of a crash.
-    struct Cleanup<'a> {
+    struct Cleanup {
         llm: Uuid,
-        state: tauri::State<'a, state::GlobalStateWrapper>,
+        state: state::GlobalStateWrapper,
     }
-    impl<'a> Drop for Cleanup<'a> {
+    impl Drop for Cleanup {
         fn drop(&mut self) {
             self.state.downloading_llms.remove(&self.llm);
         }
     }
-    // Thanks typechecker
-    let app2 = app.clone();
     let _cleaner = Cleanup {
         llm: uuid.clone(),
-        state: app2.state(),
+        state: state.clone(),
     };
 
-    state.downloading_llms.insert(
-        uuid.clone(),
-        DownloadingLLM {
-            llm_reg: llm_reg.clone(),
-            progress: 0.0,
-            uuid: uuid.clone(),
-        },
-    );
+    database::set_download_status(uuid, "queued", state.pool.clone())?;
+    let _slot = tokio::select! {
+        slot = wait_in_queue(&download, &stream_id, &state) => slot?,
+        _ = cancel.cancelled() => {
+            let part_path = PathBuf::from(&download.part_path);
+            return stop_download(uuid, &part_path, &stream_id, &state);
+        }
+    };
+    database::set_download_status(uuid, "downloading", state.pool.clone())?;
 
-    // Create the request client.
-    let client = reqwest::Client::new();
+    let part_path = PathBuf::from(&download.part_path);
+    let mut attempt: u32 = 0;
+    let digest = loop {
+        let result = tokio::select! {
+            result = fetch_to_part(&download, &part_path, &stream_id, &state) => {
+                result.map_err(|err| err.to_string())
+            }
+            _ = cancel.cancelled() => {
+                return stop_download(uuid, &part_path, &stream_id, &state);
+            }
+        };
+        match result {
+            Ok(digest) => break digest,
+            Err(message) => {
+                attempt += 1;
+                if attempt >= DOWNLOAD_RETRIES {
+                    error!(
+                        "Giving up on {} after {} attempts: {}",
+                        llm_reg.id, attempt, message
+                    );
+                    // We keep the row and the .part file, the next start will try again.
+                    database::set_download_status(uuid, "failed", state.pool.clone())?;
+                    state.emitter.emit(
+                        "downloads",
+                        &emitter::EmitterEvent {
+                            stream_id: stream_id.clone(),
+                            event: emitter::EmitterEventPayload::DownloadError { message },
+                        },
+                    )?;
+                    state.emitter.emit(
+                        "downloads",
+                        &emitter::EmitterEvent {
+                            stream_id: stream_id.clone(),
+                            event: emitter::EmitterEventPayload::ChannelClose {},
+                        },
+                    )?;
+                    return Ok(());
+                }
+                let backoff = std::cmp::min(2u64.pow(attempt), MAX_BACKOFF_SECS);
+                warn!(
+                    "Download of {} failed ({}), retrying in {}s",
+                    llm_reg.id, message, backoff
+                );
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(backoff)) => {}
+                    _ = cancel.cancelled() => {
+                        return stop_download(uuid, &part_path, &stream_id, &state);
+                    }
+                }
+            }
+        }
+    };
 
-    let state: tauri::State<'_, state::GlobalStateWrapper> = app.state();
+    if let Err(message) = verify_part(&llm_reg, &part_path, &digest) {
+        error!("Rejecting download of {}: {}", llm_reg.id, message);
+        // A bad file can't be resumed into a good one, so throw it all away.
+        let _ = std::fs::remove_file(&part_path);
+        database::delete_download(uuid, state.pool.clone())?;
+        state.emitter.emit(
+            "downloads",
+            &emitter::EmitterEvent {
+                stream_id: stream_id.clone(),
+                event: emitter::EmitterEventPayload::DownloadError {
+                    message: message.clone(),
+                },
+            },
+        )?;
+        state.emitter.emit(
+            "downloads",
+            &emitter::EmitterEvent {
+                stream_id: stream_id.clone(),
+                event: emitter::EmitterEventPayload::ChannelClose {},
+            },
+        )?;
+        return Err(message.into());
+    }
 
+    // Only complete files lose the .part suffix, so a partial model never gets registered.
     let mut path = state.llm_path.clone();
-
-    // let mut path = app
-    //     .path_resolver()
-    //     .local_data_dir()
-    //     .ok_or(format!("failed to find local data"))?;
     path.push(format!("{}-{}", llm_reg.id, uuid.to_string()));
+    std::fs::rename(&part_path, &path)?;
+    database::delete_download(uuid, state.pool.clone())?;
+
+    save_new_llm(uuid, path, Some(digest), stream_id, llm_reg, state.clone())?;
+    upgrade::finish_upgrade(upgrade::Upgrade::from_download(&download), uuid, &state);
+    Ok(())
+}
+
+// Keeps the UI posted while the download waits for a free slot.
+async fn wait_in_queue<'a>(
+    download: &Download,
+    stream_id: &String,
+    state: &'a state::GlobalStateWrapper,
+) -> Result<download_manager::DownloadSlot<'a>, Box<dyn std::error::Error>> {
+    let uuid = download.id.0;
+    let turn = download_manager::wait_turn(uuid, state);
+    tokio::pin!(turn);
+    loop {
+        tokio::select! {
+            slot = &mut turn => return Ok(slot),
+            _ = tokio::time::sleep(download_manager::REPORT_INTERVAL) => {}
+        }
+        if !state.download_manager.is_queued(uuid) {
+            continue;
+        }
+        let progress = state
+            .downloading_llms
+            .get(&uuid)
+            .map(|dl| dl.progress)
+            .unwrap_or(0.0);
+        state.emitter.emit(
+            "downloads",
+            &emitter::EmitterEvent {
+                stream_id: stream_id.clone(),
+                event: emitter::EmitterEventPayload::DownloadProgress {
+                    progress: progress.to_string(),
+                    bytes_per_second: 0,
+                    eta_seconds: None,
+                    queued: true,
+                },
+            },
+        )?;
+    }
+}
+
+// Runs once the token fires. Paused downloads keep their row and .part so they can
+// be resumed, anything else is a cancel and gets cleaned up.
+fn stop_download(
+    uuid: Uuid,
+    part_path: &PathBuf,
+    stream_id: &String,
+    state: &state::GlobalStateWrapper,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let paused = database::get_download(uuid, state.pool.clone())
+        .map(|download| download.status == "paused")
+        .unwrap_or(false);
+    if paused {
+        info!("Paused download {}", stream_id);
+    } else {
+        info!("Cancelled download {}", stream_id);
+        let _ = std::fs::remove_file(part_path);
+        database::delete_download(uuid, state.pool.clone())?;
+        state.emitter.emit(
+            "downloads",
+            &emitter::EmitterEvent {
+                stream_id: stream_id.clone(),
+                event: emitter::EmitterEventPayload::DownloadError {
+                    message: "download cancelled".into(),
+                },
+            },
+        )?;
+    }
+    state.emitter.emit(
+        "downloads",
+        &emitter::EmitterEvent {
+            stream_id: stream_id.clone(),
+            event: emitter::EmitterEventPayload::ChannelClose {},
+        },
+    )?;
+    Ok(())
+}
 
-    // Create the file to write into.
-    let mut file = File::create(&path)?;
+pub fn list_downloads(state: &state::GlobalStateWrapper) -> Result<Vec<DownloadInfo>, String> {
+    let downloads = database::get_downloads(state.pool.clone())
+        .map_err(|err| format!("Failed to load downloads: {:?}", err))?;
+    Ok(downloads
+        .iter()
+        .map(|download| {
+            let mut info: DownloadInfo = download.into();
+            if let Some(running) = state.downloading_llms.get(&download.id.0) {
+                info.bytes_per_second = Some(running.bytes_per_second);
+                info.eta_seconds = running.eta_seconds;
+            }
+            info
+        })
+        .collect())
+}
 
-    // Create the request.
-    let response = client.get(llm_reg.url.clone()).send().await?;
+pub fn pause_download(
+    uuid: Uuid,
+    state: &state::GlobalStateWrapper,
+) -> Result<DownloadInfo, String> {
+    let download = database::get_download(uuid, state.pool.clone())
+        .map_err(|_err| format!("No download with id {}", uuid))?;
+    let running = state
+        .downloading_llms
+        .get(&uuid)
+        .map(|dl| dl.cancel.clone());
+    let cancel = running.ok_or(format!("Download {} is not running", uuid))?;
+    database::set_download_status(uuid, "paused", state.pool.clone())
+        .map_err(|err| format!("Failed to pause download: {:?}", err))?;
+    cancel.cancel();
+    Ok(DownloadInfo {
+        status: "paused".into(),
+        ..(&download).into()
+    })
+}
 
-    // Get the total size if available.
-    let total_size_opt = response.content_length();
+pub fn resume_download(
+    uuid: Uuid,
+    state: &state::GlobalStateWrapper,
+) -> Result<DownloadInfo, String> {
+    let download = database::get_download(uuid, state.pool.clone())
+        .map_err(|_err| format!("No download with id {}", uuid))?;
+    let cancel = register_download(&download, state)?;
+    let info = DownloadInfo {
+        status: "downloading".into(),
+        ..(&download).into()
+    };
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(err) = run_download(download, cancel, state).await {
+            error!("Resumed download failed: {:?}", err);
+        }
+    });
+    Ok(info)
+}
 
-    let mut downloaded: u64 = 0;
+// Running downloads get stopped and clean up after themselves, stopped ones we clean up here.
+pub fn cancel_download(
+    uuid: Uuid,
+    state: &state::GlobalStateWrapper,
+) -> Result<DownloadInfo, String> {
+    let download = database::get_download(uuid, state.pool.clone())
+        .map_err(|_err| format!("No download with id {}", uuid))?;
+    database::set_download_status(uuid, "cancelled", state.pool.clone())
+        .map_err(|err| format!("Failed to cancel download: {:?}", err))?;
+    let running = state
+        .downloading_llms
+        .get(&uuid)
+        .map(|dl| dl.cancel.clone());
+    match running {
+        Some(cancel) => cancel.cancel(),
+        None => {
+            let _ = std::fs::remove_file(&download.part_path);
+            database::delete_download(uuid, state.pool.clone())
+                .map_err(|err| format!("Failed to remove download: {:?}", err))?;
+        }
+    }
+    Ok(DownloadInfo {
+        status: "cancelled".into(),
+        ..(&download).into()
+    })
+}
+
+// Checks a finished .part against the registry's size and sha256, when given.
+fn verify_part(
+    llm_reg: &LLMRegistryEntry,
+    part_path: &PathBuf,
+    digest: &String,
+) -> Result<(), String> {
+    if let Some(size) = llm_reg.size {
+        let actual = std::fs::metadata(part_path)
+            .map_err(|err| format!("failed to stat download: {:?}", err))?
+            .len();
+        if actual != size {
+            return Err(format!(
+                "size mismatch: expected {} bytes, got {}",
+                size, actual
+            ));
+        }
+    }
+    if let Some(expected) = &llm_reg.sha256 {
+        if !expected.eq_ignore_ascii_case(digest) {
+            return Err(format!(
+                "sha256 mismatch: expected {}, got {}",
+                expected, digest
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Lowercase hex sha256 of a file.
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    Ok(format!("{:x}", seed_hasher(path)?.finalize()))
+}
+
+// Starts a hasher off with whatever bytes a previous attempt already wrote.
+// Read in chunks so large models don't sit in memory.
+fn seed_hasher(path: &Path) -> std::io::Result<Sha256> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher)
+}
+
+// Hashing and copying read through whole model files, which would stall the runtime.
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> std::io::Result<T> + Send + 'static,
+) -> DownloadResult<T> {
+    Ok(tokio::task::spawn_blocking(f).await??)
+}
+
+// What a response to our (possibly ranged) request means for the .part file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PartResponse {
+    // 416 to our Range, and the file is exactly as long as what we have.
+    Complete,
+    // 206 starting where the .part ends, appended to what's there. total comes from Content-Range.
+    Resumed { total: Option<u64> },
+    // Servers that ignore Range send the whole thing, so start over.
+    Restarted { total: Option<u64> },
+}
+
+// Content-Range is "bytes 100-999/1000" on a 206 and "bytes */1000" on a 416, the total may be *.
+// Returns the range's start, if any, and the total.
+fn parse_content_range(value: &str) -> (Option<u64>, Option<u64>) {
+    let (range, total) = match value
+        .trim()
+        .strip_prefix("bytes ")
+        .and_then(|v| v.split_once('/'))
+    {
+        Some(parts) => parts,
+        None => return (None, None),
+    };
+    let start = range
+        .split_once('-')
+        .and_then(|(start, _end)| start.trim().parse::<u64>().ok());
+    (start, total.trim().parse::<u64>().ok())
+}
+
+fn part_response(
+    existing: u64,
+    status: reqwest::StatusCode,
+    content_range: Option<&str>,
+    content_length: Option<u64>,
+) -> PartResponse {
+    let (start, total) = content_range
+        .map(parse_content_range)
+        .unwrap_or((None, None));
+    if existing > 0
+        && status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE
+        && total == Some(existing)
+    {
+        return PartResponse::Complete;
+    }
+    if status == reqwest::StatusCode::PARTIAL_CONTENT {
+        // A range starting anywhere else doesn't line up with the .part.
+        return match start == Some(existing) {
+            true => PartResponse::Resumed { total },
+            false => PartResponse::Restarted { total },
+        };
+    }
+    PartResponse::Restarted {
+        total: content_length,
+    }
+}
+
+// One attempt at fetching the rest of the file, appending to whatever the .part has.
+// Returns the sha256 of the whole .part, hashed as the bytes come in.
+async fn fetch_to_part(
+    download: &Download,
+    part_path: &PathBuf,
+    stream_id: &String,
+    state: &state::GlobalStateWrapper,
+) -> DownloadResult<String> {
+    let uuid = download.id.0.clone();
+    let llm_reg = &download.llm_registry_entry;
+    let existing = std::fs::metadata(part_path).map(|m| m.len()).unwrap_or(0);
+
+    // Local mirrors, see huggingface.rs.
+    if let Some(source) = llm_reg.url.strip_prefix("file://") {
+        return copy_to_part(download, Path::new(source), part_path, existing, state).await;
+    }
+
+    // Create the request client.
+    let client = reqwest::Client::new();
+    let mut request = client.get(llm_reg.url.clone());
+    if existing > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", existing));
+    }
+    let response = request.send().await?;
+
+    let outcome = part_response(
+        existing,
+        response.status(),
+        response
+            .headers()
+            .get(reqwest::header::CONTENT_RANGE)
+            .and_then(|val| val.to_str().ok()),
+        response.content_length(),
+    );
+    let (resumed, total_size_opt) = match outcome {
+        PartResponse::Complete => {
+            let part_path = part_path.clone();
+            return blocking(move || hash_file(&part_path)).await;
+        }
+        PartResponse::Resumed { total } => (true, total),
+        // A 206 at the wrong offset or a 416 for a size that isn't ours, the .part can't be
+        // trusted. Drop it so the next attempt fetches the whole file.
+        PartResponse::Restarted { .. }
+            if existing > 0
+                && matches!(
+                    response.status(),
+                    reqwest::StatusCode::PARTIAL_CONTENT
+                        | reqwest::StatusCode::RANGE_NOT_SATISFIABLE
+                ) =>
+        {
+            File::create(part_path)?;
+            return Err(
+                format!("server couldn't resume at byte {}, starting over", existing).into(),
+            );
+        }
+        PartResponse::Restarted { total } => (false, total),
+    };
+    let response = response.error_for_status()?;
+
+    let (mut file, mut downloaded, mut hasher) = if resumed {
+        let seed_path = part_path.clone();
+        (
+            OpenOptions::new().append(true).open(part_path)?,
+            existing,
+            blocking(move || seed_hasher(&seed_path)).await?,
+        )
+    } else {
+        (File::create(part_path)?, 0, Sha256::new())
+    };
+    database::update_download_progress(
+        uuid,
+        downloaded as i64,
+        total_size_opt.map(|size| size as i64),
+        state.pool.clone(),
+    )?;
 
     let mut stream = response.bytes_stream();
 
-    //TODO: download progress for specific downloads
-    let mut update_counter = 0;
+    let mut rate = download_manager::RateMeter::new(downloaded);
     while let Some(item) = stream.next().await {
         let chunk = item?;
         file.write_all(&chunk)?;
+        hasher.update(&chunk);
         downloaded += chunk.len() as u64;
-        update_counter += 1;
-        if update_counter % 1000 != 1 {
-            continue;
+        download_manager::throttle(chunk.len(), state).await;
+        let bytes_per_second = match rate.tick(downloaded) {
+            Some(bytes_per_second) => bytes_per_second,
+            None => continue,
+        };
+        let eta_seconds =
+            download_manager::eta_seconds(downloaded, total_size_opt, bytes_per_second);
+        if let Some(mut download_status) = state.downloading_llms.get_mut(&uuid) {
+            download_status.bytes_per_second = bytes_per_second;
+            download_status.eta_seconds = eta_seconds;
         }
 
+        file.flush()?;
+        database::update_download_progress(
+            uuid,
+            downloaded as i64,
+            total_size_opt.map(|size| size as i64),
+            state.pool.clone(),
+        )?;
+
         // If the total size of the object is known, calculate the percentage.
         if let Some(total_size) = total_size_opt {
             let percent = (downloaded as f32 / total_size as f32) * 100.0;
@@ -155,41 +905,114 @@ pub async fn download_and_write_llm(
                 download_status.progress = percent.clone();
             }
             info!("Downloading {} at {}", llm_reg.id, percent);
-            app.emit_all(
+            state.emitter.emit(
                 "downloads",
-                emitter::EmitterEvent {
+                &emitter::EmitterEvent {
                     stream_id: stream_id.clone(),
                     event: emitter::EmitterEventPayload::DownloadProgress {
                         progress: percent.to_string(),
+                        bytes_per_second,
+                        eta_seconds,
+                        queued: false,
                     },
                 },
             )?;
         } else {
             info!("Downloading {} at {}", llm_reg.id, downloaded);
             // otherwise, just emit the downloaded amount.
-            app.emit_all(
+            state.emitter.emit(
                 "downloads",
-                emitter::EmitterEvent {
+                &emitter::EmitterEvent {
                     stream_id: stream_id.clone(),
                     event: emitter::EmitterEventPayload::DownloadProgress {
                         progress: downloaded.to_string(),
+                        bytes_per_second,
+                        eta_seconds,
+                        queued: false,
                     },
                 },
             )?;
         }
     }
-    save_new_llm(uuid, path, stream_id, llm_reg, app)
+    file.flush()?;
+    database::update_download_progress(
+        uuid,
+        downloaded as i64,
+        total_size_opt.map(|size| size as i64),
+        state.pool.clone(),
+    )?;
+
+    if let Some(total_size) = total_size_opt {
+        if downloaded < total_size {
+            return Err(format!(
+                "connection closed at {} of {} bytes",
+                downloaded, total_size
+            )
+            .into());
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// Same as fetch_to_part, but reading from the filesystem.
+async fn copy_to_part(
+    download: &Download,
+    source: &Path,
+    part_path: &PathBuf,
+    existing: u64,
+    state: &state::GlobalStateWrapper,
+) -> DownloadResult<String> {
+    let (source, part) = (source.to_path_buf(), part_path.clone());
+    let (total_size, digest) =
+        blocking(move || copy_file_to_part(&source, &part, existing)).await?;
+    database::update_download_progress(
+        download.id.0,
+        total_size as i64,
+        Some(total_size as i64),
+        state.pool.clone(),
+    )?;
+    Ok(digest)
+}
+
+// Returns the size of source and the sha256 of the finished .part.
+fn copy_file_to_part(
+    source: &Path,
+    part_path: &Path,
+    existing: u64,
+) -> std::io::Result<(u64, String)> {
+    let total_size = std::fs::metadata(source)?.len();
+    let mut reader = File::open(source)?;
+    let (mut file, mut hasher) = if existing > 0 && existing <= total_size {
+        reader.seek(std::io::SeekFrom::Start(existing))?;
+        (
+            OpenOptions::new().append(true).open(part_path)?,
+            seed_hasher(part_path)?,
+        )
+    } else {
+        (File::create(part_path)?, Sha256::new())
+    };
+
+    let mut buf = vec![0u8; 1024 * 1024];
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buf[..read])?;
+        hasher.update(&buf[..read]);
+    }
+    file.flush()?;
+    Ok((total_size, format!("{:x}", hasher.finalize())))
 }
 
 fn save_new_llm(
     uuid: Uuid,
     path: PathBuf,
+    sha256: Option<String>,
     stream_id: String,
     llm_reg: LLMRegistryEntry,
-    app: tauri::AppHandle,
+    state: state::GlobalStateWrapper,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let state: tauri::State<'_, state::GlobalStateWrapper> = app.state();
-
     let new_llm: llm::LLM = llm::LLM {
         id: llm_reg.id.clone(),
         family_id: llm_reg.family_id.clone(),
@@ -218,14 +1041,17 @@ fn save_new_llm(
         session_parameters: DbHashMap(llm_reg.session_parameters.clone()),
         user_session_parameters: DbVec(llm_reg.user_session_parameters.clone()),
         model_path: DbOptionPathbuf(Some(path.clone())),
+        sha256: sha256,
+        version: llm_reg.version.clone(),
+        registry: origin_registry(&llm_reg, state.pool.clone()),
     };
 
     match database::save_new_llm(new_llm, state.pool.clone()) {
         Ok(_) => {
             info!("Successful download, llms serialized");
-            app.emit_all(
+            state.emitter.emit(
                 "downloads",
-                emitter::EmitterEvent {
+                &emitter::EmitterEvent {
                     stream_id: stream_id.clone(),
                     event: emitter::EmitterEventPayload::DownloadCompletion {},
                 },
@@ -233,9 +1059,9 @@ fn save_new_llm(
         }
         Err(_) => {
             info!("Failed to save download");
-            app.emit_all(
+            state.emitter.emit(
                 "downloads",
-                emitter::EmitterEvent {
+                &emitter::EmitterEvent {
                     stream_id: stream_id.clone(),
                     event: emitter::EmitterEventPayload::DownloadError {
                         message: "failed to save llm".into(),
@@ -244,12 +1070,360 @@ fn save_new_llm(
             )?;
         }
     }
-    app.emit_all(
+    state.emitter.emit(
         "downloads",
-        emitter::EmitterEvent {
+        &emitter::EmitterEvent {
             stream_id: stream_id.clone(),
             event: emitter::EmitterEventPayload::ChannelClose {},
         },
     )?;
     Ok(())
 }
+
+/*
+ * Registries — where downloadable models come from.
+ * */
+
+pub const DEFAULT_REGISTRY_URL: &str =
+    "https://raw.githubusercontent.com/JuliaMerz/pantry/master/models/index.json";
+
+// On id clashes the model from the more trusted registry wins.
+pub const REGISTRY_TRUST_LEVELS: [&str; 2] = ["trusted", "untrusted"];
+
+#[derive(Debug, Clone, Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::registry)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Registry {
+    pub name: String,
+    // Either a http(s) url or a path to a local index.json.
+    pub location: String,
+    pub trust: String,
+    pub cached_index: Option<String>,
+    pub last_refreshed: Option<DateTime<Utc>>,
+    pub added: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RegistryInfo {
+    pub name: String,
+    pub location: String,
+    pub trust: String,
+    pub last_refreshed: Option<DateTime<Utc>>,
+    pub model_count: usize,
+}
+
+impl From<&Registry> for RegistryInfo {
+    fn from(reg: &Registry) -> Self {
+        RegistryInfo {
+            name: reg.name.clone(),
+            location: reg.location.clone(),
+            trust: reg.trust.clone(),
+            last_refreshed: reg.last_refreshed,
+            model_count: reg.models().len(),
+        }
+    }
+}
+
+// A model as listed by a registry. Entries stay raw json, since index files
+// don't carry backend fields like backendUuid.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DownloadableLLM {
+    pub registry: String,
+    pub trust: String,
+    pub id: String,
+    pub entry: Value,
+}
+
+impl Registry {
+    pub fn models(&self) -> serde_json::Map<String, Value> {
+        self.cached_index
+            .as_ref()
+            .and_then(|index| serde_json::from_str::<Value>(index).ok())
+            .and_then(|index| index.get("models").and_then(|m| m.as_object()).cloned())
+            .unwrap_or_default()
+    }
+}
+
+fn is_remote(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+async fn fetch_registry_index(location: &str) -> Result<Value, String> {
+    let index: Value = if is_remote(location) {
+        reqwest::get(location)
+            .await
+            .map_err(|err| format!("Failed to fetch {}: {:?}", location, err))?
+            .error_for_status()
+            .map_err(|err| format!("Failed to fetch {}: {:?}", location, err))?
+            .json()
+            .await
+            .map_err(|err| format!("Invalid registry at {}: {:?}", location, err))?
+    } else {
+        let path = location.strip_prefix("file://").unwrap_or(location);
+        let raw = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {:?}", path, err))?;
+        serde_json::from_str(&raw)
+            .map_err(|err| format!("Invalid registry at {}: {:?}", path, err))?
+    };
+
+    // Older indexes get migrated, broken entries get dropped rather than failing at load time.
+    let result = registry_index::lint(index);
+    for diag in result.diagnostics.iter() {
+        warn!("Registry at {}: {}", location, diag);
+    }
+    let mut migrated = result
+        .index
+        .clone()
+        .ok_or(format!("Registry at {} is not a valid index", location))?;
+    migrated["models"] = Value::Object(result.valid_models());
+    Ok(migrated)
+}
+
+// Fetches the registry again and replaces the cache. The old cache is left alone on failure.
+pub async fn refresh_registry(
+    reg: &Registry,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Registry, String> {
+    let index = fetch_registry_index(&reg.location).await?;
+    database::set_registry_cache(reg.name.clone(), index.to_string(), pool.clone())
+        .map_err(|err| format!("Failed to cache registry: {:?}", err))?;
+    database::get_registry(reg.name.clone(), pool)
+        .map_err(|err| format!("Failed to load registry: {:?}", err))
+}
+
+pub async fn add_registry(
+    name: String,
+    location: String,
+    trust: String,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Registry, String> {
+    if !REGISTRY_TRUST_LEVELS.contains(&trust.as_str()) {
+        return Err(format!(
+            "Unknown trust level {}, expected one of {:?}",
+            trust, REGISTRY_TRUST_LEVELS
+        ));
+    }
+    if database::get_registry(name.clone(), pool.clone()).is_ok() {
+        return Err(format!("A registry named {} already exists", name));
+    }
+    // Refuse registries we can't read, rather than storing a broken one.
+    let index = fetch_registry_index(&location).await?;
+    database::save_new_registry(
+        Registry {
+            name,
+            location,
+            trust,
+            cached_index: Some(index.to_string()),
+            last_refreshed: Some(Utc::now()),
+            added: Utc::now(),
+        },
+        pool,
+    )
+    .map_err(|err| format!("Failed to save registry: {:?}", err))
+}
+
+// Every registry, refreshed if asked (or never fetched), falling back to the cache when offline.
+pub async fn get_registries(
+    refresh: bool,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Vec<Registry>, String> {
+    let registries = database::get_registries(pool.clone())
+        .map_err(|err| format!("Failed to load registries: {:?}", err))?;
+    let mut result = Vec::new();
+    for reg in registries {
+        if refresh || reg.cached_index.is_none() {
+            match refresh_registry(&reg, pool.clone()).await {
+                Ok(fresh) => {
+                    result.push(fresh);
+                    continue;
+                }
+                Err(err) => warn!("Using cached copy of registry {}: {}", reg.name, err),
+            }
+        }
+        result.push(reg);
+    }
+    Ok(result)
+}
+
+// Most trusted first. Stable, so registries of equal trust keep the order they were added in.
+pub fn sort_by_trust(registries: &mut Vec<Registry>) {
+    registries.sort_by_key(|reg| {
+        REGISTRY_TRUST_LEVELS
+            .iter()
+            .position(|level| *level == reg.trust)
+            .unwrap_or(REGISTRY_TRUST_LEVELS.len())
+    });
+}
+
+// Whether a registry's raw entry is the same download as llm_reg: the same url, or for
+// Hugging Face entries the same repo and revision (their url only exists once resolved).
+pub fn same_source(listed: &Value, llm_reg: &LLMRegistryEntry) -> bool {
+    let source = listed
+        .get("huggingface")
+        .cloned()
+        .and_then(|hf| serde_json::from_value::<huggingface::HuggingFaceSource>(hf).ok());
+    match (&source, &llm_reg.huggingface) {
+        (None, None) => {
+            listed.get("url").and_then(|url| url.as_str()) == Some(llm_reg.url.as_str())
+        }
+        (Some(listed), Some(wanted)) => {
+            listed.repo == wanted.repo && listed.revision == wanted.revision
+        }
+        _ => false,
+    }
+}
+
+// The most trusted registry listing this entry, going by the cached indexes.
+fn origin_registry(
+    llm_reg: &LLMRegistryEntry,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Option<String> {
+    let mut registries = database::get_registries(pool).ok()?;
+    sort_by_trust(&mut registries);
+    registries
+        .into_iter()
+        .find(|reg| {
+            reg.models()
+                .get(&llm_reg.id)
+                .map_or(false, |listed| same_source(listed, llm_reg))
+        })
+        .map(|reg| reg.name)
+}
+
+// All downloadable models across registries, one per id.
+pub async fn downloadable_llms(
+    refresh: bool,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Vec<DownloadableLLM>, String> {
+    let mut registries = get_registries(refresh, pool).await?;
+    sort_by_trust(&mut registries);
+
+    let mut seen = std::collections::HashSet::new();
+    let mut llms = Vec::new();
+    for reg in registries.iter() {
+        for (id, entry) in reg.models() {
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            llms.push(DownloadableLLM {
+                registry: reg.name.clone(),
+                trust: reg.trust.clone(),
+                id,
+                entry,
+            });
+        }
+    }
+    Ok(llms)
+}
+
+// The models of one registry, for when the caller wants a specific source.
+pub async fn registry_llms(
+    name: String,
+    refresh: bool,
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+) -> Result<Vec<DownloadableLLM>, String> {
+    let mut reg = database::get_registry(name.clone(), pool.clone())
+        .map_err(|_err| format!("No registry named {}", name))?;
+    if refresh || reg.cached_index.is_none() {
+        reg = refresh_registry(&reg, pool).await?;
+    }
+    Ok(reg
+        .models()
+        .into_iter()
+        .map(|(id, entry)| DownloadableLLM {
+            registry: reg.name.clone(),
+            trust: reg.trust.clone(),
+            id,
+            entry,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn unsatisfiable_range_means_the_part_is_complete() {
+        assert_eq!(
+            part_response(
+                1000,
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                Some("bytes */1000"),
+                None
+            ),
+            PartResponse::Complete
+        );
+        // Our .part is longer than the file, or the server didn't say.
+        assert_eq!(
+            part_response(
+                1000,
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                Some("bytes */800"),
+                None
+            ),
+            PartResponse::Restarted { total: None }
+        );
+        assert_eq!(
+            part_response(1000, StatusCode::RANGE_NOT_SATISFIABLE, None, None),
+            PartResponse::Restarted { total: None }
+        );
+        // Without a Range header it's just an error, left to error_for_status.
+        assert_eq!(
+            part_response(0, StatusCode::RANGE_NOT_SATISFIABLE, None, Some(10)),
+            PartResponse::Restarted { total: Some(10) }
+        );
+    }
+
+    #[test]
+    fn partial_content_resumes_with_the_range_total() {
+        assert_eq!(
+            part_response(
+                100,
+                StatusCode::PARTIAL_CONTENT,
+                Some("bytes 100-999/1000"),
+                Some(900)
+            ),
+            PartResponse::Resumed { total: Some(1000) }
+        );
+        assert_eq!(
+            part_response(
+                100,
+                StatusCode::PARTIAL_CONTENT,
+                Some("bytes 100-999/*"),
+                Some(900)
+            ),
+            PartResponse::Resumed { total: None }
+        );
+    }
+
+    #[test]
+    fn partial_content_at_another_offset_starts_over() {
+        assert_eq!(
+            part_response(
+                100,
+                StatusCode::PARTIAL_CONTENT,
+                Some("bytes 50-999/1000"),
+                Some(950)
+            ),
+            PartResponse::Restarted { total: Some(1000) }
+        );
+        assert_eq!(
+            part_response(100, StatusCode::PARTIAL_CONTENT, None, Some(900)),
+            PartResponse::Restarted { total: None }
+        );
+    }
+
+    #[test]
+    fn ignored_range_starts_over() {
+        assert_eq!(
+            part_response(100, StatusCode::OK, None, Some(1000)),
+            PartResponse::Restarted { total: Some(1000) }
+        );
+        assert_eq!(
+            part_response(0, StatusCode::OK, None, None),
+            PartResponse::Restarted { total: None }
+        );
+    }
+}
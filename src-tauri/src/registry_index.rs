@@ -0,0 +1,565 @@
+// registry_index.rs
+//
+// The index.json format registries are published in. Every index carries a version,
+// older versions get migrated forward before validation, so registries in the wild
+// keep working while the format moves on.
+
+use crate::registry::LLMRegistryEntry;
+use serde_json::{Map, Value};
+use std::fmt;
+
+pub const INDEX_VERSION: &str = "0.0.2";
+
+// Oldest first. Each version but the last has a migration to the next one.
+const INDEX_VERSIONS: [&str; 2] = ["0.0.1", "0.0.2"];
+
+const CONNECTOR_TYPES: [&str; 3] = ["llmrs", "openai", "genericapi"];
+
+// What llm.rs can load, see connectors/llmrs.rs.
+pub const MODEL_ARCHITECTURES: [&str; 7] =
+    ["bloom", "gpt2", "gptj", "gptneox", "llama", "mpt", "falcon"];
+
+const ENTRY_FIELDS: [&str; 22] = [
+    "id",
+    "familyId",
+    "organization",
+    "name",
+    "license",
+    "description",
+    "homepage",
+    "capabilities",
+    "tags",
+    "requirements",
+    "url",
+    "config",
+    "local",
+    "connectorType",
+    "parameters",
+    "userParameters",
+    "sessionParameters",
+    "userSessionParameters",
+    "sha256",
+    "size",
+    "huggingface",
+    "version",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // None for problems with the index itself.
+    pub entry: Option<String>,
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match (&self.entry, &self.field) {
+            (Some(entry), Some(field)) => {
+                write!(f, "{}: {}.{}: {}", severity, entry, field, self.message)
+            }
+            (Some(entry), None) => write!(f, "{}: {}: {}", severity, entry, self.message),
+            (None, Some(field)) => write!(f, "{}: {}: {}", severity, field, self.message),
+            (None, None) => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+fn diagnostic(
+    severity: Severity,
+    entry: Option<&str>,
+    field: Option<&str>,
+    message: String,
+) -> Diagnostic {
+    Diagnostic {
+        severity,
+        entry: entry.map(|e| e.to_string()),
+        field: field.map(|f| f.to_string()),
+        message,
+    }
+}
+
+pub struct LintResult {
+    // The index migrated to INDEX_VERSION, if it could be.
+    pub index: Option<Value>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl LintResult {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diag| diag.severity == Severity::Error)
+    }
+
+    // Entries without errors, keyed by id.
+    pub fn valid_models(&self) -> Map<String, Value> {
+        let broken: Vec<&String> = self
+            .diagnostics
+            .iter()
+            .filter(|diag| diag.severity == Severity::Error)
+            .filter_map(|diag| diag.entry.as_ref())
+            .collect();
+        self.index
+            .as_ref()
+            .and_then(|index| index.get("models"))
+            .and_then(|models| models.as_object())
+            .map(|models| {
+                models
+                    .iter()
+                    .filter(|(id, _)| !broken.contains(id))
+                    .map(|(id, entry)| (id.clone(), entry.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+// Migrates and validates an index.
+pub fn lint(index: Value) -> LintResult {
+    let mut diagnostics = Vec::new();
+    let index = match migrate(index, &mut diagnostics) {
+        Some(index) => index,
+        None => {
+            return LintResult {
+                index: None,
+                diagnostics,
+            }
+        }
+    };
+    validate(&index, &mut diagnostics);
+    LintResult {
+        index: Some(index),
+        diagnostics,
+    }
+}
+
+fn migrate(mut index: Value, diagnostics: &mut Vec<Diagnostic>) -> Option<Value> {
+    if !index.is_object() {
+        diagnostics.push(diagnostic(
+            Severity::Error,
+            None,
+            None,
+            "index must be a json object".into(),
+        ));
+        return None;
+    }
+
+    // Indexes from before anyone read the field are treated as the first version.
+    let version = match index.get("version") {
+        Some(Value::String(version)) => version.clone(),
+        None => {
+            diagnostics.push(diagnostic(
+                Severity::Warning,
+                None,
+                Some("version"),
+                format!("missing, assuming {}", INDEX_VERSIONS[0]),
+            ));
+            INDEX_VERSIONS[0].to_string()
+        }
+        Some(other) => {
+            diagnostics.push(diagnostic(
+                Severity::Error,
+                None,
+                Some("version"),
+                format!("must be a string, got {}", other),
+            ));
+            return None;
+        }
+    };
+
+    let mut position = match INDEX_VERSIONS.iter().position(|v| *v == version) {
+        Some(position) => position,
+        None => {
+            diagnostics.push(diagnostic(
+                Severity::Error,
+                None,
+                Some("version"),
+                format!(
+                    "unsupported version {}, this pantry understands {:?}",
+                    version, INDEX_VERSIONS
+                ),
+            ));
+            return None;
+        }
+    };
+
+    while position + 1 < INDEX_VERSIONS.len() {
+        match INDEX_VERSIONS[position] {
+            "0.0.1" => migrate_0_0_1(&mut index, diagnostics),
+            _ => {}
+        }
+        position += 1;
+        index["version"] = Value::String(INDEX_VERSIONS[position].into());
+    }
+    Some(index)
+}
+
+// 0.0.1 -> 0.0.2: config keys are snake_case (connectors read model_architecture), and
+// the frontend's backendUuid/downloadState don't belong in published entries.
+fn migrate_0_0_1(index: &mut Value, diagnostics: &mut Vec<Diagnostic>) {
+    let models = match index.get_mut("models").and_then(|m| m.as_object_mut()) {
+        Some(models) => models,
+        None => return,
+    };
+    for (id, entry) in models.iter_mut() {
+        let entry = match entry.as_object_mut() {
+            Some(entry) => entry,
+            None => continue,
+        };
+        for key in ["backendUuid", "downloadState"] {
+            if entry.remove(key).is_some() {
+                diagnostics.push(diagnostic(
+                    Severity::Warning,
+                    Some(id),
+                    Some(key),
+                    "frontend only field, removed".into(),
+                ));
+            }
+        }
+        if let Some(Value::Object(config)) = entry.get_mut("config") {
+            let renamed: Vec<(String, String)> = config
+                .keys()
+                .map(|key| (key.clone(), to_snake_case(key)))
+                .filter(|(key, snake)| key != snake)
+                .collect();
+            for (key, snake) in renamed {
+                if let Some(value) = config.remove(&key) {
+                    diagnostics.push(diagnostic(
+                        Severity::Warning,
+                        Some(id),
+                        Some("config"),
+                        format!("renamed {} to {}", key, snake),
+                    ));
+                    config.entry(snake).or_insert(value);
+                }
+            }
+        }
+    }
+}
+
+fn to_snake_case(key: &str) -> String {
+    let mut snake = String::new();
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            snake.push('_');
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+fn validate(index: &Value, diagnostics: &mut Vec<Diagnostic>) {
+    match index.get("name") {
+        Some(Value::String(_)) => {}
+        _ => diagnostics.push(diagnostic(
+            Severity::Warning,
+            None,
+            Some("name"),
+            "registries should have a name".into(),
+        )),
+    }
+
+    let models = match index.get("models") {
+        Some(Value::Object(models)) => models,
+        _ => {
+            diagnostics.push(diagnostic(
+                Severity::Error,
+                None,
+                Some("models"),
+                "must be an object of id to entry".into(),
+            ));
+            return;
+        }
+    };
+    for (id, entry) in models.iter() {
+        validate_entry(id, entry, diagnostics);
+    }
+}
+
+fn validate_entry(id: &str, entry: &Value, diagnostics: &mut Vec<Diagnostic>) {
+    let mut error = |field: Option<&str>, message: String| {
+        diagnostics.push(diagnostic(Severity::Error, Some(id), field, message))
+    };
+
+    let obj = match entry.as_object() {
+        Some(obj) => obj,
+        None => return error(None, "entry must be an object".into()),
+    };
+
+    match obj.get("id") {
+        Some(Value::String(entry_id)) if entry_id == id => {}
+        Some(Value::String(entry_id)) => error(
+            Some("id"),
+            format!("{} doesn't match its key in models", entry_id),
+        ),
+        _ => error(Some("id"), "required string".into()),
+    }
+    match obj.get("name") {
+        Some(Value::String(name)) if !name.trim().is_empty() => {}
+        _ => error(Some("name"), "required, non-empty string".into()),
+    }
+
+    let connector = obj.get("connectorType").and_then(|c| c.as_str());
+    match connector {
+        Some(connector) if CONNECTOR_TYPES.contains(&connector) => {}
+        _ => error(
+            Some("connectorType"),
+            format!("must be one of {:?}", CONNECTOR_TYPES),
+        ),
+    }
+
+    let url = obj.get("url").and_then(|u| u.as_str()).unwrap_or("");
+    let huggingface = obj.get("huggingface");
+    if let Some(source) = huggingface {
+        let repo = source.get("repo").and_then(|r| r.as_str()).unwrap_or("");
+        let parts: Vec<&str> = repo.split('/').collect();
+        if parts.len() != 2 || parts.iter().any(|part| part.is_empty()) {
+            error(
+                Some("huggingface"),
+                "repo must look like organization/name".into(),
+            );
+        }
+    }
+    if connector == Some("llmrs") {
+        // Hugging Face entries get their url when they're downloaded.
+        if huggingface.is_none() && !(url.starts_with("https://") || url.starts_with("http://")) {
+            error(
+                Some("url"),
+                "llmrs models need a http(s) url or a huggingface repo".into(),
+            );
+        }
+        match obj
+            .get("config")
+            .and_then(|c| c.get("model_architecture"))
+            .and_then(|a| a.as_str())
+        {
+            Some(arch) if MODEL_ARCHITECTURES.contains(&arch) => {}
+            Some(arch) => error(
+                Some("config"),
+                format!(
+                    "unknown model_architecture {}, expected one of {:?}",
+                    arch, MODEL_ARCHITECTURES
+                ),
+            ),
+            None => error(
+                Some("config"),
+                "llmrs models need a model_architecture".into(),
+            ),
+        }
+    }
+
+    if let Some(capabilities) = obj.get("capabilities") {
+        let valid = capabilities
+            .as_object()
+            .map(|caps| caps.values().all(|v| v.as_i64().map_or(false, |v| v >= -1)))
+            .unwrap_or(false);
+        if !valid {
+            error(
+                Some("capabilities"),
+                "must map capability names to integers, -1 for unevaluated".into(),
+            );
+        }
+    }
+
+    if let Some(sha256) = obj.get("sha256") {
+        let valid = sha256
+            .as_str()
+            .map(|s| s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()))
+            .unwrap_or(false);
+        if !valid {
+            error(Some("sha256"), "must be 64 hex characters".into());
+        }
+    }
+    if let Some(size) = obj.get("size") {
+        if !size.is_u64() {
+            error(Some("size"), "must be a non-negative integer".into());
+        }
+    }
+    if let Some(version) = obj.get("version") {
+        if !version.as_str().map_or(false, |v| !v.trim().is_empty()) {
+            error(
+                Some("version"),
+                "must be a non-empty string, like 1.2.0".into(),
+            );
+        }
+    }
+
+    // Anything the backend can't deserialize would fail at download time instead.
+    let mut with_backend = obj.clone();
+    with_backend.insert("backendUuid".into(), Value::String("".into()));
+    if let Err(err) = serde_json::from_value::<LLMRegistryEntry>(Value::Object(with_backend)) {
+        error(None, format!("not a valid registry entry: {}", err));
+    }
+
+    for key in obj.keys() {
+        if !ENTRY_FIELDS.contains(&key.as_str()) {
+            diagnostics.push(diagnostic(
+                Severity::Warning,
+                Some(id),
+                Some(key),
+                "unknown field, ignored".into(),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry() -> Value {
+        json!({
+            "id": "llama",
+            "familyId": "llama",
+            "organization": "meta",
+            "name": "Llama",
+            "license": "",
+            "description": "",
+            "homepage": "",
+            "capabilities": {"TEXT_COMPLETION": 2, "CONVERSATION": -1},
+            "tags": [],
+            "requirements": "",
+            "url": "https://example.com/llama.bin",
+            "config": {"model_architecture": "llama"},
+            "local": true,
+            "connectorType": "llmrs",
+            "parameters": {},
+            "userParameters": [],
+            "sessionParameters": {},
+            "userSessionParameters": [],
+        })
+    }
+
+    fn errors(id: &str, entry: &Value) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        validate_entry(id, entry, &mut diagnostics);
+        diagnostics
+            .into_iter()
+            .filter(|diag| diag.severity == Severity::Error)
+            .collect()
+    }
+
+    fn has_error(diagnostics: &[Diagnostic], field: &str) -> bool {
+        diagnostics
+            .iter()
+            .any(|diag| diag.field.as_deref() == Some(field))
+    }
+
+    #[test]
+    fn migrates_0_0_1_indexes() {
+        let mut old = entry();
+        old["backendUuid"] = json!("abc");
+        old["downloadState"] = json!("done");
+        old["config"] = json!({"modelArchitecture": "llama"});
+        let result = lint(json!({"name": "old", "models": {"llama": old}}));
+
+        assert!(!result.has_errors(), "{:?}", result.diagnostics);
+        let index = result.index.unwrap();
+        assert_eq!(index["version"], json!(INDEX_VERSION));
+        let migrated = &index["models"]["llama"];
+        assert!(migrated.get("backendUuid").is_none());
+        assert!(migrated.get("downloadState").is_none());
+        assert_eq!(migrated["config"], json!({"model_architecture": "llama"}));
+        // The missing version, the two removed fields and the rename.
+        assert_eq!(result.diagnostics.len(), 4);
+    }
+
+    #[test]
+    fn snake_case_config_wins_over_renamed_keys() {
+        let mut old = entry();
+        old["config"] = json!({"modelArchitecture": "gpt2", "model_architecture": "llama"});
+        let result = lint(json!({"version": "0.0.1", "name": "old", "models": {"llama": old}}));
+        assert_eq!(
+            result.index.unwrap()["models"]["llama"]["config"],
+            json!({"model_architecture": "llama"})
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let result = lint(json!({"version": "9.9.9", "models": {}}));
+        assert!(result.index.is_none());
+        assert!(result.has_errors());
+
+        let result = lint(json!({"version": 2, "models": {}}));
+        assert!(result.index.is_none());
+    }
+
+    #[test]
+    fn valid_entry_has_no_errors() {
+        assert!(errors("llama", &entry()).is_empty());
+    }
+
+    #[test]
+    fn validates_entry_fields() {
+        let mut bad = entry();
+        bad["id"] = json!("other");
+        bad["name"] = json!(" ");
+        bad["connectorType"] = json!("ggml");
+        bad["sha256"] = json!("abc");
+        bad["size"] = json!(-1);
+        bad["version"] = json!("");
+        bad["capabilities"] = json!({"CONVERSATION": -2});
+        let diagnostics = errors("llama", &bad);
+        for field in [
+            "id",
+            "name",
+            "connectorType",
+            "sha256",
+            "size",
+            "version",
+            "capabilities",
+        ] {
+            assert!(has_error(&diagnostics, field), "no error for {}", field);
+        }
+    }
+
+    #[test]
+    fn llmrs_entries_need_a_source_and_architecture() {
+        let mut bad = entry();
+        bad["url"] = json!("file:///models/llama.bin");
+        bad["config"] = json!({"model_architecture": "lstm"});
+        let diagnostics = errors("llama", &bad);
+        assert!(has_error(&diagnostics, "url"));
+        assert!(has_error(&diagnostics, "config"));
+
+        let mut hf = entry();
+        hf["url"] = json!("");
+        hf["huggingface"] = json!({"repo": "org/llama"});
+        assert!(errors("llama", &hf).is_empty());
+
+        hf["huggingface"] = json!({"repo": "llama"});
+        assert!(has_error(&errors("llama", &hf), "huggingface"));
+    }
+
+    #[test]
+    fn broken_entries_are_left_out() {
+        let mut bad = entry();
+        bad["id"] = json!("broken");
+        bad["name"] = json!("");
+        let result = lint(json!({
+            "version": INDEX_VERSION,
+            "name": "test",
+            "models": {"llama": entry(), "broken": bad},
+        }));
+        let models = result.valid_models();
+        assert!(models.contains_key("llama"));
+        assert!(!models.contains_key("broken"));
+    }
+}
@@ -1,6 +1,8 @@
 //request.rs
+use crate::database;
 use crate::database_types::*;
 use crate::registry;
+use crate::state;
 
 use crate::user;
 use chrono::DateTime;
@@ -12,7 +14,9 @@ use diesel::serialize::{self, Output, ToSql};
 use diesel::sqlite::{Sqlite, SqliteValue};
 use diesel::*;
 
+use log::{debug, error, info, warn};
 use serde_json;
+use uuid::Uuid;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, FromSqlRow, AsExpression)]
 #[diesel(sql_type = diesel::sql_types::Text)]
@@ -48,6 +52,18 @@ pub enum UserRequestType {
     UnloadRequest(UnloadRequest),
 }
 
+impl UserRequestType {
+    // As used by AutoApprovalRule.request_type.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            UserRequestType::DownloadRequest(_) => "download",
+            UserRequestType::PermissionRequest(_) => "permission",
+            UserRequestType::LoadRequest(_) => "load",
+            UserRequestType::UnloadRequest(_) => "unload",
+        }
+    }
+}
+
 impl FromSql<diesel::sql_types::Text, Sqlite> for UserRequestType {
     fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> diesel::deserialize::Result<Self> {
         let str = <String as FromSql<diesel::sql_types::Text, Sqlite>>::from_sql(bytes)?;
@@ -77,6 +93,353 @@ pub struct UserRequest {
     pub request: UserRequestType,
     pub complete: bool,
     pub accepted: bool,
+    // Left by whoever decided, e.g. why it was rejected.
+    pub decision_note: Option<String>,
+    // Set when only part of a PermissionRequest was granted.
+    pub granted_permissions: Option<user::Permissions>,
+}
+
+// Sent on request_events when a request is decided, and again once an accepted request
+// has been carried out (completed or failed), so clients don't have to poll for it.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RequestEvent {
+    pub request_id: Uuid,
+    pub user_id: Uuid,
+    // accepted, rejected, expired, completed or failed.
+    pub status: String,
+    // The decision note, the new LLM's uuid for finished downloads, or why it failed.
+    pub detail: Option<String>,
+}
+
+impl RequestEvent {
+    // Whether the request has been decided.
+    pub fn is_decision(&self) -> bool {
+        matches!(self.status.as_str(), "accepted" | "rejected" | "expired")
+    }
+}
+
+fn publish(
+    req: &UserRequest,
+    status: &str,
+    detail: Option<String>,
+    state: &state::GlobalStateWrapper,
+) {
+    // Nobody listening isn't an error.
+    let _ = state.request_events.send(RequestEvent {
+        request_id: req.id.0,
+        user_id: req.user_id.0,
+        status: status.into(),
+        detail,
+    });
+}
+
+// Decided requests are deleted once they're this old.
+const COMPLETED_REQUEST_RETENTION_DAYS: i64 = 30;
+
+// Set by the owner in the settings. A new request is accepted right away if every
+// condition of one rule holds, e.g. load requests for downloaded models from users with
+// the session permission:
+// {"request_type": "load", "user_permissions": ["session"], "downloaded_only": true}
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AutoApprovalRule {
+    // download, permission, load or unload.
+    pub request_type: String,
+    // Permissions the user must already have, with or without the perm_ prefix.
+    #[serde(default)]
+    pub user_permissions: Vec<String>,
+    // Empty for any user.
+    #[serde(default)]
+    pub user_ids: Vec<Uuid>,
+    // Load requests only match models that are already downloaded.
+    #[serde(default)]
+    pub downloaded_only: bool,
+}
+
+// The registry's own entry, if a configured registry lists this one under its id as the same
+// download. Anything else in a request body is up to the user who sent it, so that's what an
+// auto-approved download fetches instead.
+fn listed_entry(
+    entry: &registry::LLMRegistryEntry,
+    downloadable: &[registry::DownloadableLLM],
+) -> Option<registry::LLMRegistryEntry> {
+    downloadable
+        .iter()
+        .filter(|llm| llm.id == entry.id && registry::same_source(&llm.entry, entry))
+        .find_map(|llm| serde_json::from_value(llm.entry.clone()).ok())
+}
+
+impl AutoApprovalRule {
+    // downloadable is what the registries list, download rules only cover those.
+    // is_downloaded looks an LLM's uuid up for downloaded_only.
+    fn matches(
+        &self,
+        req: &UserRequest,
+        user: &user::User,
+        downloadable: &[registry::DownloadableLLM],
+        is_downloaded: &dyn Fn(Uuid) -> bool,
+    ) -> bool {
+        if self.request_type != req.request.kind() {
+            return false;
+        }
+        if !self.user_ids.is_empty() && !self.user_ids.contains(&req.user_id.0) {
+            return false;
+        }
+        let permissions = user::Permissions::from(user);
+        for name in self.user_permissions.iter() {
+            match permissions.get(name) {
+                Ok(true) => {}
+                Ok(false) => return false,
+                Err(err) => {
+                    warn!("Ignoring auto-approval rule: {}", err);
+                    return false;
+                }
+            }
+        }
+        match &req.request {
+            // Superuser is never handed out without the owner looking at it.
+            UserRequestType::PermissionRequest(pr) => !pr.requested_permissions.perm_superuser,
+            UserRequestType::DownloadRequest(dr) => {
+                listed_entry(&dr.llm_registry_entry, downloadable).is_some()
+            }
+            UserRequestType::LoadRequest(lr) if self.downloaded_only => Uuid::parse_str(&lr.llm_id)
+                .map(is_downloaded)
+                .unwrap_or(false),
+            _ => true,
+        }
+    }
+}
+
+// Rejects pending requests older than request_ttl_seconds and deletes old decided ones.
+pub fn expire_requests(state: &state::GlobalStateWrapper) -> Result<(), String> {
+    let ttl = state.user_settings.read().unwrap().request_ttl_seconds;
+    let now = Utc::now();
+    if let Some(ttl) = ttl {
+        let expired = database::expire_requests(
+            now - chrono::Duration::seconds(ttl as i64),
+            state.pool.clone(),
+        )
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+        if !expired.is_empty() {
+            info!("Expired {} pending requests", expired.len());
+        }
+        for req in expired.iter() {
+            publish(req, "expired", None, state);
+        }
+    }
+    database::delete_completed_requests(
+        now - chrono::Duration::days(COMPLETED_REQUEST_RETENTION_DAYS),
+        state.pool.clone(),
+    )
+    .map_err(|err| format!("Database failure: {:?}", err))?;
+    Ok(())
+}
+
+// The user's undecided request asking for the very same thing, if there is one.
+fn pending_duplicate(
+    requests: Vec<UserRequest>,
+    request: &UserRequestType,
+) -> Result<Option<UserRequest>, String> {
+    let requested = serde_json::to_value(request).map_err(|err| err.to_string())?;
+    Ok(requests.into_iter().find(|req| {
+        !req.complete && serde_json::to_value(&req.request).ok().as_ref() == Some(&requested)
+    }))
+}
+
+// Where new requests come in. A pending request identical to one the user already made is
+// returned instead of saved again, and requests an auto-approval rule covers are accepted.
+pub async fn submit_request(
+    mut new_request: UserRequest,
+    state: &state::GlobalStateWrapper,
+) -> Result<UserRequest, String> {
+    if let Err(err) = expire_requests(state) {
+        error!("Failed to expire requests: {}", err);
+    }
+    let requests = database::get_requests_for_user(new_request.user_id.0, state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+    if let Some(existing) = pending_duplicate(requests, &new_request.request)? {
+        debug!("Request repeats pending request {}", existing.id.0);
+        return Ok(existing);
+    }
+
+    let user = database::get_user(new_request.user_id.0, state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+    let rules = state
+        .user_settings
+        .read()
+        .unwrap()
+        .auto_approval_rules
+        .clone();
+    let downloadable = match &new_request.request {
+        UserRequestType::DownloadRequest(_)
+            if rules.iter().any(|rule| rule.request_type == "download") =>
+        {
+            registry::downloadable_llms(false, state.pool.clone())
+                .await
+                .unwrap_or_else(|err| {
+                    warn!("Can't check download rules against the registries: {}", err);
+                    Vec::new()
+                })
+        }
+        _ => Vec::new(),
+    };
+    let is_downloaded = |uuid| database::get_llm(uuid, state.pool.clone()).is_ok();
+    let auto_approved = rules
+        .iter()
+        .any(|rule| rule.matches(&new_request, &user, &downloadable, &is_downloaded));
+
+    // What gets downloaded and saved is the registry's entry, see listed_entry.
+    if auto_approved {
+        if let UserRequestType::DownloadRequest(dr) = &mut new_request.request {
+            if let Some(entry) = listed_entry(&dr.llm_registry_entry, &downloadable) {
+                dr.llm_registry_entry = entry;
+            }
+        }
+    }
+    let req = database::save_new_request(new_request, state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+    if !auto_approved {
+        return Ok(req);
+    }
+    info!("Auto-approving request {} from {}", req.id.0, user.name);
+    let note = Some("Accepted by an auto-approval rule".to_string());
+    if let Err(err) = accept_request(req.id.0, note, None, state).await {
+        error!("Failed to auto-approve request {}: {}", req.id.0, err);
+    }
+    database::get_request(req.id.0, state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))
+}
+
+fn undecided_request(
+    req_uuid: Uuid,
+    state: &state::GlobalStateWrapper,
+) -> Result<UserRequest, String> {
+    let req = database::get_request(req_uuid, state.pool.clone())
+        .map_err(|err| format!("Request not found: {:?}", err))?;
+    if req.complete {
+        return Err(format!(
+            "Request {} was already {}.",
+            req_uuid,
+            if req.accepted { "accepted" } else { "rejected" }
+        ));
+    }
+    Ok(req)
+}
+
+// Marks the request decided, unless someone else got there first. This is what claims it,
+// so it has to happen before anything requested is carried out.
+fn decide(
+    req_uuid: Uuid,
+    accepted: bool,
+    note: Option<String>,
+    granted: Option<user::Permissions>,
+    state: &state::GlobalStateWrapper,
+) -> Result<(), String> {
+    let updated =
+        database::mark_request_complete(req_uuid, accepted, note, granted, state.pool.clone())
+            .map_err(|err| format!("Database failure: {:?}", err))?;
+    match updated {
+        0 => Err(format!("Request {} was already decided.", req_uuid)),
+        _ => Ok(()),
+    }
+}
+
+// Marks it accepted, then carries out what was requested. Used by the UI and the admin API.
+// Downloads and loads finish in the background and publish completed or failed when done.
+// granted can narrow a PermissionRequest down to some of the requested permissions.
+pub async fn accept_request(
+    req_uuid: Uuid,
+    note: Option<String>,
+    granted: Option<user::Permissions>,
+    state: &state::GlobalStateWrapper,
+) -> Result<(), String> {
+    let req = undecided_request(req_uuid, state)?;
+    match (&req.request, &granted) {
+        (_, None) => {}
+        (UserRequestType::PermissionRequest(pr), Some(granted)) => {
+            if !granted.within(&pr.requested_permissions) {
+                return Err("Can only grant permissions that were requested.".into());
+            }
+        }
+        (_, Some(_)) => return Err("Only permission requests can be partially granted.".into()),
+    }
+    decide(req_uuid, true, note.clone(), granted.clone(), state)?;
+    publish(&req, "accepted", note, state);
+
+    let mut download = None;
+    let mut loading = None;
+    let carried_out = match req.request.clone() {
+        UserRequestType::DownloadRequest(dlr) => {
+            download = Some(dlr.llm_registry_entry);
+            Ok(())
+        }
+        UserRequestType::PermissionRequest(pr) => database::update_permissions(
+            req.user_id.0,
+            granted.unwrap_or(pr.requested_permissions),
+            state.pool.clone(),
+        )
+        .map(|_| ())
+        .map_err(|err| format!("Database failure: {:?}", err)),
+        UserRequestType::LoadRequest(lr) => match Uuid::parse_str(&lr.llm_id) {
+            Ok(uuid) => {
+                loading = Some(uuid);
+                state::activate_llm(uuid, state).await
+            }
+            Err(err) => Err(err.to_string()),
+        },
+        UserRequestType::UnloadRequest(ur) => match Uuid::parse_str(&ur.llm_id) {
+            Ok(uuid) => state::deactivate_llm(uuid, state).await,
+            Err(err) => Err(err.to_string()),
+        },
+    };
+    // It's accepted either way, failed tells whoever is waiting that nothing came of it.
+    if let Err(err) = carried_out {
+        publish(&req, "failed", Some(err.clone()), state);
+        return Err(err);
+    }
+
+    if let Some(llm_reg) = download {
+        let uuid = Uuid::new_v4();
+        let global_state = state.clone();
+        tokio::spawn(async move {
+            let result =
+                match registry::download_and_write_llm(llm_reg, uuid, global_state.clone()).await {
+                    // Paused and cancelled downloads return without saving the LLM.
+                    Ok(()) => database::get_llm(uuid, global_state.pool.clone())
+                        .map(|_| ())
+                        .map_err(|_| "Download paused or cancelled".to_string()),
+                    Err(err) => Err(format!("{:?}", err)),
+                };
+            match result {
+                Ok(()) => publish(&req, "completed", Some(uuid.to_string()), &global_state),
+                Err(err) => {
+                    error!("Requested download failed: {}", err);
+                    publish(&req, "failed", Some(err), &global_state);
+                }
+            }
+        });
+    } else if let Some(uuid) = loading {
+        let global_state = state.clone();
+        tokio::spawn(async move {
+            match state::wait_until_loaded(uuid, &global_state).await {
+                Ok(()) => publish(&req, "completed", Some(uuid.to_string()), &global_state),
+                Err(err) => publish(&req, "failed", Some(err), &global_state),
+            }
+        });
+    } else {
+        publish(&req, "completed", None, state);
+    }
+    Ok(())
+}
+
+pub fn reject_request(
+    req_uuid: Uuid,
+    note: Option<String>,
+    state: &state::GlobalStateWrapper,
+) -> Result<(), String> {
+    let req = undecided_request(req_uuid, state)?;
+    decide(req_uuid, false, note.clone(), None, state)?;
+    publish(&req, "rejected", note, state);
+    Ok(())
 }
 
 // pub fn serialize_all(
@@ -107,3 +470,202 @@ pub struct UserRequest {
 //     // let llms: Vec<LLM> = rmp_serde::deserialize(&buffer)?;
 //     Ok(blank_map)
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn listed(id: &str, url: &str) -> registry::DownloadableLLM {
+        registry::DownloadableLLM {
+            registry: "test".into(),
+            trust: "official".into(),
+            id: id.into(),
+            entry: serde_json::to_value(registry::test_entry(id, url)).unwrap(),
+        }
+    }
+
+    fn user() -> user::User {
+        let mut user = user::get_local_user();
+        user.id = DbUuid(Uuid::new_v4());
+        user.perm_superuser = false;
+        user.perm_session = true;
+        user
+    }
+
+    fn request(user: &user::User, request: UserRequestType) -> UserRequest {
+        UserRequest {
+            id: DbUuid(Uuid::new_v4()),
+            user_id: user.id.clone(),
+            reason: "".into(),
+            timestamp: Utc::now(),
+            originator: "test".into(),
+            request,
+            complete: false,
+            accepted: false,
+            decision_note: None,
+            granted_permissions: None,
+        }
+    }
+
+    fn rule(value: serde_json::Value) -> AutoApprovalRule {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn download(url: &str) -> UserRequestType {
+        UserRequestType::DownloadRequest(DownloadRequest {
+            llm_registry_entry: registry::test_entry("llama", url),
+        })
+    }
+
+    fn load(llm_id: &str) -> UserRequestType {
+        UserRequestType::LoadRequest(LoadRequest {
+            llm_id: llm_id.into(),
+        })
+    }
+
+    #[test]
+    fn only_listed_downloads_are_auto_approved() {
+        let user = user();
+        let rule = rule(json!({"request_type": "download"}));
+        let downloadable = vec![listed("llama", "https://example.com/llama.bin")];
+        let never = |_: Uuid| false;
+
+        let req = request(&user, download("https://example.com/llama.bin"));
+        assert!(rule.matches(&req, &user, &downloadable, &never));
+        assert!(!rule.matches(&req, &user, &[], &never));
+
+        let req = request(&user, download("https://evil.example.com/llama.bin"));
+        assert!(!rule.matches(&req, &user, &downloadable, &never));
+
+        let other_id = vec![listed("other", "https://example.com/llama.bin")];
+        let req = request(&user, download("https://example.com/llama.bin"));
+        assert!(listed_entry(
+            &registry::test_entry("llama", "https://example.com/llama.bin"),
+            &other_id
+        )
+        .is_none());
+        assert!(!rule.matches(&req, &user, &other_id, &never));
+    }
+
+    #[test]
+    fn auto_approved_downloads_use_the_listed_entry() {
+        let downloadable = vec![listed("llama", "https://example.com/llama.bin")];
+        let mut requested = registry::test_entry("llama", "https://example.com/llama.bin");
+        requested
+            .config
+            .insert("vocabulary_path".into(), json!("/etc/shadow"));
+        requested.sha256 = Some("0".repeat(64));
+
+        let entry = listed_entry(&requested, &downloadable).unwrap();
+        assert!(!entry.config.contains_key("vocabulary_path"));
+        assert!(entry.sha256.is_none());
+        assert_eq!(entry.url, "https://example.com/llama.bin");
+    }
+
+    #[test]
+    fn rules_check_type_users_and_permissions() {
+        let user = user();
+        let uuid = Uuid::new_v4();
+        let req = request(&user, load(&uuid.to_string()));
+        let always = |_: Uuid| true;
+
+        assert!(rule(json!({"request_type": "load"})).matches(&req, &user, &[], &always));
+        assert!(!rule(json!({"request_type": "unload"})).matches(&req, &user, &[], &always));
+        assert!(
+            rule(json!({"request_type": "load", "user_permissions": ["perm_session"]})).matches(
+                &req,
+                &user,
+                &[],
+                &always
+            )
+        );
+        assert!(
+            !rule(json!({"request_type": "load", "user_permissions": ["load_llm"]})).matches(
+                &req,
+                &user,
+                &[],
+                &always
+            )
+        );
+        // Unknown permission names never match.
+        assert!(
+            !rule(json!({"request_type": "load", "user_permissions": ["root"]})).matches(
+                &req,
+                &user,
+                &[],
+                &always
+            )
+        );
+        assert!(
+            !rule(json!({"request_type": "load", "user_ids": [Uuid::new_v4()]})).matches(
+                &req,
+                &user,
+                &[],
+                &always
+            )
+        );
+        assert!(
+            rule(json!({"request_type": "load", "user_ids": [user.id.0]})).matches(
+                &req,
+                &user,
+                &[],
+                &always
+            )
+        );
+    }
+
+    #[test]
+    fn downloaded_only_looks_the_llm_up() {
+        let user = user();
+        let uuid = Uuid::new_v4();
+        let rule = rule(json!({"request_type": "load", "downloaded_only": true}));
+        let is_downloaded = |id: Uuid| id == uuid;
+
+        let req = request(&user, load(&uuid.to_string()));
+        assert!(rule.matches(&req, &user, &[], &is_downloaded));
+        let req = request(&user, load(&Uuid::new_v4().to_string()));
+        assert!(!rule.matches(&req, &user, &[], &is_downloaded));
+        let req = request(&user, load("llama"));
+        assert!(!rule.matches(&req, &user, &[], &is_downloaded));
+    }
+
+    #[test]
+    fn superuser_is_never_auto_approved() {
+        let user = user();
+        let rule = rule(json!({"request_type": "permission"}));
+        let mut permissions = user::Permissions::from(&user);
+        let req = request(
+            &user,
+            UserRequestType::PermissionRequest(PermissionRequest {
+                requested_permissions: permissions.clone(),
+            }),
+        );
+        assert!(rule.matches(&req, &user, &[], &|_| false));
+
+        permissions.perm_superuser = true;
+        let req = request(
+            &user,
+            UserRequestType::PermissionRequest(PermissionRequest {
+                requested_permissions: permissions,
+            }),
+        );
+        assert!(!rule.matches(&req, &user, &[], &|_| false));
+    }
+
+    #[test]
+    fn repeated_requests_return_the_pending_one() {
+        let user = user();
+        let pending = request(&user, load("a"));
+        let mut decided = request(&user, load("b"));
+        decided.complete = true;
+        let requests = vec![pending.clone(), decided];
+
+        let found = pending_duplicate(requests.clone(), &load("a")).unwrap();
+        assert_eq!(found.map(|req| req.id.0), Some(pending.id.0));
+        assert!(pending_duplicate(requests.clone(), &load("b"))
+            .unwrap()
+            .is_none());
+        assert!(pending_duplicate(requests, &load("c")).unwrap().is_none());
+    }
+}
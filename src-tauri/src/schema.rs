@@ -1,5 +1,32 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_key (id) {
+        id -> Text,
+        user_id -> Text,
+        name -> Text,
+        key_hash -> Text,
+        created -> TimestamptzSqlite,
+        expires -> Nullable<TimestamptzSqlite>,
+        revoked -> Bool,
+    }
+}
+
+diesel::table! {
+    download (id) {
+        id -> Text,
+        llm_registry_entry -> Text,
+        part_path -> Text,
+        downloaded_bytes -> BigInt,
+        total_bytes -> Nullable<BigInt>,
+        status -> Text,
+        started -> TimestamptzSqlite,
+        updated -> TimestamptzSqlite,
+        upgrade_of -> Nullable<Text>,
+        keep_old -> Bool,
+    }
+}
+
 diesel::table! {
     llm (uuid) {
         uuid -> Text,
@@ -25,6 +52,9 @@ diesel::table! {
         user_parameters -> Text,
         session_parameters -> Text,
         user_session_parameters -> Text,
+        sha256 -> Nullable<Text>,
+        version -> Nullable<Text>,
+        registry -> Nullable<Text>,
     }
 }
 
@@ -52,6 +82,17 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    registry (name) {
+        name -> Text,
+        location -> Text,
+        trust -> Text,
+        cached_index -> Nullable<Text>,
+        last_refreshed -> Nullable<TimestamptzSqlite>,
+        added -> TimestamptzSqlite,
+    }
+}
+
 diesel::table! {
     requests (id) {
         id -> Text,
@@ -62,6 +103,8 @@ diesel::table! {
         request -> Text,
         complete -> Bool,
         accepted -> Bool,
+        decision_note -> Nullable<Text>,
+        granted_permissions -> Nullable<Text>,
     }
 }
 
@@ -69,7 +112,6 @@ diesel::table! {
     user (id) {
         id -> Text,
         name -> Text,
-        api_key -> Text,
         perm_superuser -> Bool,
         perm_load_llm -> Bool,
         perm_unload_llm -> Bool,
@@ -83,15 +125,19 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(api_key -> user (user_id));
 diesel::joinable!(llm_history -> llm_session (llm_session_id));
 diesel::joinable!(llm_session -> llm (llm_uuid));
 diesel::joinable!(llm_session -> user (user_id));
 diesel::joinable!(requests -> user (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_key,
+    download,
     llm,
     llm_history,
     llm_session,
+    registry,
     requests,
     user,
 );
@@ -2,20 +2,32 @@
 
 use crate::database;
 use crate::database_types::DbUuid;
-use crate::listeners::create_listeners;
-use crate::llm::{LLMActivated, LLMWrapper, LLM};
+use crate::disk;
+use crate::huggingface;
+use crate::import;
+use crate::listeners::{create_listeners, ListenerConfig};
+use crate::llm::{HistoryFilter, HistorySearchHit, LLMActivated, LLMWrapper, LLM};
 use crate::llm_manager;
 use crate::registry::{self, DownloadingLLM};
 use crate::request;
 use crate::request::{UserRequest, UserRequestType};
 
 use crate::state;
+use crate::transfer;
+use crate::upgrade;
 use crate::user;
-use axum::{extract::State, Json};
 use axum::{
+    body::{Body, HttpBody},
+    http::{header, HeaderMap, Request},
+    middleware::{self, Next},
     response::sse::{Event, KeepAlive, Sse},
-    routing::post,
-    Router,
+    response::Response,
+    routing::{get, post, MethodRouter},
+    Extension, Router,
+};
+use axum::{
+    extract::{Query, State},
+    Json,
 };
 use axum_macros;
 use chrono::DateTime;
@@ -24,14 +36,14 @@ use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, Pool};
 use futures_util::stream::Stream;
 use hyper::StatusCode;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use serde;
 use serde_json::Value;
-use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fmt;
+use std::time::Duration;
 
-use tokio::sync::oneshot;
+use tokio::sync::{broadcast, oneshot};
 use tokio_stream::{wrappers::ReceiverStream, StreamExt as _};
 use uuid::Uuid;
 
@@ -188,6 +200,10 @@ pub struct UserRequestStatus {
     pub request: UserRequestType,
     pub complete: bool,
     pub accepted: bool,
+    // Why it was decided the way it was, if the owner said.
+    pub decision_note: Option<String>,
+    // What was actually granted, when it's less than was requested.
+    pub granted_permissions: Option<user::Permissions>,
 }
 impl From<&UserRequest> for UserRequestStatus {
     fn from(llm_req: &UserRequest) -> Self {
@@ -198,6 +214,8 @@ impl From<&UserRequest> for UserRequestStatus {
             request: llm_req.request.clone(),
             complete: llm_req.complete.clone(),
             accepted: llm_req.accepted.clone(),
+            decision_note: llm_req.decision_note.clone(),
+            granted_permissions: llm_req.granted_permissions.clone(),
         }
     }
 }
@@ -246,26 +264,56 @@ pub struct LLMPreference {
     pub capability_type: Option<CapabilityType>,
 }
 
-fn user_permission_check(
-    required: &str,
+// Finds the key the caller presented. Revoked and expired keys never match.
+async fn authenticate_key(
     api_key: String,
-    // user: &user::User,
     user_id: Uuid,
     pool: Pool<ConnectionManager<SqliteConnection>>,
-) -> Result<user::User, (StatusCode, String)> {
-    let user = database::get_user(user_id, pool)
+) -> Result<(user::User, user::ApiKey), (StatusCode, String)> {
+    let user = database::get_user(user_id, pool.clone())
         .map_err(|_err| (StatusCode::UNAUTHORIZED, "Not a Valid User {:?}".into()))?;
 
-    let mut hasher = Sha256::new();
-    hasher.update(api_key);
-    let hash_result = format!("{:X}", hasher.finalize());
-    if hash_result != user.api_key {
-        return Err((StatusCode::UNAUTHORIZED, "Incorrect API Key".into()));
+    // Keys name their row, only keys issued before that get tried one by one.
+    let keys = match user::api_key_id(&api_key) {
+        Some(key_id) => match database::get_api_key(key_id, pool.clone()) {
+            Ok(key) if key.user_id.0 == user_id && key.is_valid() => vec![key],
+            _ => Vec::new(),
+        },
+        None => database::get_valid_api_keys(user_id, pool.clone()).map_err(|err| {
+            error!("Failed to database: {:?}", err.to_string());
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
+        })?,
+    };
+
+    // PBKDF2 is slow on purpose, keep it off the runtime's workers.
+    let presented = api_key.clone();
+    let found = tokio::task::spawn_blocking(move || {
+        keys.into_iter()
+            .find(|key| user::verify_api_key(&presented, &key.key_hash))
+    })
+    .await
+    .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    let mut key = match found {
+        Some(key) => key,
+        None => return Err((StatusCode::UNAUTHORIZED, "Incorrect API Key".into())),
     };
+
+    // Upgrade unsalted rows now that we have the clear text key.
+    if user::is_legacy_hash(&key.key_hash) {
+        let new_hash = user::hash_api_key(&api_key);
+        match database::set_api_key_hash(key.id.0, new_hash.clone(), pool) {
+            Ok(_) => key.key_hash = new_hash,
+            Err(err) => warn!("Failed to upgrade legacy key hash: {:?}", err),
+        }
+    }
+    Ok((user, key))
+}
+
+fn has_permission(user: &user::User, required: &str) -> bool {
     if user.perm_superuser.clone() {
-        return Ok(user);
+        return true;
     }
-    let auth = match required {
+    match required {
         "" => true,
         "load_llm" => user.perm_load_llm.clone(),
         "unload_llm" => user.perm_unload_llm.clone(),
@@ -277,11 +325,127 @@ fn user_permission_check(
         "view_llms" => user.perm_view_llms.clone(),
         "bare_model" => user.perm_bare_model.clone(),
         &_ => false,
+    }
+}
+
+// Attached to each route in build_server, read by `authenticate`.
+// "" means any authenticated user.
+#[derive(Debug, Clone, Copy)]
+struct RequiredPermission(&'static str);
+
+// Pre-header clients (pantry-rs 0.0.x) send credentials in the JSON body.
+#[derive(Debug, serde::Deserialize)]
+struct BodyCredentials {
+    user_id: String,
+    api_key: String,
+}
+
+// Authorization: Bearer <user_id>:<api_key>
+fn bearer_credentials(headers: &HeaderMap) -> Option<Result<(Uuid, String), (StatusCode, String)>> {
+    let value = headers.get(header::AUTHORIZATION)?;
+    let creds = value
+        .to_str()
+        .ok()
+        .and_then(|val| val.strip_prefix("Bearer "))
+        .and_then(|val| val.split_once(':'))
+        .ok_or((
+            StatusCode::UNAUTHORIZED,
+            "Malformed Authorization header, expected Bearer <user_id>:<api_key>".into(),
+        ))
+        .and_then(|(user_id, api_key)| {
+            Uuid::parse_str(user_id)
+                .map(|uuid| (uuid, api_key.to_string()))
+                .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+        });
+    Some(creds)
+}
+
+// Body credentials mean buffering the body before we know who's asking, so keep it small.
+// Requests that old never carried much more than a registry entry.
+const MAX_CREDENTIAL_BODY: usize = 64 * 1024;
+
+// How long a listener restart waits for in-flight requests before dropping the old listeners.
+const LISTENER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn read_credential_body(
+    headers: &HeaderMap,
+    mut body: Body,
+) -> Result<Vec<u8>, (StatusCode, String)> {
+    let too_large = || {
+        (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "Request bodies with credentials are limited to {} bytes, use the Authorization header.",
+                MAX_CREDENTIAL_BODY
+            ),
+        )
+    };
+    let declared = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|val| val.to_str().ok())
+        .and_then(|val| val.parse::<usize>().ok());
+    if declared.map_or(false, |length| length > MAX_CREDENTIAL_BODY) {
+        return Err(too_large());
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        if bytes.len() + chunk.len() > MAX_CREDENTIAL_BODY {
+            return Err(too_large());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+// Authenticates the caller and checks the route's RequiredPermission. On success
+// the User and the ApiKey they used are available to handlers as extensions.
+async fn authenticate(
+    State(state): State<state::GlobalStateWrapper>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Result<Response, (StatusCode, String)> {
+    let required = req
+        .extensions()
+        .get::<RequiredPermission>()
+        .copied()
+        .ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Route has no permission set".into(),
+        ))?;
+
+    let (mut req, (user_id, api_key)) = match bearer_credentials(req.headers()) {
+        Some(creds) => (req, creds?),
+        None => {
+            let (parts, body) = req.into_parts();
+            let bytes = read_credential_body(&parts.headers, body).await?;
+            let creds: BodyCredentials = serde_json::from_slice(&bytes).map_err(|_err| {
+                (
+                    StatusCode::UNAUTHORIZED,
+                    "Missing Authorization header".into(),
+                )
+            })?;
+            warn!(
+                "{} sent credentials in the request body, which is deprecated. Use the Authorization header.",
+                parts.uri
+            );
+            let user_id = Uuid::parse_str(&creds.user_id)
+                .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+            (
+                Request::from_parts(parts, Body::from(bytes)),
+                (user_id, creds.api_key),
+            )
+        }
     };
-    match auth {
-        true => Ok(user),
-        false => Err((StatusCode::UNAUTHORIZED, "Incorrect Permissions".into())),
+
+    let (user, key) = authenticate_key(api_key, user_id, state.pool.clone()).await?;
+    if !has_permission(&user, required.0) {
+        return Err((StatusCode::UNAUTHORIZED, "Incorrect Permissions".into()));
     }
+
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(key);
+    Ok(next.run(req).await)
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -296,9 +460,12 @@ async fn register_user(
 ) -> Result<Json<user::UserInfo>, (StatusCode, String)> {
     info!("Called register_user from API.");
     let user = user::User::new(payload.user_name);
-    match database::save_new_user(user.clone(), state.pool.clone()) {
-        // Small detail: we need to return the presave user to keep the raw api_key
-        Ok(_) => Ok(Json((&user).into())),
+    let (key, api_key) = user::ApiKey::new(user.id.clone(), "default".into(), None);
+    let saved = database::save_new_user(user, state.pool.clone())
+        .and_then(|user| database::save_new_api_key(key, state.pool.clone()).map(|_| user));
+    match saved {
+        // Small detail: the key only exists in clear text here, so we hand it back now.
+        Ok(user) => Ok(Json(user::UserInfo::new(&user, api_key))),
         Err(err) => {
             error!("Error creating user: {:?}", err.to_string());
             Err((
@@ -309,21 +476,145 @@ async fn register_user(
     }
 }
 
+#[axum_macros::debug_handler]
+async fn list_api_keys(
+    state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
+) -> Result<Json<Vec<user::ApiKeyInfo>>, (StatusCode, String)> {
+    info!("Called list_api_keys from API.");
+
+    let keys = database::get_api_keys_for_user(user.id.0, state.pool.clone()).map_err(|err| {
+        error!("Failed to database: {:?}", err.to_string());
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
+    })?;
+    Ok(Json(keys.iter().map(|key| key.into()).collect()))
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct CreateApiKeyRequest {
+    key_name: String,
+    expires: Option<DateTime<Utc>>,
+}
+
+#[axum_macros::debug_handler]
+async fn create_api_key(
+    state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> Result<Json<user::ApiKeyCreated>, (StatusCode, String)> {
+    info!("Called create_api_key from API.");
+
+    let (key, api_key) = user::ApiKey::new(user.id, payload.key_name, payload.expires);
+    let key = database::save_new_api_key(key, state.pool.clone()).map_err(|err| {
+        error!("failed to save to database because... {:?}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Error saving new key.".into(),
+        )
+    })?;
+    Ok(Json(user::ApiKeyCreated {
+        key_info: (&key).into(),
+        api_key,
+    }))
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct RotateApiKeyRequest {
+    // Defaults to the name of the key being rotated.
+    key_name: Option<String>,
+    expires: Option<DateTime<Utc>>,
+    // How long the old key keeps working. Unset revokes it immediately.
+    grace_period_seconds: Option<i64>,
+}
+
+// Self-service rotation: issues a new key and retires the one used to call this.
+#[axum_macros::debug_handler]
+async fn rotate_api_key(
+    state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
+    Extension(old_key): Extension<user::ApiKey>,
+    Json(payload): Json<RotateApiKeyRequest>,
+) -> Result<Json<user::ApiKeyCreated>, (StatusCode, String)> {
+    info!("Called rotate_api_key from API.");
+
+    let (key, api_key) = user::ApiKey::new(
+        user.id,
+        payload.key_name.unwrap_or(old_key.name.clone()),
+        payload.expires,
+    );
+    let key = database::save_new_api_key(key, state.pool.clone()).map_err(|err| {
+        error!("failed to save to database because... {:?}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Error saving new key.".into(),
+        )
+    })?;
+
+    let retired = match payload.grace_period_seconds {
+        Some(secs) if secs > 0 => database::set_api_key_expiry(
+            old_key.id.0,
+            Some(Utc::now() + chrono::Duration::seconds(secs)),
+            state.pool.clone(),
+        ),
+        _ => database::revoke_api_key(old_key.id.0, state.pool.clone()),
+    };
+    retired.map_err(|err| {
+        error!("failed to retire key because... {:?}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Error retiring old key.".into(),
+        )
+    })?;
+
+    Ok(Json(user::ApiKeyCreated {
+        key_info: (&key).into(),
+        api_key,
+    }))
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct RevokeApiKeyRequest {
+    key_id: String,
+}
+
+// Users can revoke their own keys, including the one they're calling with.
+#[axum_macros::debug_handler]
+async fn revoke_api_key(
+    state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
+    Json(payload): Json<RevokeApiKeyRequest>,
+) -> Result<Json<user::ApiKeyInfo>, (StatusCode, String)> {
+    info!("Called revoke_api_key from API.");
+    let key_uuid =
+        Uuid::parse_str(&payload.key_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let key = database::get_api_key(key_uuid, state.pool.clone())
+        .map_err(|_err| (StatusCode::NOT_FOUND, "Key Not Found".into()))?;
+    if key.user_id != user.id {
+        return Err((StatusCode::NOT_FOUND, "Key Not Found".into()));
+    }
+    database::revoke_api_key(key_uuid, state.pool.clone()).map_err(|err| {
+        error!("Failed to database: {:?}", err.to_string());
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
+    })?;
+    let key = database::get_api_key(key_uuid, state.pool.clone()).map_err(|err| {
+        error!("Failed to database: {:?}", err.to_string());
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
+    })?;
+    Ok(Json((&key).into()))
+}
+
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct RequestPermissionRequest {
-    user_id: String,
-    api_key: String,
     requested_permissions: user::Permissions,
 }
 #[axum_macros::debug_handler]
 async fn request_permissions(
     state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
     Json(payload): Json<RequestPermissionRequest>,
 ) -> Result<Json<UserRequest>, (StatusCode, String)> {
     info!("Called request_permissions from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let user = user_permission_check("", payload.api_key, user_uuid, state.pool.clone())?;
 
     let request = UserRequest {
         id: DbUuid(Uuid::new_v4()),
@@ -336,40 +627,34 @@ async fn request_permissions(
         }),
         complete: false,
         accepted: false,
+        decision_note: None,
+        granted_permissions: None,
     };
 
-    let req = database::save_new_request(request, state.pool.clone()).map_err(|err| {
-        error!("failed to save to database because... {:?}", err);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Error saving new request.".into(),
-        )
-    })?;
+    let req = request::submit_request(request, &state.0)
+        .await
+        .map_err(|err| {
+            error!("failed to save to database because... {:?}", err);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Error saving new request.".into(),
+            )
+        })?;
     Ok(Json(req))
     // Ok(Json(request.id.to_string()))
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct RequestDownloadRequest {
-    user_id: String,
-    api_key: String,
     llm_registry_entry: registry::LLMRegistryEntry,
 }
 #[axum_macros::debug_handler]
 async fn request_download(
     state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
     Json(payload): Json<RequestDownloadRequest>,
 ) -> Result<Json<UserRequest>, (StatusCode, String)> {
     info!("Called request_download from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-
-    let user = user_permission_check(
-        "request_download",
-        payload.api_key,
-        user_uuid,
-        state.pool.clone(),
-    )?;
 
     let request = UserRequest {
         id: DbUuid(Uuid::new_v4()),
@@ -382,38 +667,33 @@ async fn request_download(
         }),
         complete: false,
         accepted: false,
+        decision_note: None,
+        granted_permissions: None,
     };
-    let req = database::save_new_request(request, state.pool.clone()).map_err(|err| {
-        error!("failed to save to database because... {:?}", err);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Error saving new request.".into(),
-        )
-    })?;
+    let req = request::submit_request(request, &state.0)
+        .await
+        .map_err(|err| {
+            error!("failed to save to database because... {:?}", err);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Error saving new request.".into(),
+            )
+        })?;
     Ok(Json(req))
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct RequestLoadRequest {
-    user_id: String,
-    api_key: String,
     llm_id: String,
 }
 
 #[axum_macros::debug_handler]
 async fn request_load(
     state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
     Json(payload): Json<RequestLoadRequest>,
 ) -> Result<Json<UserRequest>, (StatusCode, String)> {
     info!("Called request_load from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let user = user_permission_check(
-        "request_load_llm",
-        payload.api_key,
-        user_uuid,
-        state.pool.clone(),
-    )?;
 
     let request = UserRequest {
         id: DbUuid(Uuid::new_v4()),
@@ -426,38 +706,33 @@ async fn request_load(
         }),
         complete: false,
         accepted: false,
+        decision_note: None,
+        granted_permissions: None,
     };
 
-    let req = database::save_new_request(request, state.pool.clone()).map_err(|err| {
-        error!("failed to save to database because... {:?}", err);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Error saving new request.".into(),
-        )
-    })?;
+    let req = request::submit_request(request, &state.0)
+        .await
+        .map_err(|err| {
+            error!("failed to save to database because... {:?}", err);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Error saving new request.".into(),
+            )
+        })?;
     Ok(Json(req))
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct RequestUnloadRequest {
-    user_id: String,
-    api_key: String,
     llm_id: String,
 }
 #[axum_macros::debug_handler]
 async fn request_unload(
     state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
     Json(payload): Json<RequestUnloadRequest>,
 ) -> Result<Json<UserRequest>, (StatusCode, String)> {
     info!("Called request_unload from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let user = user_permission_check(
-        "request_unload_llm",
-        payload.api_key,
-        user_uuid,
-        state.pool.clone(),
-    )?;
 
     let request = UserRequest {
         id: DbUuid(Uuid::new_v4()),
@@ -470,54 +745,137 @@ async fn request_unload(
         }),
         complete: false,
         accepted: false,
+        decision_note: None,
+        granted_permissions: None,
     };
-    let req = database::save_new_request(request, state.pool.clone()).map_err(|err| {
-        error!("failed to save to database because... {:?}", err);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Error saving new request.".into(),
-        )
-    })?;
+    let req = request::submit_request(request, &state.0)
+        .await
+        .map_err(|err| {
+            error!("failed to save to database because... {:?}", err);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Error saving new request.".into(),
+            )
+        })?;
     Ok(Json(req))
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct RequestStatusRequest {
-    user_id: String,
-    api_key: String,
     request_id: String,
 }
 
+// Requests are only visible to the user who made them.
+fn own_request(
+    request_uuid: Uuid,
+    user: &user::User,
+    state: &state::GlobalStateWrapper,
+) -> Result<UserRequest, (StatusCode, String)> {
+    let req = database::get_request(request_uuid, state.pool.clone()).map_err(|_err| {
+        error!("didn't find {:?}", request_uuid);
+        (StatusCode::NOT_FOUND, "Request Not Found".into())
+    })?;
+    if user.id != req.user_id {
+        error!(
+            "uuid didn't match find {:?} vs {:?}",
+            user.id.0, req.user_id.0
+        );
+        return Err((StatusCode::NOT_FOUND, "Request Not Found".into()));
+    }
+    Ok(req)
+}
+
 #[axum_macros::debug_handler]
 async fn request_status(
     state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
     Json(payload): Json<RequestStatusRequest>,
 ) -> Result<Json<UserRequestStatus>, (StatusCode, String)> {
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
     let request_uuid = Uuid::parse_str(&payload.request_id)
         .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let _user = user_permission_check("", payload.api_key.clone(), user_uuid, state.pool.clone())?;
+    request::expire_requests(&state.0).map_err(|err| {
+        error!("Failed to database: {:?}", err);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
+    })?;
 
-    let req = database::get_request(request_uuid, state.pool.clone()).map_err(|_err| {
-        error!("didn't find {:?}", request_uuid);
-        (StatusCode::NOT_FOUND, "Request Not Found".into())
+    let req = own_request(request_uuid, &user, &state.0)?;
+    Ok(Json((&req).into()))
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct WaitRequestRequest {
+    request_id: String,
+    // Defaults to 30, at most 300.
+    timeout_seconds: Option<u64>,
+}
+
+// Long-poll version of request_status, returns once the request is decided or the timeout
+// runs out. Either way the status says which, clients call again while complete is false.
+#[axum_macros::debug_handler]
+async fn wait_request(
+    state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
+    Json(payload): Json<WaitRequestRequest>,
+) -> Result<Json<UserRequestStatus>, (StatusCode, String)> {
+    info!("Called wait_request from API.");
+    let request_uuid = Uuid::parse_str(&payload.request_id)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    // Subscribe first so a decision made while we look it up isn't missed.
+    let mut events = state.request_events.subscribe();
+    request::expire_requests(&state.0).map_err(|err| {
+        error!("Failed to database: {:?}", err);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
     })?;
-    if user_uuid != req.user_id.0 {
-        error!(
-            "uuid didn't match find {:?} vs {:?}",
-            user_uuid, req.user_id.0
-        );
-        return Err((StatusCode::NOT_FOUND, "Request Not Found".into()));
+
+    let req = own_request(request_uuid, &user, &state.0)?;
+    if !req.complete {
+        let timeout = Duration::from_secs(payload.timeout_seconds.unwrap_or(30).min(300));
+        let _ = tokio::time::timeout(timeout, async {
+            loop {
+                match events.recv().await {
+                    Ok(event) if event.request_id == request_uuid && event.is_decision() => break,
+                    Ok(_) => {}
+                    // If we fell behind we may have missed it, the lookup below will tell.
+                    Err(_) => break,
+                }
+            }
+        })
+        .await;
     }
 
+    let req = own_request(request_uuid, &user, &state.0)?;
     Ok(Json((&req).into()))
 }
 
+// Server-sent events for the caller's requests: decisions, then completed or failed once an
+// accepted download or load is done. Superusers get every user's.
+#[axum_macros::debug_handler]
+async fn request_events(
+    state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
+) -> Sse<impl Stream<Item = Result<Event, serde_json::Error>>> {
+    info!("Called request_events from API.");
+    let (user_id, superuser) = (user.id.0, user.perm_superuser);
+    let events = state.request_events.subscribe();
+    let event_stream = futures_util::stream::unfold(events, move |mut events| async move {
+        loop {
+            match events.recv().await {
+                Ok(event) if superuser || event.user_id == user_id => {
+                    return Some((Event::default().json_data(event), events))
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("Request event stream missed {} events", missed)
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(event_stream).keep_alive(KeepAlive::default())
+}
+
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct RequestLoadFlexRequest {
-    user_id: String,
-    api_key: String,
     llm_id: String,
     filter: Option<LLMFilter>,
     preference: Option<LLMPreference>,
@@ -526,17 +884,10 @@ struct RequestLoadFlexRequest {
 #[axum_macros::debug_handler]
 async fn request_load_flex(
     state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
     Json(payload): Json<RequestLoadFlexRequest>,
 ) -> Result<Json<UserRequest>, (StatusCode, String)> {
     info!("Called request_load_flex from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let _user = user_permission_check(
-        "request_load_llm",
-        payload.api_key.clone(),
-        user_uuid,
-        state.pool.clone(),
-    )?;
 
     let mut llms = database::get_available_llms(state.pool.clone()).map_err(|err| {
         error!("failed to save to database because... {:?}", err);
@@ -604,9 +955,8 @@ async fn request_load_flex(
     } else if llms.len() == 1 {
         return request_load(
             state,
+            Extension(user),
             Json(RequestLoadRequest {
-                user_id: payload.user_id,
-                api_key: payload.api_key,
                 llm_id: llms.pop().unwrap().uuid.0.to_string(),
             }),
         )
@@ -620,9 +970,8 @@ async fn request_load_flex(
             if let Some(_found) = llms.iter().find(|llm| llm.uuid.0 == uuid_pref) {
                 return request_load(
                     state,
+                    Extension(user),
                     Json(RequestLoadRequest {
-                        user_id: payload.user_id,
-                        api_key: payload.api_key,
                         llm_id: llms.pop().unwrap().uuid.0.to_string(),
                     }),
                 )
@@ -635,9 +984,8 @@ async fn request_load_flex(
             if let Some(_found) = llms.iter().find(|llm| llm.id == id_pref) {
                 return request_load(
                     state,
+                    Extension(user),
                     Json(RequestLoadRequest {
-                        user_id: payload.user_id,
-                        api_key: payload.api_key,
                         llm_id: llms.pop().unwrap().uuid.0.to_string(),
                     }),
                 )
@@ -697,9 +1045,8 @@ async fn request_load_flex(
     }
     return request_load(
         state,
+        Extension(user),
         Json(RequestLoadRequest {
-            user_id: payload.user_id,
-            api_key: payload.api_key,
             llm_id: llms.pop().unwrap().uuid.0.to_string(),
         }),
     )
@@ -708,8 +1055,6 @@ async fn request_load_flex(
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct GetLLMStatusRequest {
-    user_id: String,
-    api_key: String,
     llm_id: String,
 }
 
@@ -719,11 +1064,8 @@ async fn get_llm_status(
     Json(payload): Json<GetLLMStatusRequest>,
 ) -> Result<Json<LLMStatus>, (StatusCode, String)> {
     info!("Called get_llm_status from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
     let llm_id =
         Uuid::parse_str(&payload.llm_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let _user = user_permission_check("view_llms", payload.api_key, user_uuid, state.pool.clone())?;
 
     if let Some(downloading_llm) = state.downloading_llms.get(&llm_id) {
         let llm_stat: LLMStatus = (downloading_llm.value()).into();
@@ -743,21 +1085,11 @@ async fn get_llm_status(
     Ok(Json((&llm).into()))
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-pub struct GetAvailableLLMRequest {
-    user_id: String,
-    api_key: String,
-}
-
 #[axum_macros::debug_handler]
 async fn get_available_llms(
     state: State<state::GlobalStateWrapper>,
-    Json(payload): Json<GetAvailableLLMRequest>,
 ) -> Result<Json<Vec<LLMStatus>>, (StatusCode, String)> {
     info!("Called get_available_llms from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let _user = user_permission_check("view_llms", payload.api_key, user_uuid, state.pool.clone())?;
     let llms = database::get_available_llms(state.pool.clone()).map_err(|err| {
         error!("Failed to database: {:?}", err.to_string());
         (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
@@ -766,21 +1098,11 @@ async fn get_available_llms(
     Ok(Json(llms.iter().map(|val| (val).into()).collect()))
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-pub struct GetRunningLLMRequest {
-    user_id: String,
-    api_key: String,
-}
-
 #[axum_macros::debug_handler]
 async fn get_running_llms(
     state: State<state::GlobalStateWrapper>,
-    Json(payload): Json<GetRunningLLMRequest>,
 ) -> Result<Json<Vec<LLMStatus>>, (StatusCode, String)> {
     info!("Called get_running_llms from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let _user = user_permission_check("view_llms", payload.api_key, user_uuid, state.pool.clone())?;
     let llms: Vec<LLMStatus> = state
         .activated_llms
         .iter()
@@ -791,8 +1113,6 @@ async fn get_running_llms(
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct InterruptSessionRequest {
-    user_id: String,
-    api_key: String,
     llm_uuid: String,
     session_id: String,
 }
@@ -800,16 +1120,14 @@ pub struct InterruptSessionRequest {
 #[axum_macros::debug_handler]
 async fn interrupt_session(
     state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
     Json(payload): Json<InterruptSessionRequest>,
 ) -> Result<Json<LLMRunningStatus>, (StatusCode, String)> {
     info!("Called interrupt_session from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
     let llm_id =
         Uuid::parse_str(&payload.llm_uuid).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
     let session_id = Uuid::parse_str(&payload.session_id)
         .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let user = user_permission_check("session", payload.api_key, user_uuid, state.pool.clone())?;
     let llm = state
         .activated_llms
         .get(&llm_id)
@@ -851,7 +1169,7 @@ async fn llm_loading_assistant(
         path,
         settings,
         state.pool.clone(),
-        state.handle.clone(),
+        state.emitter.clone(),
     )
     .await;
     // new_llm.load();
@@ -870,9 +1188,10 @@ async fn llm_loading_assistant(
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct LoadLLMRequest {
-    user_id: String,
-    api_key: String,
     llm_id: String,
+    // Answer once the model is loaded (or failed to), instead of once loading started.
+    #[serde(default)]
+    wait: bool,
 }
 #[axum_macros::debug_handler]
 async fn load_llm(
@@ -880,10 +1199,6 @@ async fn load_llm(
     Json(payload): Json<LoadLLMRequest>,
 ) -> Result<Json<LLMRunningStatus>, (StatusCode, String)> {
     info!("Called load_llm from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-
-    let _user = user_permission_check("load_llm", payload.api_key, user_uuid, state.pool.clone())?;
 
     let count = database::count_llm_by_pub_id(payload.llm_id.clone(), state.pool.clone()).map_err(
         |err| {
@@ -907,13 +1222,18 @@ async fn load_llm(
             _ => (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into()),
         })?;
     };
-    llm_loading_assistant(state, new_llm).await
+    let new_uuid = new_llm.uuid.0;
+    let status = llm_loading_assistant(state.clone(), new_llm).await?;
+    if payload.wait {
+        state::wait_until_loaded(new_uuid, &state.0)
+            .await
+            .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))?;
+    }
+    Ok(status)
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct LoadLLMFlexRequest {
-    user_id: String,
-    api_key: String,
     filter: Option<LLMFilter>,
     preference: Option<LLMPreference>,
 }
@@ -924,9 +1244,6 @@ async fn load_llm_flex(
     Json(payload): Json<LoadLLMFlexRequest>,
 ) -> Result<Json<LLMRunningStatus>, (StatusCode, String)> {
     info!("Called load_llm_flex from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let _user = user_permission_check("load_llm", payload.api_key, user_uuid, state.pool.clone())?;
     // We should use currently running LLMs.
     let mut llms = database::get_available_llms(state.pool.clone()).map_err(|err| {
         error!("Failed to database: {:?}", err.to_string());
@@ -1066,8 +1383,6 @@ async fn load_llm_flex(
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct UnloadLLMRequest {
-    user_id: String,
-    api_key: String,
     llm_id: String,
 }
 #[axum_macros::debug_handler]
@@ -1076,11 +1391,6 @@ async fn unload_llm(
     Json(payload): Json<UnloadLLMRequest>,
 ) -> Result<Json<LLMStatus>, (StatusCode, String)> {
     info!("Called unload_llm from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    // let user = state
-    let _user =
-        user_permission_check("unload_llm", payload.api_key, user_uuid, state.pool.clone())?;
     let llm_uuid = match Uuid::parse_str(&payload.llm_id) {
         Ok(id) => id,
         Err(_) => {
@@ -1121,9 +1431,10 @@ async fn unload_llm(
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct DownloadLLMRequest {
-    user_id: String,
-    api_key: String,
     llm_registry_entry: registry::LLMRegistryEntry,
+    // Only for Hugging Face entries, see /list_quantizations.
+    #[serde(default)]
+    quantization: Option<String>,
 }
 #[axum_macros::debug_handler]
 async fn download_llm(
@@ -1131,22 +1442,14 @@ async fn download_llm(
     Json(payload): Json<DownloadLLMRequest>,
 ) -> Result<Json<Value>, (StatusCode, String)> {
     info!("Called download_llm from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let _user = user_permission_check(
-        "download_llm",
-        payload.api_key,
-        user_uuid,
-        state.pool.clone(),
-    )?;
 
     let uuid = Uuid::new_v4();
 
     let _id = payload.llm_registry_entry.id.clone();
+    let llm_reg = huggingface::with_quantization(payload.llm_registry_entry, payload.quantization);
 
     tokio::spawn(async move {
-        registry::download_and_write_llm(payload.llm_registry_entry, uuid, state.handle.clone())
-            .await;
+        registry::download_and_write_llm(llm_reg, uuid, state.0.clone()).await;
     });
 
     Ok(Json(uuid.to_string().into()))
@@ -1154,9 +1457,9 @@ async fn download_llm(
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct GetOrDownloadLLMRequest {
-    user_id: String,
-    api_key: String,
     llm_registry_entry: registry::LLMRegistryEntry,
+    #[serde(default)]
+    quantization: Option<String>,
 }
 #[axum_macros::debug_handler]
 async fn get_or_download_llm(
@@ -1164,16 +1467,22 @@ async fn get_or_download_llm(
     Json(payload): Json<GetOrDownloadLLMRequest>,
 ) -> Result<Json<Value>, (StatusCode, String)> {
     info!("Called get_or_download_llm from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let _user = user_permission_check(
-        "download_llm",
-        payload.api_key,
-        user_uuid,
-        state.pool.clone(),
-    )?;
 
-    let llm_opt = database::get_equal_llm(payload.llm_registry_entry.clone(), state.pool.clone())
+    // Hugging Face entries only have a url to compare once resolved.
+    let endpoint = state
+        .user_settings
+        .read()
+        .unwrap()
+        .huggingface_endpoint
+        .clone();
+    let llm_reg = huggingface::resolve(
+        huggingface::with_quantization(payload.llm_registry_entry, payload.quantization),
+        &endpoint,
+    )
+    .await
+    .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+
+    let llm_opt = database::get_equal_llm(llm_reg.clone(), state.pool.clone())
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     if let Some(llm) = llm_opt {
@@ -1182,32 +1491,520 @@ async fn get_or_download_llm(
 
     let uuid = Uuid::new_v4();
 
-    let _id = payload.llm_registry_entry.id.clone();
+    let _id = llm_reg.id.clone();
 
     tokio::spawn(async move {
-        registry::download_and_write_llm(payload.llm_registry_entry, uuid, state.handle.clone())
-            .await;
+        registry::download_and_write_llm(llm_reg, uuid, state.0.clone()).await;
     });
 
     Ok(Json(uuid.to_string().into()))
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
-struct CreateSessionRequest {
-    user_id: String,
-    api_key: String,
-    user_session_parameters: HashMap<String, Value>,
+struct ListQuantizationsRequest {
+    llm_registry_entry: registry::LLMRegistryEntry,
+}
+#[axum_macros::debug_handler]
+async fn list_quantizations(
+    state: State<state::GlobalStateWrapper>,
+    Json(payload): Json<ListQuantizationsRequest>,
+) -> Result<Json<Vec<huggingface::HuggingFaceFile>>, (StatusCode, String)> {
+    info!("Called list_quantizations from API.");
+    let endpoint = state
+        .user_settings
+        .read()
+        .unwrap()
+        .huggingface_endpoint
+        .clone();
+    huggingface::list_quantizations(&payload.llm_registry_entry, &endpoint)
+        .await
+        .map(Json)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))
 }
 
+// Paths are on the pantry host, so only superusers get to import.
 #[axum_macros::debug_handler]
-async fn create_session(
+async fn import_llm(
     state: State<state::GlobalStateWrapper>,
-    Json(payload): Json<CreateSessionRequest>,
-) -> Result<Json<CreateSessionResponse>, (StatusCode, String)> {
-    info!("Called create_session from API.");
-    let _user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    // let user = state
+    Json(payload): Json<import::ImportRequest>,
+) -> Result<Json<LLMStatus>, (StatusCode, String)> {
+    info!("Called import_llm from API.");
+    let state = state.0.clone();
+    // Hashing a multi-gigabyte file takes a while, keep it off the runtime.
+    let llm = tokio::task::spawn_blocking(move || import::import_llm(payload, &state))
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    Ok(Json((&llm).into()))
+}
+
+#[axum_macros::debug_handler]
+async fn list_downloads(
+    state: State<state::GlobalStateWrapper>,
+) -> Result<Json<Vec<registry::DownloadInfo>>, (StatusCode, String)> {
+    info!("Called list_downloads from API.");
+    registry::list_downloads(&state.0).map(Json).map_err(|err| {
+        error!("{}", err);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
+    })
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct DownloadIdRequest {
+    download_id: String,
+}
+
+// The registry functions only fail on bad ids or a download in the wrong state.
+fn download_control(
+    payload: DownloadIdRequest,
+    state: &state::GlobalStateWrapper,
+    action: fn(Uuid, &state::GlobalStateWrapper) -> Result<registry::DownloadInfo, String>,
+) -> Result<Json<registry::DownloadInfo>, (StatusCode, String)> {
+    let download_uuid = Uuid::parse_str(&payload.download_id)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    action(download_uuid, state)
+        .map(Json)
+        .map_err(|err| (StatusCode::CONFLICT, err))
+}
+
+#[axum_macros::debug_handler]
+async fn cancel_download(
+    state: State<state::GlobalStateWrapper>,
+    Json(payload): Json<DownloadIdRequest>,
+) -> Result<Json<registry::DownloadInfo>, (StatusCode, String)> {
+    info!("Called cancel_download from API.");
+    download_control(payload, &state.0, registry::cancel_download)
+}
+
+#[axum_macros::debug_handler]
+async fn pause_download(
+    state: State<state::GlobalStateWrapper>,
+    Json(payload): Json<DownloadIdRequest>,
+) -> Result<Json<registry::DownloadInfo>, (StatusCode, String)> {
+    info!("Called pause_download from API.");
+    download_control(payload, &state.0, registry::pause_download)
+}
+
+#[axum_macros::debug_handler]
+async fn resume_download(
+    state: State<state::GlobalStateWrapper>,
+    Json(payload): Json<DownloadIdRequest>,
+) -> Result<Json<registry::DownloadInfo>, (StatusCode, String)> {
+    info!("Called resume_download from API.");
+    download_control(payload, &state.0, registry::resume_download)
+}
+
+// Try parsing UUID, if it succeeds, use UUID, otherwise use pub id.
+fn find_llm(llm_id: &str, state: &state::GlobalStateWrapper) -> Result<LLM, (StatusCode, String)> {
+    match Uuid::parse_str(llm_id) {
+        Ok(llm_uuid) => database::get_llm(llm_uuid, state.pool.clone()),
+        Err(_) => database::get_llm_pub_id(llm_id.to_string(), state.pool.clone()),
+    }
+    .map_err(|_err| (StatusCode::NOT_FOUND, "Unable to find LLM".into()))
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct DeleteLLMRequest {
+    llm_id: String,
+}
+
+// Refuses running LLMs, model files are only removed once nothing else uses them.
+#[axum_macros::debug_handler]
+async fn delete_llm(
+    state: State<state::GlobalStateWrapper>,
+    Json(payload): Json<DeleteLLMRequest>,
+) -> Result<Json<disk::FreedSpace>, (StatusCode, String)> {
+    info!("Called delete_llm from API.");
+    let llm = find_llm(&payload.llm_id, &state.0)?;
+    disk::delete_llm(llm.uuid.0, &state.0)
+        .map(Json)
+        .map_err(|err| (StatusCode::CONFLICT, err))
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct ListDownloadableRequest {
+    #[serde(default)]
+    refresh: bool,
+    // Only list this registry, otherwise the most trusted entry for each id.
+    registry: Option<String>,
+}
+
+#[axum_macros::debug_handler]
+async fn list_downloadable_llms(
+    state: State<state::GlobalStateWrapper>,
+    payload: Option<Json<ListDownloadableRequest>>,
+) -> Result<Json<Vec<registry::DownloadableLLM>>, (StatusCode, String)> {
+    info!("Called list_downloadable_llms from API.");
+    let (refresh, registry_name) = payload.map_or((false, None), |Json(payload)| {
+        (payload.refresh, payload.registry)
+    });
+    match registry_name {
+        Some(name) => registry::registry_llms(name, refresh, state.pool.clone())
+            .await
+            .map_err(|err| (StatusCode::NOT_FOUND, err)),
+        None => registry::downloadable_llms(refresh, state.pool.clone())
+            .await
+            .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err)),
+    }
+    .map(Json)
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct ListUpgradesRequest {
+    #[serde(default)]
+    refresh: bool,
+}
+
+#[axum_macros::debug_handler]
+async fn list_upgrades(
+    state: State<state::GlobalStateWrapper>,
+    payload: Option<Json<ListUpgradesRequest>>,
+) -> Result<Json<Vec<upgrade::UpgradeInfo>>, (StatusCode, String)> {
+    info!("Called list_upgrades from API.");
+    let refresh = payload.map_or(false, |Json(payload)| payload.refresh);
+    upgrade::available_upgrades(refresh, &state.0)
+        .await
+        .map(Json)
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct UpgradeLLMRequest {
+    llm_id: String,
+    // Install the new version next to the old one instead of replacing it.
+    #[serde(default)]
+    keep_old: bool,
+}
+
+#[axum_macros::debug_handler]
+async fn upgrade_llm(
+    state: State<state::GlobalStateWrapper>,
+    Json(payload): Json<UpgradeLLMRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    info!("Called upgrade_llm from API.");
+    let llm = find_llm(&payload.llm_id, &state.0)?;
+    let new_uuid = upgrade::upgrade_llm(llm.uuid.0, payload.keep_old, &state.0)
+        .await
+        .map_err(|err| (StatusCode::CONFLICT, err))?;
+    Ok(Json(new_uuid.to_string().into()))
+}
+
+#[axum_macros::debug_handler]
+async fn disk_usage(
+    state: State<state::GlobalStateWrapper>,
+) -> Result<Json<disk::DiskReport>, (StatusCode, String)> {
+    info!("Called disk_usage from API.");
+    disk::disk_usage(&state.0)
+        .map(Json)
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))
+}
+
+#[axum_macros::debug_handler]
+async fn collect_garbage(
+    state: State<state::GlobalStateWrapper>,
+) -> Result<Json<disk::FreedSpace>, (StatusCode, String)> {
+    info!("Called collect_garbage from API.");
+    disk::collect_garbage(&state.0)
+        .map(Json)
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))
+}
+
+// Admin view of a request, with who made it and why.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct RequestAdminStatus {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub originator: String,
+    pub reason: String,
+    pub timestamp: DateTime<Utc>,
+    pub request: UserRequestType,
+    pub complete: bool,
+    pub accepted: bool,
+    pub decision_note: Option<String>,
+    pub granted_permissions: Option<user::Permissions>,
+}
+
+impl From<&UserRequest> for RequestAdminStatus {
+    fn from(req: &UserRequest) -> Self {
+        RequestAdminStatus {
+            id: req.id.0.clone(),
+            user_id: req.user_id.0.clone(),
+            originator: req.originator.clone(),
+            reason: req.reason.clone(),
+            timestamp: req.timestamp.clone(),
+            request: req.request.clone(),
+            complete: req.complete.clone(),
+            accepted: req.accepted.clone(),
+            decision_note: req.decision_note.clone(),
+            granted_permissions: req.granted_permissions.clone(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct ListRequestsRequest {
+    // Decided requests are left out unless this is set.
+    #[serde(default)]
+    all: bool,
+}
+
+#[axum_macros::debug_handler]
+async fn list_requests(
+    state: State<state::GlobalStateWrapper>,
+    payload: Option<Json<ListRequestsRequest>>,
+) -> Result<Json<Vec<RequestAdminStatus>>, (StatusCode, String)> {
+    info!("Called list_requests from API.");
+    let all = payload.map_or(false, |Json(payload)| payload.all);
+    request::expire_requests(&state.0).map_err(|err| {
+        error!("Failed to database: {:?}", err);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
+    })?;
+    let requests = database::get_requests(state.pool.clone()).map_err(|err| {
+        error!("Failed to database: {:?}", err.to_string());
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
+    })?;
+    Ok(Json(
+        requests
+            .iter()
+            .filter(|req| all || !req.complete)
+            .map(|req| req.into())
+            .collect(),
+    ))
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct DecideRequestRequest {
+    request_id: String,
+    // Passed on to the requesting program, e.g. why it was rejected.
+    note: Option<String>,
+    // Accepting a permission request can grant a subset of what was requested.
+    granted_permissions: Option<user::Permissions>,
+}
+
+fn get_request_admin(
+    request_id: &str,
+    state: &state::GlobalStateWrapper,
+) -> Result<RequestAdminStatus, (StatusCode, String)> {
+    let req_uuid =
+        Uuid::parse_str(request_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    database::get_request(req_uuid, state.pool.clone())
+        .map(|req| (&req).into())
+        .map_err(|_err| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("No request with id {}", request_id),
+            )
+        })
+}
+
+#[axum_macros::debug_handler]
+async fn accept_request(
+    state: State<state::GlobalStateWrapper>,
+    Json(payload): Json<DecideRequestRequest>,
+) -> Result<Json<RequestAdminStatus>, (StatusCode, String)> {
+    info!("Called accept_request from API.");
+    let req = get_request_admin(&payload.request_id, &state.0)?;
+    request::accept_request(req.id, payload.note, payload.granted_permissions, &state.0)
+        .await
+        .map_err(|err| (StatusCode::CONFLICT, err))?;
+    get_request_admin(&payload.request_id, &state.0).map(Json)
+}
+
+#[axum_macros::debug_handler]
+async fn reject_request(
+    state: State<state::GlobalStateWrapper>,
+    Json(payload): Json<DecideRequestRequest>,
+) -> Result<Json<RequestAdminStatus>, (StatusCode, String)> {
+    info!("Called reject_request from API.");
+    let req = get_request_admin(&payload.request_id, &state.0)?;
+    if payload.granted_permissions.is_some() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Rejected requests don't grant anything.".into(),
+        ));
+    }
+    request::reject_request(req.id, payload.note, &state.0)
+        .map_err(|err| (StatusCode::CONFLICT, err))?;
+    get_request_admin(&payload.request_id, &state.0).map(Json)
+}
+
+// Keys come without their hashes.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct UserAdminStatus {
+    pub id: Uuid,
+    pub name: String,
+    pub permissions: user::Permissions,
+    pub keys: Vec<user::ApiKeyInfo>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct UserDetailsStatus {
+    #[serde(flatten)]
+    pub user: UserAdminStatus,
+    pub requests: Vec<RequestAdminStatus>,
+}
+
+fn user_admin_status(
+    u: &user::User,
+    state: &state::GlobalStateWrapper,
+) -> Result<UserAdminStatus, (StatusCode, String)> {
+    let keys = database::get_api_keys_for_user(u.id.0, state.pool.clone()).map_err(|err| {
+        error!("Failed to database: {:?}", err.to_string());
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
+    })?;
+    Ok(UserAdminStatus {
+        id: u.id.0.clone(),
+        name: u.name.clone(),
+        permissions: u.into(),
+        keys: keys.iter().map(|key| key.into()).collect(),
+    })
+}
+
+fn get_user_admin(
+    user_id: &str,
+    state: &state::GlobalStateWrapper,
+) -> Result<user::User, (StatusCode, String)> {
+    let user_uuid =
+        Uuid::parse_str(user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    database::get_user(user_uuid, state.pool.clone()).map_err(|_err| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("No user with id {}", user_id),
+        )
+    })
+}
+
+#[axum_macros::debug_handler]
+async fn list_users(
+    state: State<state::GlobalStateWrapper>,
+) -> Result<Json<Vec<UserAdminStatus>>, (StatusCode, String)> {
+    info!("Called list_users from API.");
+    let users = database::get_users(state.pool.clone()).map_err(|err| {
+        error!("Failed to database: {:?}", err.to_string());
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
+    })?;
+    users
+        .iter()
+        .map(|u| user_admin_status(u, &state.0))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Json)
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct ShowUserRequest {
+    user_id: String,
+}
+
+#[axum_macros::debug_handler]
+async fn show_user(
+    state: State<state::GlobalStateWrapper>,
+    Json(payload): Json<ShowUserRequest>,
+) -> Result<Json<UserDetailsStatus>, (StatusCode, String)> {
+    info!("Called show_user from API.");
+    let u = get_user_admin(&payload.user_id, &state.0)?;
+    let requests = database::get_requests_for_user(u.id.0, state.pool.clone()).map_err(|err| {
+        error!("Failed to database: {:?}", err.to_string());
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
+    })?;
+    Ok(Json(UserDetailsStatus {
+        user: user_admin_status(&u, &state.0)?,
+        requests: requests.iter().map(|req| req.into()).collect(),
+    }))
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct RevokeUserKeysRequest {
+    user_id: String,
+    // Only this key, otherwise all of the user's keys.
+    #[serde(default)]
+    key_id: Option<String>,
+}
+
+// Returns how many keys were revoked.
+#[axum_macros::debug_handler]
+async fn revoke_user_keys(
+    state: State<state::GlobalStateWrapper>,
+    Json(payload): Json<RevokeUserKeysRequest>,
+) -> Result<Json<usize>, (StatusCode, String)> {
+    info!("Called revoke_user_keys from API.");
+    let u = get_user_admin(&payload.user_id, &state.0)?;
+    if u.id == user::get_local_user().id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "The local user's keys can't be revoked over the API.".into(),
+        ));
+    }
+    let result = match payload.key_id {
+        Some(key_id) => {
+            let key_uuid =
+                Uuid::parse_str(&key_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+            match database::get_api_key(key_uuid, state.pool.clone()) {
+                Ok(key) if key.user_id == u.id => {
+                    database::revoke_api_key(key_uuid, state.pool.clone())
+                }
+                _ => {
+                    return Err((
+                        StatusCode::NOT_FOUND,
+                        format!("User {} has no key {}", payload.user_id, key_id),
+                    ))
+                }
+            }
+        }
+        None => database::revoke_api_keys_for_user(u.id.0, state.pool.clone()),
+    };
+    result.map(Json).map_err(|err| {
+        error!("Failed to database: {:?}", err.to_string());
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
+    })
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct SetUserPermissionsRequest {
+    user_id: String,
+    // Only the permissions given change, e.g. {"session": true, "perm_bare_model": false}.
+    permissions: HashMap<String, bool>,
+}
+
+#[axum_macros::debug_handler]
+async fn set_user_permissions(
+    state: State<state::GlobalStateWrapper>,
+    Json(payload): Json<SetUserPermissionsRequest>,
+) -> Result<Json<UserAdminStatus>, (StatusCode, String)> {
+    info!("Called set_user_permissions from API.");
+    let u = get_user_admin(&payload.user_id, &state.0)?;
+    if u.id == user::get_local_user().id {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "The local user's permissions can't be changed.".into(),
+        ));
+    }
+    let mut permissions: user::Permissions = (&u).into();
+    for (name, value) in payload.permissions.iter() {
+        permissions
+            .set(name, *value)
+            .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    }
+    database::update_permissions(u.id.0, permissions, state.pool.clone()).map_err(|err| {
+        error!("Failed to database: {:?}", err.to_string());
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
+    })?;
+    let u = get_user_admin(&payload.user_id, &state.0)?;
+    user_admin_status(&u, &state.0).map(Json)
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct CreateSessionRequest {
+    user_session_parameters: HashMap<String, Value>,
+}
+
+#[axum_macros::debug_handler]
+async fn create_session(
+    state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
+    Json(payload): Json<CreateSessionRequest>,
+) -> Result<Json<CreateSessionResponse>, (StatusCode, String)> {
+    info!("Called create_session from API.");
+    // let user = state
     //     .registered_users
     //     .get(&user_uuid)
     //     .ok_or((StatusCode::UNAUTHORIZED, "Invalid User".into()))?;
@@ -1216,9 +2013,8 @@ async fn create_session(
     // create_session_flex(state, user_id, api_key, None, None, user_session_parameters).await
     create_session_flex(
         state,
+        Extension(user),
         Json(CreateSessionFlexRequest {
-            user_id: payload.user_id,
-            api_key: payload.api_key,
             filter: None,
             preference: None,
             user_session_parameters: payload.user_session_parameters,
@@ -1229,14 +2025,13 @@ async fn create_session(
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct CreateSessionIdRequest {
-    user_id: String,
-    api_key: String,
     llm_id: String,
     user_session_parameters: HashMap<String, Value>,
 }
 #[axum_macros::debug_handler]
 async fn create_session_id(
     state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
     Json(payload): Json<CreateSessionIdRequest>,
 ) -> Result<Json<CreateSessionResponse>, (StatusCode, String)> {
     info!("Called create_session_id from API.");
@@ -1246,9 +2041,8 @@ async fn create_session_id(
         Ok(uuid) => {
             create_session_flex(
                 state,
+                Extension(user),
                 Json(CreateSessionFlexRequest {
-                    user_id: payload.user_id,
-                    api_key: payload.api_key,
                     filter: Some(LLMFilter {
                         llm_uuid: Some(uuid),
                         llm_id: None,
@@ -1265,9 +2059,8 @@ async fn create_session_id(
         Err(_err) => {
             create_session_flex(
                 state,
+                Extension(user),
                 Json(CreateSessionFlexRequest {
-                    user_id: payload.user_id,
-                    api_key: payload.api_key,
                     filter: Some(LLMFilter {
                         llm_id: Some(payload.llm_id),
                         llm_uuid: None,
@@ -1286,8 +2079,6 @@ async fn create_session_id(
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct CreateSessionFlexRequest {
-    user_id: String,
-    api_key: String,
     filter: Option<LLMFilter>,
     preference: Option<LLMPreference>,
     user_session_parameters: HashMap<String, Value>,
@@ -1296,12 +2087,10 @@ struct CreateSessionFlexRequest {
 #[axum_macros::debug_handler]
 async fn create_session_flex(
     state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
     Json(payload): Json<CreateSessionFlexRequest>,
 ) -> Result<Json<CreateSessionResponse>, (StatusCode, String)> {
     info!("Called create_session_flex from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let user = user_permission_check("session", payload.api_key, user_uuid, state.pool.clone())?;
     // We should use currently running LLMs.
     let mut llms: Vec<Uuid> = state
         .activated_llms
@@ -1549,8 +2338,6 @@ async fn create_session_internal(
 }
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct PromptSessionStreamRequest {
-    user_id: String,
-    api_key: String,
     session_id: String,
     llm_uuid: String,
     prompt: String,
@@ -1560,18 +2347,15 @@ struct PromptSessionStreamRequest {
 #[axum_macros::debug_handler]
 async fn prompt_session_stream(
     state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
     Json(payload): Json<PromptSessionStreamRequest>,
 ) -> Result<Sse<impl Stream<Item = Result<Event, serde_json::Error>>>, (StatusCode, String)> {
     info!("Called prompt_session_stream from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
     let llm_uuid =
         Uuid::parse_str(&payload.llm_uuid).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
     let session_id = Uuid::parse_str(&payload.session_id)
         .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
 
-    let user = user_permission_check("session", payload.api_key, user_uuid, state.pool.clone())?;
-
     if let Some(llm) = state.activated_llms.get(&llm_uuid) {
         match llm
             .value()
@@ -1603,10 +2387,127 @@ async fn prompt_session_stream(
     }
 }
 
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct SearchHistoryRequest {
+    query: String,
+    #[serde(flatten)]
+    filter: HistoryFilter,
+}
+
+#[axum_macros::debug_handler]
+async fn search_history(
+    state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
+    Json(mut payload): Json<SearchHistoryRequest>,
+) -> Result<Json<Vec<HistorySearchHit>>, (StatusCode, String)> {
+    info!("Called search_history from API.");
+    // Only superusers can look at other users' history.
+    if !user.perm_superuser {
+        payload.filter.user_id = Some(user.id.0);
+    }
+    database::search_history(&payload.query, &payload.filter, state.pool.clone())
+        .map(Json)
+        .map_err(|err| match err {
+            diesel::result::Error::DatabaseError(_, info) if info.message().contains("fts5") => (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid search query: {}", info.message()),
+            ),
+            err => {
+                error!("Failed to database: {:?}", err);
+                (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
+            }
+        })
+}
+
+#[axum_macros::debug_handler]
+async fn export_sessions(
+    state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
+    Json(mut payload): Json<transfer::ExportFilter>,
+) -> Result<Json<Vec<transfer::SessionExport>>, (StatusCode, String)> {
+    info!("Called export_sessions from API.");
+    // Only superusers can export other users' sessions.
+    if !user.perm_superuser {
+        payload.user_id = Some(user.id.0);
+    }
+    transfer::export_sessions(&payload, &state.0)
+        .map(Json)
+        .map_err(|err| {
+            error!("Failed to export sessions: {}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, err)
+        })
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct ImportSessionsQuery {
+    // Who the sessions belong to here, the caller by default.
+    user_id: Option<Uuid>,
+}
+
+// The body is the JSONL export as is. It's read and imported a line at a time, so snapshots
+// don't have to fit in memory all at once.
+#[axum_macros::debug_handler]
+async fn import_sessions(
+    state: State<state::GlobalStateWrapper>,
+    Extension(user): Extension<user::User>,
+    Query(query): Query<ImportSessionsQuery>,
+    mut body: Body,
+) -> Result<Json<transfer::ImportSummary>, (StatusCode, String)> {
+    info!("Called import_sessions from API.");
+    let user_id = query.user_id.unwrap_or(user.id.0);
+    database::get_user(user_id, state.pool.clone()).map_err(|_err| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("No user with id {}", user_id),
+        )
+    })?;
+
+    let mut summary = transfer::ImportSummary::default();
+    let mut line_number = 0;
+    let mut import_line = |line: Vec<u8>| -> Result<(), (StatusCode, String)> {
+        line_number += 1;
+        if line.iter().all(|byte| byte.is_ascii_whitespace()) {
+            return Ok(());
+        }
+        let export: transfer::SessionExport = serde_json::from_slice(&line).map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Line {} isn't an exported session, the {} before it were imported: {}",
+                    line_number,
+                    summary.imported.len(),
+                    e
+                ),
+            )
+        })?;
+        let imported =
+            transfer::import_sessions(vec![export], user_id, &state.0).map_err(|err| {
+                error!("Failed to import sessions: {}", err);
+                (StatusCode::INTERNAL_SERVER_ERROR, err)
+            })?;
+        summary.imported.extend(imported.imported);
+        summary.skipped.extend(imported.skipped);
+        Ok(())
+    };
+
+    let mut lines = transfer::LineSplitter::new(transfer::MAX_SESSION_LINE_BYTES);
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        let complete = lines
+            .push(&chunk)
+            .map_err(|err| (StatusCode::PAYLOAD_TOO_LARGE, err))?;
+        for line in complete {
+            import_line(line)?;
+        }
+    }
+    if let Some(line) = lines.finish() {
+        import_line(line)?;
+    }
+    Ok(Json(summary))
+}
+
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct BareModelFlexRequest {
-    user_id: String,
-    api_key: String,
     filter: Option<LLMFilter>,
     preference: Option<LLMPreference>,
 }
@@ -1621,10 +2522,6 @@ async fn bare_model_flex(
     Json(payload): Json<BareModelFlexRequest>,
 ) -> Result<Json<BareModelResponse>, (StatusCode, String)> {
     info!("Called bare_model_flex from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let _user =
-        user_permission_check("bare_model", payload.api_key, user_uuid, state.pool.clone())?;
     let mut llms = database::get_available_llms(state.pool.clone()).map_err(|err| {
         error!("Failed to database: {:?}", err.to_string());
         (StatusCode::INTERNAL_SERVER_ERROR, "Database Error".into())
@@ -1821,8 +2718,6 @@ async fn bare_model_flex(
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct BareModelRequest {
-    user_id: String,
-    api_key: String,
     llm_id: String,
 }
 #[axum_macros::debug_handler]
@@ -1831,18 +2726,8 @@ async fn bare_model(
     Json(payload): Json<BareModelRequest>,
 ) -> Result<Json<BareModelResponse>, (StatusCode, String)> {
     info!("Called bare_model from API.");
-    let user_uuid =
-        Uuid::parse_str(&payload.user_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
-    let _user =
-        user_permission_check("bare_model", payload.api_key, user_uuid, state.pool.clone())?;
-
-    // Try parsing UUID, if it succeeds, use UUID, otherwise use pub id.
-    let llm = match Uuid::parse_str(&payload.llm_id) {
-        Ok(llm_uuid) => database::get_llm(llm_uuid, state.pool.clone())
-            .map_err(|_err| (StatusCode::NOT_FOUND, "Unable to find LLM".into()))?,
-        Err(_) => database::get_llm_pub_id(payload.llm_id, state.pool.clone())
-            .map_err(|_err| (StatusCode::NOT_FOUND, "Unable to find LLM".into()))?,
-    };
+
+    let llm = find_llm(&payload.llm_id, &state.0)?;
     let resp = BareModelResponse {
         model: (&llm).into(),
         path: llm
@@ -1861,37 +2746,293 @@ pub async fn build_server(
     global_state: state::GlobalStateWrapper,
     rx: oneshot::Receiver<()>,
 ) -> Result<(), String> {
+    // Every route except registration goes through `authenticate`.
+    fn authed(
+        state: &state::GlobalStateWrapper,
+        required: &'static str,
+        route: MethodRouter<state::GlobalStateWrapper>,
+    ) -> MethodRouter<state::GlobalStateWrapper> {
+        route
+            .route_layer(middleware::from_fn_with_state(state.clone(), authenticate))
+            .route_layer(Extension(RequiredPermission(required)))
+    }
+
     // Define your API routes
     fn routes(state: state::GlobalStateWrapper) -> Router {
+        let s = &state;
         Router::new()
             .route("/register_user", post(register_user))
-            .route("/request_permissions", post(request_permissions))
-            .route("/request_download", post(request_download))
-            .route("/request_load", post(request_load))
-            .route("/request_unload", post(request_unload))
-            .route("/request_load_flex", post(request_load_flex))
-            .route("/get_request_status", post(request_status))
-            .route("/get_llm_status", post(get_llm_status))
-            .route("/get_available_llms", post(get_available_llms))
-            .route("/get_running_llms", post(get_running_llms))
+            .route(
+                "/list_api_keys",
+                authed(s, "", get(list_api_keys).post(list_api_keys)),
+            )
+            .route("/create_api_key", authed(s, "", post(create_api_key)))
+            .route("/rotate_api_key", authed(s, "", post(rotate_api_key)))
+            .route("/revoke_api_key", authed(s, "", post(revoke_api_key)))
+            .route(
+                "/request_permissions",
+                authed(s, "", post(request_permissions)),
+            )
+            .route(
+                "/request_download",
+                authed(s, "request_download", post(request_download)),
+            )
+            .route(
+                "/request_load",
+                authed(s, "request_load", post(request_load)),
+            )
+            .route(
+                "/request_unload",
+                authed(s, "request_unload", post(request_unload)),
+            )
+            .route(
+                "/request_load_flex",
+                authed(s, "request_load", post(request_load_flex)),
+            )
+            .route("/get_request_status", authed(s, "", post(request_status)))
+            .route("/wait_request", authed(s, "", post(wait_request)))
+            .route(
+                "/request_events",
+                authed(s, "", get(request_events).post(request_events)),
+            )
+            .route(
+                "/get_llm_status",
+                authed(s, "view_llms", post(get_llm_status)),
+            )
+            .route(
+                "/get_available_llms",
+                authed(
+                    s,
+                    "view_llms",
+                    get(get_available_llms).post(get_available_llms),
+                ),
+            )
+            .route(
+                "/get_running_llms",
+                authed(s, "view_llms", get(get_running_llms).post(get_running_llms)),
+            )
             //compatability with 0.0.1 and 0.0.2 pantry-rs APIs.
-            .route("/request_running_llms", post(get_running_llms))
-            .route("/interrupt_session", post(interrupt_session))
+            .route(
+                "/request_running_llms",
+                authed(s, "view_llms", post(get_running_llms)),
+            )
+            .route(
+                "/interrupt_session",
+                authed(s, "session", post(interrupt_session)),
+            )
             // .route("/load_session_id", post(load_session_id))
-            .route("/load_llm", post(load_llm))
-            .route("/load_llm_flex", post(load_llm_flex))
-            .route("/unload_llm", post(unload_llm))
-            .route("/download_llm", post(download_llm))
-            .route("/get_or_download_llm", post(get_or_download_llm))
-            .route("/create_session", post(create_session))
-            .route("/create_session_id", post(create_session_id))
-            .route("/create_session_flex", post(create_session_flex))
-            .route("/prompt_session_stream", post(prompt_session_stream))
-            .route("/bare_model", post(bare_model))
-            .route("/bare_model_flex", post(bare_model_flex))
+            .route("/load_llm", authed(s, "load_llm", post(load_llm)))
+            .route("/load_llm_flex", authed(s, "load_llm", post(load_llm_flex)))
+            .route("/unload_llm", authed(s, "unload_llm", post(unload_llm)))
+            .route(
+                "/download_llm",
+                authed(s, "download_llm", post(download_llm)),
+            )
+            .route(
+                "/get_or_download_llm",
+                authed(s, "download_llm", post(get_or_download_llm)),
+            )
+            .route(
+                "/list_downloadable_llms",
+                authed(
+                    s,
+                    "view_llms",
+                    get(list_downloadable_llms).post(list_downloadable_llms),
+                ),
+            )
+            .route("/import_llm", authed(s, "superuser", post(import_llm)))
+            .route(
+                "/list_quantizations",
+                authed(s, "download_llm", post(list_quantizations)),
+            )
+            .route("/delete_llm", authed(s, "superuser", post(delete_llm)))
+            .route(
+                "/list_upgrades",
+                authed(s, "download_llm", get(list_upgrades).post(list_upgrades)),
+            )
+            .route("/upgrade_llm", authed(s, "superuser", post(upgrade_llm)))
+            .route(
+                "/list_requests",
+                authed(s, "superuser", get(list_requests).post(list_requests)),
+            )
+            .route(
+                "/accept_request",
+                authed(s, "superuser", post(accept_request)),
+            )
+            .route(
+                "/reject_request",
+                authed(s, "superuser", post(reject_request)),
+            )
+            .route(
+                "/list_users",
+                authed(s, "superuser", get(list_users).post(list_users)),
+            )
+            .route("/show_user", authed(s, "superuser", post(show_user)))
+            .route(
+                "/revoke_user_keys",
+                authed(s, "superuser", post(revoke_user_keys)),
+            )
+            .route(
+                "/set_user_permissions",
+                authed(s, "superuser", post(set_user_permissions)),
+            )
+            .route(
+                "/disk_usage",
+                authed(s, "superuser", get(disk_usage).post(disk_usage)),
+            )
+            .route(
+                "/collect_garbage",
+                authed(s, "superuser", post(collect_garbage)),
+            )
+            .route(
+                "/list_downloads",
+                authed(s, "download_llm", get(list_downloads).post(list_downloads)),
+            )
+            .route(
+                "/cancel_download",
+                authed(s, "download_llm", post(cancel_download)),
+            )
+            .route(
+                "/pause_download",
+                authed(s, "download_llm", post(pause_download)),
+            )
+            .route(
+                "/resume_download",
+                authed(s, "download_llm", post(resume_download)),
+            )
+            .route(
+                "/create_session",
+                authed(s, "session", post(create_session)),
+            )
+            .route(
+                "/create_session_id",
+                authed(s, "session", post(create_session_id)),
+            )
+            .route(
+                "/create_session_flex",
+                authed(s, "session", post(create_session_flex)),
+            )
+            .route(
+                "/prompt_session_stream",
+                authed(s, "session", post(prompt_session_stream)),
+            )
+            .route(
+                "/search_history",
+                authed(s, "session", post(search_history)),
+            )
+            .route(
+                "/export_sessions",
+                authed(s, "session", post(export_sessions)),
+            )
+            // Streamed, see import_sessions for its limit.
+            .route(
+                "/import_sessions",
+                authed(s, "superuser", post(import_sessions)),
+            )
+            .route("/bare_model", authed(s, "bare_model", post(bare_model)))
+            .route(
+                "/bare_model_flex",
+                authed(s, "bare_model", post(bare_model_flex)),
+            )
             .with_state(state)
     }
-    let app = routes(global_state);
+    let app = routes(global_state.clone());
+
+    let mut rx = rx;
+    loop {
+        let config: ListenerConfig = (&*global_state.user_settings.read().unwrap()).into();
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let mut listeners = tokio::spawn(create_listeners(app.clone(), config, stop_rx));
+
+        tokio::select! {
+            _ = &mut rx => {
+                let _ = stop_tx.send(());
+                return listeners.await.map_err(|err| err.to_string())?;
+            }
+            _ = global_state.listener_restart.notified() => {
+                info!("Listener settings changed, restarting listeners.");
+                let _ = stop_tx.send(());
+                // Open /request_events streams and /wait_request polls can hold up a graceful
+                // shutdown indefinitely, past the timeout the old listeners are dropped.
+                match tokio::time::timeout(LISTENER_SHUTDOWN_TIMEOUT, &mut listeners).await {
+                    Ok(Ok(Err(err))) => error!("Listeners closed with error: {:?}", err),
+                    Ok(_) => {}
+                    Err(_elapsed) => {
+                        warn!("Listeners didn't shut down in time, aborting them.");
+                        listeners.abort();
+                        let _ = listeners.await;
+                    }
+                }
+            }
+        }
+    }
+}
 
-    create_listeners(app, rx).await
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn authorization(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn parses_bearer_credentials() {
+        let (user_id, api_key) = bearer_credentials(&authorization(
+            "Bearer 6f1c3b2a-8d4e-4f5a-9b6c-7d8e9f0a1b2c:secret:with:colons",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(user_id.to_string(), "6f1c3b2a-8d4e-4f5a-9b6c-7d8e9f0a1b2c");
+        assert_eq!(api_key, "secret:with:colons");
+    }
+
+    #[test]
+    fn no_authorization_header_falls_back_to_the_body() {
+        assert!(bearer_credentials(&HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_bearer_credentials() {
+        let status = |value| {
+            bearer_credentials(&authorization(value))
+                .unwrap()
+                .unwrap_err()
+                .0
+        };
+        assert_eq!(status("Basic dXNlcjpwYXNz"), StatusCode::UNAUTHORIZED);
+        assert_eq!(status("Bearer no-colon"), StatusCode::UNAUTHORIZED);
+        assert_eq!(status("bearer a:b"), StatusCode::UNAUTHORIZED);
+        assert_eq!(status("Bearer not-a-uuid:secret"), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn credential_bodies_are_capped() {
+        let body = read_credential_body(&HeaderMap::new(), Body::from("{}"))
+            .await
+            .unwrap();
+        assert_eq!(body, b"{}");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_LENGTH,
+            HeaderValue::from(MAX_CREDENTIAL_BODY + 1),
+        );
+        let err = read_credential_body(&headers, Body::from("{}"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.0, StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Without a Content-Length we find out while reading.
+        let err = read_credential_body(
+            &HeaderMap::new(),
+            Body::from(vec![b' '; MAX_CREDENTIAL_BODY + 1]),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.0, StatusCode::PAYLOAD_TOO_LARGE);
+    }
 }
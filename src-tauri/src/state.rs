@@ -1,20 +1,25 @@
 use crate::connectors; //::LLMRegistryEntry;
 use crate::connectors::llm_manager;
+use crate::database;
+use crate::download_manager;
+use crate::emitter;
+use crate::huggingface;
 use crate::llm;
 use crate::registry;
+use crate::request;
 use dashmap::DashMap;
 use diesel::prelude::*;
 use diesel::r2d2::ConnectionManager;
 use diesel::r2d2::Pool;
 use keyring;
-use log::error;
+use log::{debug, error};
 use serde::de::{self, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
-use tauri::AppHandle;
 use tiny_tokio_actor::*;
+use tokio::sync::{broadcast, Notify};
 
 use uuid::Uuid;
 
@@ -96,6 +101,83 @@ pub struct UserSettings {
     pub use_gpu: bool,
     pub n_thread: usize,
     pub n_batch: usize,
+    // API listeners. Changing any of these restarts them.
+    #[serde(default = "default_api_bind_address")]
+    pub api_bind_address: String,
+    #[serde(default = "default_api_port")]
+    pub api_port: u16,
+    #[serde(default = "default_true")]
+    pub enable_tcp_listener: bool,
+    #[serde(default = "default_true")]
+    pub enable_socket_listener: bool,
+    // None uses default_socket_path().
+    #[serde(default)]
+    pub socket_path: Option<PathBuf>,
+    // TLS is only used when both are set, and only for the TCP listener.
+    #[serde(default)]
+    pub tls_cert_path: Option<PathBuf>,
+    #[serde(default)]
+    pub tls_key_path: Option<PathBuf>,
+    // Hub api or local mirror directory used to resolve huggingface registry entries.
+    #[serde(default = "default_huggingface_endpoint")]
+    pub huggingface_endpoint: String,
+    // More downloads than this wait in a queue.
+    #[serde(default = "default_max_concurrent_downloads")]
+    pub max_concurrent_downloads: usize,
+    // Shared by all running downloads, None is unlimited.
+    #[serde(default)]
+    pub download_bytes_per_second: Option<u64>,
+    // Pending requests older than this are rejected, None keeps them until decided.
+    #[serde(default = "default_request_ttl_seconds")]
+    pub request_ttl_seconds: Option<u64>,
+    // Requests one of these covers are accepted without asking.
+    #[serde(default)]
+    pub auto_approval_rules: Vec<request::AutoApprovalRule>,
+}
+
+fn default_api_bind_address() -> String {
+    "127.0.0.1".into()
+}
+
+fn default_api_port() -> u16 {
+    9404
+}
+
+fn default_huggingface_endpoint() -> String {
+    std::env::var("HF_ENDPOINT").unwrap_or(huggingface::DEFAULT_ENDPOINT.into())
+}
+
+fn default_max_concurrent_downloads() -> usize {
+    2
+}
+
+fn default_request_ttl_seconds() -> Option<u64> {
+    Some(24 * 60 * 60)
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// A per-user runtime dir, so two users on one machine don't fight over the socket.
+pub fn default_socket_path() -> PathBuf {
+    let mut path = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) => {
+            let mut path = PathBuf::from(runtime_dir);
+            path.push("pantry");
+            path
+        }
+        None => {
+            let mut path = std::env::temp_dir();
+            path.push(format!(
+                "pantry-{}",
+                std::env::var("USER").unwrap_or("user".into())
+            ));
+            path
+        }
+    };
+    path.push("pantry.sock");
+    path
 }
 
 impl UserSettings {
@@ -127,6 +209,18 @@ impl UserSettings {
             use_gpu: false,
             n_thread: 4,
             n_batch: 1,
+            api_bind_address: default_api_bind_address(),
+            api_port: default_api_port(),
+            enable_tcp_listener: true,
+            enable_socket_listener: true,
+            socket_path: None,
+            tls_cert_path: None,
+            tls_key_path: None,
+            huggingface_endpoint: default_huggingface_endpoint(),
+            max_concurrent_downloads: default_max_concurrent_downloads(),
+            download_bytes_per_second: None,
+            request_ttl_seconds: default_request_ttl_seconds(),
+            auto_approval_rules: Vec::new(),
         }
     }
     pub fn save(&self) -> Result<(), String> {
@@ -137,6 +231,26 @@ impl UserSettings {
     pub fn get_location(&self) -> PathBuf {
         self.location.clone()
     }
+
+    pub fn get_socket_path(&self) -> PathBuf {
+        self.socket_path.clone().unwrap_or_else(default_socket_path)
+    }
+
+    pub fn use_tls(&self) -> bool {
+        self.tls_cert_path.is_some() && self.tls_key_path.is_some()
+    }
+
+    // Where a local client should connect to reach the TCP listener.
+    pub fn api_url(&self) -> String {
+        let host = match self.api_bind_address.as_str() {
+            "0.0.0.0" => "127.0.0.1".to_string(),
+            "::" => "[::1]".to_string(),
+            addr if addr.contains(':') => format!("[{}]", addr),
+            addr => addr.to_string(),
+        };
+        let scheme = if self.use_tls() { "https" } else { "http" };
+        format!("{}://{}:{}", scheme, host, self.api_port)
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -146,6 +260,18 @@ pub struct UserSettingsInfo {
     pub n_batch: usize,
     pub preferred_active_sessions: usize,
     pub dedup_downloads: bool,
+    pub api_bind_address: String,
+    pub api_port: u16,
+    pub enable_tcp_listener: bool,
+    pub enable_socket_listener: bool,
+    pub socket_path: PathBuf,
+    pub tls_cert_path: Option<PathBuf>,
+    pub tls_key_path: Option<PathBuf>,
+    pub huggingface_endpoint: String,
+    pub max_concurrent_downloads: usize,
+    pub download_bytes_per_second: Option<u64>,
+    pub request_ttl_seconds: Option<u64>,
+    pub auto_approval_rules: Vec<request::AutoApprovalRule>,
 }
 
 impl From<&UserSettings> for UserSettingsInfo {
@@ -156,6 +282,18 @@ impl From<&UserSettings> for UserSettingsInfo {
             n_batch: user_settings.n_batch.clone(),
             dedup_downloads: user_settings.dedup_downloads.clone(),
             preferred_active_sessions: user_settings.preferred_active_sessions.clone(),
+            api_bind_address: user_settings.api_bind_address.clone(),
+            api_port: user_settings.api_port.clone(),
+            enable_tcp_listener: user_settings.enable_tcp_listener.clone(),
+            enable_socket_listener: user_settings.enable_socket_listener.clone(),
+            socket_path: user_settings.get_socket_path(),
+            tls_cert_path: user_settings.tls_cert_path.clone(),
+            tls_key_path: user_settings.tls_key_path.clone(),
+            huggingface_endpoint: user_settings.huggingface_endpoint.clone(),
+            max_concurrent_downloads: user_settings.max_concurrent_downloads.clone(),
+            download_bytes_per_second: user_settings.download_bytes_per_second.clone(),
+            request_ttl_seconds: user_settings.request_ttl_seconds.clone(),
+            auto_approval_rules: user_settings.auto_approval_rules.clone(),
         }
     }
 }
@@ -180,11 +318,17 @@ pub struct GlobalState {
     pub activated_llms: DashMap<Uuid, llm::LLMActivated>,
     pub local_path: PathBuf,
     pub llm_path: PathBuf,
-    pub handle: AppHandle,
+    pub emitter: emitter::SharedEmitter,
     // pub available_llms: DashMap<Uuid, Arc<llm::LLM>>,
     pub pool: Pool<ConnectionManager<SqliteConnection>>,
     // used by server to provide llmstatus for downloading llms
     pub downloading_llms: DashMap<Uuid, registry::DownloadingLLM>,
+    // Queue and bandwidth limit shared by all downloads.
+    pub download_manager: download_manager::DownloadManager,
+    // restart_listeners pokes this once listener settings are saved.
+    pub listener_restart: Notify,
+    // Request decisions and their outcomes, for /wait_request and /request_events.
+    pub request_events: broadcast::Sender<request::RequestEvent>,
 }
 
 /*
@@ -193,7 +337,7 @@ pub struct GlobalState {
 pub fn create_global_state(
     addr: ActorRef<connectors::SysEvent, llm_manager::LLMManagerActor>,
     activated_llms: DashMap<Uuid, llm::LLMActivated>,
-    handle: AppHandle,
+    emitter: emitter::SharedEmitter,
     local_path: PathBuf,
     llm_path: PathBuf,
     pool: Pool<ConnectionManager<SqliteConnection>>,
@@ -205,13 +349,66 @@ pub fn create_global_state(
             activated_llms,
             local_path,
             llm_path,
-            handle,
+            emitter,
             pool: pool,
             downloading_llms: DashMap::new(),
+            download_manager: download_manager::DownloadManager::new(),
+            listener_restart: Notify::new(),
+            request_events: broadcast::channel(64).0,
         }),
     }
 }
 
+// Shared by the UI, and by accepting load/unload requests from the admin API.
+pub async fn activate_llm(uuid: Uuid, state: &GlobalStateWrapper) -> Result<(), String> {
+    if state.activated_llms.contains_key(&uuid) {
+        return Err("llm already loaded".into());
+    }
+    let new_llm = database::get_llm(uuid, state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+    let settings = state.user_settings.read().unwrap().clone();
+    let running = llm::LLMActivated::activate_llm(
+        new_llm,
+        state.manager_addr.clone(),
+        state.local_path.clone(),
+        settings,
+        state.pool.clone(),
+        state.emitter.clone(),
+    )
+    .await
+    .map_err(|_err| format!("failed to launch {}", uuid))?;
+    debug!("Inserting {uuid} into running LLMs");
+    state.activated_llms.insert(uuid, running);
+    Ok(())
+}
+
+// Models load in the background after activate_llm. Resolves once this one is ready, and
+// unloads it again if loading failed.
+pub async fn wait_until_loaded(uuid: Uuid, state: &GlobalStateWrapper) -> Result<(), String> {
+    let loaded = match state.activated_llms.get(&uuid) {
+        Some(running) => running.wait_loaded(),
+        None => return Err("LLM not found or already unloaded".into()),
+    };
+    if let Err(err) = loaded.await {
+        error!("{}", err);
+        let _ = deactivate_llm(uuid, state).await;
+        return Err(err);
+    }
+    Ok(())
+}
+
+pub async fn deactivate_llm(uuid: Uuid, state: &GlobalStateWrapper) -> Result<(), String> {
+    if state.activated_llms.remove(&uuid).is_none() {
+        return Err("LLM not found or already unloaded".into());
+    }
+    state
+        .manager_addr
+        .ask(llm_manager::UnloadLLMActorMessage { uuid })
+        .await
+        .map_err(|_err| "Failed to send unload message to LLMManagerActor".to_string())?;
+    Ok(())
+}
+
 // Write this if we have to save LLMs in a second place.
 // pub fn save_available_llms_state(path:PathBuf, available_llms: DashMap<String, Arc<llm::LLM>>) -> Result<(), PantryError>
 
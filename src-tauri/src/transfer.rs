@@ -0,0 +1,290 @@
+// transfer.rs
+//
+// Moves sessions and their history between pantry instances. Each session exports to one
+// JSON object, so a set of them is written and read as JSONL.
+
+use crate::database;
+use crate::database_types::*;
+use crate::disk;
+use crate::llm;
+use crate::state;
+use base64::{engine::general_purpose, Engine as _};
+use log::{info, warn};
+use std::fs;
+use uuid::Uuid;
+
+pub const EXPORT_VERSION: u32 = 1;
+
+// Longest line of an /import_sessions body, i.e. one session. Snapshots hold the model's
+// context, a full one is around 1GB for 7B models, and base64 adds a third on top.
+// Lines are read one at a time, so this bounds memory rather than the whole import.
+pub const MAX_SESSION_LINE_BYTES: usize = 1536 * 1024 * 1024;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SessionExport {
+    pub version: u32,
+    pub session: llm::LLMSession,
+    // The LLM's uuid differs between machines, these are used to find it on import.
+    pub llm_id: String,
+    pub llm_url: String,
+    // Snapshots are only restored onto a file with the same hash.
+    #[serde(default)]
+    pub llm_sha256: Option<String>,
+    pub history: Vec<llm::LLMHistoryItem>,
+    // Base64 of the llmrs inference snapshot, if asked for and the session has one.
+    // Only usable with the very same model file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct ExportFilter {
+    // Empty for every session.
+    #[serde(default)]
+    pub session_ids: Vec<Uuid>,
+    pub user_id: Option<Uuid>,
+    // The LLM's uuid or id.
+    pub llm: Option<String>,
+    #[serde(default)]
+    pub include_snapshots: bool,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct ImportSummary {
+    pub imported: Vec<Uuid>,
+    // Session id and why it wasn't imported.
+    pub skipped: Vec<(Uuid, String)>,
+}
+
+// Cuts a body that arrives in chunks into lines, holding on to at most one unfinished line.
+pub struct LineSplitter {
+    pending: Vec<u8>,
+    // How much of pending is known not to contain a newline.
+    scanned: usize,
+    max_line: usize,
+}
+
+impl LineSplitter {
+    pub fn new(max_line: usize) -> Self {
+        LineSplitter {
+            pending: Vec::new(),
+            scanned: 0,
+            max_line,
+        }
+    }
+
+    // The lines this chunk completed, without their newlines.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+        self.pending.extend_from_slice(chunk);
+        let mut lines = Vec::new();
+        while let Some(pos) = self.pending[self.scanned..]
+            .iter()
+            .position(|byte| *byte == b'\n')
+        {
+            let rest = self.pending.split_off(self.scanned + pos + 1);
+            let mut line = std::mem::replace(&mut self.pending, rest);
+            line.pop();
+            lines.push(line);
+            self.scanned = 0;
+        }
+        self.scanned = self.pending.len();
+        if self.pending.len() > self.max_line {
+            return Err(format!("Lines are limited to {} bytes", self.max_line));
+        }
+        Ok(lines)
+    }
+
+    // The last line, for bodies that don't end in a newline.
+    pub fn finish(self) -> Option<Vec<u8>> {
+        Some(self.pending).filter(|line| !line.is_empty())
+    }
+}
+
+// Snapshots are written when a session is unloaded, so a session that's loaded right now
+// exports whatever snapshot it had before, if any.
+pub fn export_sessions(
+    filter: &ExportFilter,
+    state: &state::GlobalStateWrapper,
+) -> Result<Vec<SessionExport>, String> {
+    let llms = database::get_available_llms(state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+    let sessions = database::get_llm_sessions(state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+
+    let mut exports = Vec::new();
+    for session in sessions {
+        if !filter.session_ids.is_empty() && !filter.session_ids.contains(&session.id.0) {
+            continue;
+        }
+        if filter
+            .user_id
+            .map_or(false, |user_id| user_id != session.user_id.0)
+        {
+            continue;
+        }
+        let llm = match llms.iter().find(|llm| llm.uuid == session.llm_uuid) {
+            Some(llm) => llm,
+            None => continue,
+        };
+        if let Some(wanted) = filter.llm.as_ref() {
+            if *wanted != llm.id && *wanted != llm.uuid.0.to_string() {
+                continue;
+            }
+        }
+        let history = database::get_history_for_session(session.id.0, state.pool.clone())
+            .map_err(|err| format!("Database failure: {:?}", err))?;
+        let snapshot = if filter.include_snapshots {
+            fs::read(disk::snapshot_dir(llm.uuid.0, state).join(session.id.0.to_string()))
+                .ok()
+                .map(|bytes| general_purpose::STANDARD.encode(bytes))
+        } else {
+            None
+        };
+        exports.push(SessionExport {
+            version: EXPORT_VERSION,
+            session,
+            llm_id: llm.id.clone(),
+            llm_url: llm.url.clone(),
+            llm_sha256: llm.sha256.clone(),
+            history,
+            snapshot,
+        });
+    }
+    Ok(exports)
+}
+
+// Imported sessions belong to user_id. Each is attached to the local LLM downloaded from
+// the same url, or failing that the one with the same id. Snapshots are only kept when that
+// LLM's sha256 matches the exported one, a url or id can point at a different file by now.
+pub fn import_sessions(
+    exports: Vec<SessionExport>,
+    user_id: Uuid,
+    state: &state::GlobalStateWrapper,
+) -> Result<ImportSummary, String> {
+    let llms = database::get_available_llms(state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+
+    let mut summary = ImportSummary::default();
+    for export in exports {
+        let session_id = export.session.id.0;
+        if export.version > EXPORT_VERSION {
+            summary.skipped.push((
+                session_id,
+                format!("Exported by a newer pantry (version {})", export.version),
+            ));
+            continue;
+        }
+        if database::get_llm_session(session_id, state.pool.clone()).is_ok() {
+            summary
+                .skipped
+                .push((session_id, "Session already exists".into()));
+            continue;
+        }
+        let found = llms
+            .iter()
+            .find(|llm| llm.url == export.llm_url)
+            .or_else(|| llms.iter().find(|llm| llm.id == export.llm_id));
+        let llm = match found {
+            Some(llm) => llm,
+            None => {
+                summary.skipped.push((
+                    session_id,
+                    format!("No LLM with id {} or url {}", export.llm_id, export.llm_url),
+                ));
+                continue;
+            }
+        };
+        let same_file = match (&export.llm_sha256, &llm.sha256) {
+            (Some(exported), Some(local)) => exported.eq_ignore_ascii_case(local),
+            _ => false,
+        };
+
+        // Decoded up front, so a broken snapshot skips the session rather than half importing it.
+        let snapshot = match export.snapshot {
+            Some(snapshot) if same_file => match general_purpose::STANDARD.decode(snapshot) {
+                Ok(bytes) => Some(bytes),
+                Err(err) => {
+                    summary
+                        .skipped
+                        .push((session_id, format!("Invalid snapshot: {:?}", err)));
+                    continue;
+                }
+            },
+            Some(_) => {
+                warn!(
+                    "Dropping the snapshot of {}, {} is a different file",
+                    session_id, llm.id
+                );
+                None
+            }
+            None => None,
+        };
+
+        let session = llm::LLMSession {
+            llm_uuid: llm.uuid.clone(),
+            user_id: DbUuid(user_id),
+            ..export.session
+        };
+        let history = export
+            .history
+            .into_iter()
+            .map(|item| llm::LLMHistoryItem {
+                llm_session_id: DbUuid(session_id),
+                ..item
+            })
+            .collect();
+        if let Err(err) = database::save_imported_session(session, history, state.pool.clone()) {
+            summary
+                .skipped
+                .push((session_id, format!("Database failure: {:?}", err)));
+            continue;
+        }
+
+        // The session works without it, it just starts over.
+        if let Some(bytes) = snapshot {
+            let dir = disk::snapshot_dir(llm.uuid.0, state);
+            if let Err(err) = fs::create_dir_all(&dir)
+                .and_then(|_| fs::write(dir.join(session_id.to_string()), bytes))
+            {
+                warn!("Failed to write the snapshot of {}: {:?}", session_id, err);
+            }
+        }
+        info!("Imported session {} for {}", session_id, llm.id);
+        summary.imported.push(session_id);
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_lines_across_chunks() {
+        let mut splitter = LineSplitter::new(64);
+        assert!(splitter.push(b"{\"a\"").unwrap().is_empty());
+        assert_eq!(
+            splitter.push(b": 1}\n{\"b\": 2}\n{").unwrap(),
+            vec![b"{\"a\": 1}".to_vec(), b"{\"b\": 2}".to_vec()]
+        );
+        assert!(splitter.push(b"\"c\": 3}").unwrap().is_empty());
+        assert_eq!(splitter.finish(), Some(b"{\"c\": 3}".to_vec()));
+
+        let mut splitter = LineSplitter::new(64);
+        assert_eq!(splitter.push(b"x\n").unwrap(), vec![b"x".to_vec()]);
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[test]
+    fn refuses_overlong_lines() {
+        let mut splitter = LineSplitter::new(8);
+        assert!(splitter.push(b"12345").is_ok());
+        assert!(splitter.push(b"6789").is_err());
+
+        // Only the unfinished line counts, not the whole body.
+        let mut splitter = LineSplitter::new(8);
+        for _ in 0..10 {
+            assert_eq!(splitter.push(b"1234567\n").unwrap().len(), 1);
+        }
+    }
+}
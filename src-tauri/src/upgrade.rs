@@ -0,0 +1,408 @@
+// upgrade.rs
+//
+// Registry entries carry an optional version. When a registry publishes a newer version
+// of an installed LLM, upgrading downloads it as a new LLM, carries the old one's local
+// settings over, and removes the old one unless both should be kept side by side.
+
+use crate::database;
+use crate::disk;
+use crate::emitter;
+use crate::huggingface;
+use crate::llm;
+use crate::registry::{self, Download, LLMRegistryEntry};
+use crate::state;
+use log::{error, info, warn};
+use serde_json::Value;
+use std::cmp::Ordering;
+use uuid::Uuid;
+
+// Persisted on the download row, so upgrades survive a restart mid download.
+#[derive(Debug, Clone)]
+pub struct Upgrade {
+    pub of: Uuid,
+    pub keep_old: bool,
+}
+
+impl Upgrade {
+    pub fn from_download(download: &Download) -> Option<Upgrade> {
+        let of = Uuid::parse_str(download.upgrade_of.as_ref()?).ok()?;
+        Some(Upgrade {
+            of,
+            keep_old: download.keep_old,
+        })
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct UpgradeInfo {
+    pub llm_uuid: String,
+    pub id: String,
+    pub name: String,
+    pub installed_version: Option<String>,
+    pub available_version: Option<String>,
+    pub registry: String,
+}
+
+// Dotted numeric parts compare as numbers (1.10 > 1.9), anything else as strings.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let split = |v: &str| {
+        v.trim_start_matches('v')
+            .split(|c| c == '.' || c == '-')
+            .map(|part| part.to_string())
+            .collect::<Vec<String>>()
+    };
+    let (a, b) = (split(a), split(b));
+    for (x, y) in a.iter().zip(b.iter()) {
+        let order = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => x.cmp(y),
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+// Hugging Face entries have to be resolved first, the file and hash are only known then.
+fn is_newer(entry: &LLMRegistryEntry, installed: &llm::LLM) -> bool {
+    match (&entry.version, &installed.version) {
+        (Some(available), Some(current)) => {
+            compare_versions(available, current) == Ordering::Greater
+        }
+        // Installed before the registry versioned it, only a different file is an upgrade.
+        (Some(_), None) => match (&entry.sha256, &installed.sha256) {
+            (Some(available), Some(current)) => !available.eq_ignore_ascii_case(current),
+            _ => entry.url != installed.url,
+        },
+        (None, _) => false,
+    }
+}
+
+// For LLMs installed before we recorded the registry: the one that still lists its file.
+fn lists_installed(listed: &Value, installed: &llm::LLM) -> bool {
+    match listed.get("huggingface").and_then(|hf| hf.get("repo")) {
+        Some(Value::String(repo)) => installed.url.contains(&format!("/{}/resolve/", repo)),
+        _ => listed.get("url").and_then(|url| url.as_str()) == Some(installed.url.as_str()),
+    }
+}
+
+// Resolved against the installed file's quantization, so the same file compares equal.
+async fn resolve_like(
+    entry: LLMRegistryEntry,
+    installed: &llm::LLM,
+    state: &state::GlobalStateWrapper,
+) -> Result<LLMRegistryEntry, String> {
+    let endpoint = state
+        .user_settings
+        .read()
+        .unwrap()
+        .huggingface_endpoint
+        .clone();
+    let quantization = installed
+        .url
+        .rsplit('/')
+        .next()
+        .and_then(huggingface::quantization_of);
+    huggingface::resolve(
+        huggingface::with_quantization(entry, quantization),
+        &endpoint,
+    )
+    .await
+}
+
+async fn find_upgrades(
+    refresh: bool,
+    state: &state::GlobalStateWrapper,
+) -> Result<Vec<(UpgradeInfo, LLMRegistryEntry)>, String> {
+    let mut registries = registry::get_registries(refresh, state.pool.clone()).await?;
+    registry::sort_by_trust(&mut registries);
+    let installed = database::get_available_llms(state.pool.clone())
+        .map_err(|err| format!("Database failure: {:?}", err))?;
+
+    let mut upgrades = Vec::new();
+    for llm in installed.iter() {
+        // Only the registry it came from gets to upgrade it.
+        let origin = match &llm.registry {
+            Some(name) => registries.iter().find(|reg| &reg.name == name),
+            None => registries.iter().find(|reg| {
+                reg.models()
+                    .get(&llm.id)
+                    .map_or(false, |listed| lists_installed(listed, llm))
+            }),
+        };
+        let reg = match origin {
+            Some(reg) => reg,
+            None => continue,
+        };
+        let listed = reg.models().get(&llm.id).cloned();
+        let mut entry = match listed.map(serde_json::from_value::<LLMRegistryEntry>) {
+            Some(Ok(entry)) => entry,
+            _ => continue,
+        };
+        let copies: Vec<&llm::LLM> = installed
+            .iter()
+            .filter(|other| other.id == llm.id && other.registry == llm.registry)
+            .collect();
+        // Only unversioned installs compare files, don't hit the hub otherwise.
+        if entry.huggingface.is_some()
+            && entry.version.is_some()
+            && copies.iter().any(|other| other.version.is_none())
+        {
+            entry = match resolve_like(entry, llm, state).await {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warn!("Can't check {} for upgrades: {}", llm.id, err);
+                    continue;
+                }
+            };
+        }
+        // With old versions kept around, only offer it if no installed copy is as new.
+        if !copies.iter().all(|other| is_newer(&entry, other)) {
+            continue;
+        }
+        upgrades.push((
+            UpgradeInfo {
+                llm_uuid: llm.uuid.0.to_string(),
+                id: llm.id.clone(),
+                name: llm.name.clone(),
+                installed_version: llm.version.clone(),
+                available_version: entry.version.clone(),
+                registry: reg.name.clone(),
+            },
+            entry,
+        ));
+    }
+    Ok(upgrades)
+}
+
+pub async fn available_upgrades(
+    refresh: bool,
+    state: &state::GlobalStateWrapper,
+) -> Result<Vec<UpgradeInfo>, String> {
+    Ok(find_upgrades(refresh, state)
+        .await?
+        .into_iter()
+        .map(|(info, _)| info)
+        .collect())
+}
+
+// Starts downloading the new version, returns the uuid the new LLM will get.
+pub async fn upgrade_llm(
+    llm_uuid: Uuid,
+    keep_old: bool,
+    state: &state::GlobalStateWrapper,
+) -> Result<Uuid, String> {
+    let (info, entry) = find_upgrades(false, state)
+        .await?
+        .into_iter()
+        .find(|(info, _)| info.llm_uuid == llm_uuid.to_string())
+        .ok_or(format!("No upgrade available for {}", llm_uuid))?;
+
+    let new_uuid = Uuid::new_v4();
+    info!(
+        "Upgrading {} from {:?} to {:?}",
+        info.id, info.installed_version, info.available_version
+    );
+    notify(
+        state,
+        new_uuid,
+        format!(
+            "Upgrading {} to version {}.",
+            info.name,
+            info.available_version.clone().unwrap_or_default()
+        ),
+    );
+    let upgrade = Upgrade {
+        of: llm_uuid,
+        keep_old,
+    };
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(err) = registry::start_download(entry, new_uuid, Some(upgrade), state).await {
+            error!("Upgrade download failed: {:?}", err);
+        }
+    });
+    Ok(new_uuid)
+}
+
+fn notify(state: &state::GlobalStateWrapper, stream: Uuid, message: String) {
+    let notifications = emitter::NotificationEmitter {
+        emitter: state.emitter.clone(),
+    };
+    if let Err(err) = notifications.send_notification(stream.to_string(), message) {
+        error!("Failed to send notification: {}", err);
+    }
+}
+
+// Registry values win for the keys the new entry sets, anything else the old LLM had is
+// a local addition and carries over.
+fn migrate_settings(old: &llm::LLM, new: &mut llm::LLM) {
+    for (key, value) in old.config.0.iter() {
+        new.config.0.entry(key.clone()).or_insert(value.clone());
+    }
+    for (key, value) in old.parameters.0.iter() {
+        new.parameters.0.entry(key.clone()).or_insert(value.clone());
+    }
+    for (key, value) in old.session_parameters.0.iter() {
+        new.session_parameters
+            .0
+            .entry(key.clone())
+            .or_insert(value.clone());
+    }
+    for param in old.user_parameters.0.iter() {
+        if !new.user_parameters.0.contains(param) {
+            new.user_parameters.0.push(param.clone());
+        }
+    }
+    for param in old.user_session_parameters.0.iter() {
+        if !new.user_session_parameters.0.contains(param) {
+            new.user_session_parameters.0.push(param.clone());
+        }
+    }
+}
+
+fn migrate(
+    upgrade: &Upgrade,
+    new_uuid: Uuid,
+    state: &state::GlobalStateWrapper,
+) -> Result<String, String> {
+    let old = database::get_llm(upgrade.of, state.pool.clone())
+        .map_err(|_err| "The old version is gone, nothing to migrate.".to_string())?;
+    let mut new = database::get_llm(new_uuid, state.pool.clone())
+        .map_err(|err| format!("Upgraded LLM wasn't saved: {:?}", err))?;
+
+    migrate_settings(&old, &mut new);
+    database::update_llm_settings(&new, state.pool.clone())
+        .map_err(|err| format!("Failed to migrate settings: {:?}", err))?;
+
+    let version = new.version.clone().unwrap_or_default();
+    if upgrade.keep_old {
+        return Ok(format!(
+            "Installed {} version {} next to the old version.",
+            new.name, version
+        ));
+    }
+    match disk::delete_llm(upgrade.of, state) {
+        Ok(_) => Ok(format!("Upgraded {} to version {}.", new.name, version)),
+        Err(err) => Ok(format!(
+            "Upgraded {} to version {}, but kept the old version: {}",
+            new.name, version, err
+        )),
+    }
+}
+
+// Runs once the new LLM is saved.
+pub fn finish_upgrade(upgrade: Option<Upgrade>, new_uuid: Uuid, state: &state::GlobalStateWrapper) {
+    let upgrade = match upgrade {
+        Some(upgrade) => upgrade,
+        None => return,
+    };
+    let message = match migrate(&upgrade, new_uuid, state) {
+        Ok(message) => message,
+        Err(err) => {
+            error!("Upgrade of {} failed: {}", upgrade.of, err);
+            format!("Upgrade failed: {}", err)
+        }
+    };
+    info!("{}", message);
+    notify(state, new_uuid, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::factory;
+    use serde_json::json;
+
+    fn entry(version: Option<&str>, url: &str, sha256: Option<&str>) -> LLMRegistryEntry {
+        let mut entry = registry::test_entry("llama", url);
+        entry.version = version.map(String::from);
+        entry.sha256 = sha256.map(String::from);
+        entry
+    }
+
+    fn installed(version: Option<&str>, url: &str, sha256: Option<&str>) -> llm::LLM {
+        let mut llm = factory::factory_llms().remove(0);
+        llm.id = "llama".into();
+        llm.url = url.into();
+        llm.sha256 = sha256.map(String::from);
+        llm.version = version.map(String::from);
+        llm
+    }
+
+    #[test]
+    fn compares_versions_numerically() {
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("v2.0", "2.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0.1", "1.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0-beta", "1.0-alpha"), Ordering::Greater);
+        assert_eq!(compare_versions("0.9", "1"), Ordering::Less);
+    }
+
+    #[test]
+    fn newer_version_is_an_upgrade() {
+        let current = installed(Some("1.0"), "https://a/model.bin", None);
+        assert!(is_newer(
+            &entry(Some("1.1"), "https://a/model.bin", None),
+            &current
+        ));
+        assert!(!is_newer(
+            &entry(Some("1.0"), "https://a/new.bin", None),
+            &current
+        ));
+        assert!(!is_newer(
+            &entry(Some("0.9"), "https://a/new.bin", None),
+            &current
+        ));
+        assert!(!is_newer(&entry(None, "https://a/new.bin", None), &current));
+    }
+
+    #[test]
+    fn unversioned_install_compares_the_file() {
+        let current = installed(None, "https://a/model.bin", Some("abcd"));
+        assert!(!is_newer(
+            &entry(Some("1.0"), "https://a/model.bin", Some("ABCD")),
+            &current
+        ));
+        assert!(is_newer(
+            &entry(Some("1.0"), "https://a/model.bin", Some("ef01")),
+            &current
+        ));
+
+        let current = installed(None, "https://a/model.bin", None);
+        assert!(!is_newer(
+            &entry(Some("1.0"), "https://a/model.bin", Some("ef01")),
+            &current
+        ));
+        assert!(is_newer(
+            &entry(Some("1.0"), "https://a/new.bin", None),
+            &current
+        ));
+    }
+
+    #[test]
+    fn finds_the_registry_listing_a_legacy_install() {
+        let current = installed(
+            None,
+            "https://huggingface.co/org/repo/resolve/main/model.q4_0.bin",
+            None,
+        );
+        assert!(lists_installed(
+            &json!({"huggingface": {"repo": "org/repo"}}),
+            &current
+        ));
+        assert!(!lists_installed(
+            &json!({"huggingface": {"repo": "org/other"}}),
+            &current
+        ));
+        assert!(lists_installed(
+            &json!({"url": current.url.clone()}),
+            &current
+        ));
+        assert!(!lists_installed(
+            &json!({"url": "https://a/model.bin"}),
+            &current
+        ));
+    }
+}
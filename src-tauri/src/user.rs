@@ -4,16 +4,24 @@ use base64::{
     engine::{self, general_purpose},
     Engine as _,
 };
+use chrono::{DateTime, Utc};
+use diesel::deserialize::FromSql;
 use diesel::prelude::*;
-
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::{AsExpression, FromSqlRow};
+use hmac::Hmac;
 use rand::Rng;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
 use uuid::uuid;
 use uuid::Uuid;
 
 const CUSTOM_ENGINE: engine::GeneralPurpose =
     engine::GeneralPurpose::new(&alphabet::URL_SAFE, general_purpose::NO_PAD);
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, FromSqlRow, AsExpression)]
+#[diesel(sql_type = diesel::sql_types::Text)]
 pub struct Permissions {
     // We flatten these in here for easier DB storage.
     pub perm_superuser: bool,
@@ -45,13 +53,77 @@ impl From<&User> for Permissions {
     }
 }
 
+impl Permissions {
+    // Takes the column name, with or without the perm_ prefix.
+    fn field_mut(&mut self, name: &str) -> Result<&mut bool, String> {
+        Ok(match name.strip_prefix("perm_").unwrap_or(name) {
+            "superuser" => &mut self.perm_superuser,
+            "load_llm" => &mut self.perm_load_llm,
+            "unload_llm" => &mut self.perm_unload_llm,
+            "download_llm" => &mut self.perm_download_llm,
+            "session" => &mut self.perm_session,
+            "request_download" => &mut self.perm_request_download,
+            "request_load" => &mut self.perm_request_load,
+            "request_unload" => &mut self.perm_request_unload,
+            "view_llms" => &mut self.perm_view_llms,
+            "bare_model" => &mut self.perm_bare_model,
+            _ => return Err(format!("Unknown permission '{}'", name)),
+        })
+    }
+
+    pub fn set(&mut self, name: &str, value: bool) -> Result<(), String> {
+        *self.field_mut(name)? = value;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<bool, String> {
+        self.clone().field_mut(name).map(|perm| *perm)
+    }
+
+    // True if every permission set here is also set in other.
+    pub fn within(&self, other: &Permissions) -> bool {
+        PERMISSION_NAMES
+            .iter()
+            .all(|name| !self.get(name).unwrap() || other.get(name).unwrap())
+    }
+}
+
+pub const PERMISSION_NAMES: [&str; 10] = [
+    "superuser",
+    "load_llm",
+    "unload_llm",
+    "download_llm",
+    "session",
+    "request_download",
+    "request_load",
+    "request_unload",
+    "view_llms",
+    "bare_model",
+];
+
+// Stored as json, like request::UserRequestType.
+impl FromSql<diesel::sql_types::Text, Sqlite> for Permissions {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> diesel::deserialize::Result<Self> {
+        let str = <String as FromSql<diesel::sql_types::Text, Sqlite>>::from_sql(bytes)?;
+        let value: Permissions = serde_json::from_str(&str)?;
+        Ok(value)
+    }
+}
+
+impl ToSql<diesel::sql_types::Text, Sqlite> for Permissions {
+    fn to_sql<'W>(&'W self, out: &mut Output<'W, '_, Sqlite>) -> serialize::Result {
+        let str = serde_json::to_string(self)?;
+        out.set_value(str);
+        Ok(serialize::IsNull::No)
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Queryable, Selectable, Insertable)]
 #[diesel(table_name = crate::schema::user)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
 pub struct User {
     pub id: DbUuid,
     pub name: String, //self identified, insecure
-    pub api_key: String,
 
     // We flatten these in here for easier DB storage.
     pub perm_superuser: bool,
@@ -84,12 +156,13 @@ pub struct UserInfo {
     pub perm_bare_model: bool,
 }
 
-impl From<&User> for UserInfo {
-    fn from(user: &User) -> Self {
+impl UserInfo {
+    // Keys only exist in clear text at creation time, so we need to be handed one.
+    pub fn new(user: &User, api_key: String) -> Self {
         UserInfo {
             id: user.id.0.clone(),
             name: user.name.clone(),
-            api_key: user.api_key.clone(),
+            api_key,
 
             perm_superuser: user.perm_superuser.clone(),
             perm_load_llm: user.perm_load_llm.clone(),
@@ -110,7 +183,6 @@ impl User {
         User {
             id: DbUuid(Uuid::new_v4()),
             name: name,
-            api_key: generate_api_key(),
             perm_superuser: false,
             perm_load_llm: false,
             perm_unload_llm: false,
@@ -125,20 +197,161 @@ impl User {
     }
 }
 
-// The first time the user gets generated, this API key is in clear text.
-// It gets hashed into the DB, then hashed every time it gets checked in the API layer
-// before being compared to the saved DB value.
+// A user can hold several keys. Each one is named so programs can roll keys
+// without downtime: create the new key, switch over, then revoke the old one.
+#[derive(
+    Debug,
+    Clone,
+    serde::Serialize,
+    serde::Deserialize,
+    Identifiable,
+    Queryable,
+    Selectable,
+    Insertable,
+    Associations,
+)]
+#[diesel(table_name = crate::schema::api_key)]
+#[diesel(belongs_to(User))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct ApiKey {
+    pub id: DbUuid,
+    pub user_id: DbUuid,
+    pub name: String,
+    pub key_hash: String,
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+    pub revoked: bool,
+}
+
+impl ApiKey {
+    // Returns the row to save alongside the clear text key, which is never stored.
+    // The clear text starts with the key's id, see api_key_id.
+    pub fn new(user_id: DbUuid, name: String, expires: Option<DateTime<Utc>>) -> (ApiKey, String) {
+        let id = Uuid::new_v4();
+        let api_key = format!("{}.{}", id, generate_api_key());
+        (
+            ApiKey {
+                id: DbUuid(id),
+                user_id,
+                name,
+                key_hash: hash_api_key(&api_key),
+                created: Utc::now(),
+                expires,
+                revoked: false,
+            },
+            api_key,
+        )
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.revoked && self.expires.map_or(true, |exp| exp > Utc::now())
+    }
+}
+
+// Everything about a key except the hash.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ApiKeyInfo {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+    pub revoked: bool,
+}
+
+impl From<&ApiKey> for ApiKeyInfo {
+    fn from(key: &ApiKey) -> Self {
+        ApiKeyInfo {
+            id: key.id.0.clone(),
+            user_id: key.user_id.0.clone(),
+            name: key.name.clone(),
+            created: key.created.clone(),
+            expires: key.expires.clone(),
+            revoked: key.revoked.clone(),
+        }
+    }
+}
+
+// Returned exactly once, when a key is created.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ApiKeyCreated {
+    pub key_info: ApiKeyInfo,
+    pub api_key: String,
+}
+
+// The first time a key gets generated, it is in clear text.
+// It gets hashed into the DB with a per-key salt, then every presented key is
+// rehashed with that salt and compared in constant time.
 pub fn generate_api_key() -> String {
     let mut rng = rand::thread_rng();
     let key: [u8; 32] = rng.gen();
     CUSTOM_ENGINE.encode(&key)
 }
 
+// Keys are <key id>.<secret>, so authenticating only has to hash against one row.
+// Keys issued before that have no id and '.' isn't in the base64 alphabet.
+pub fn api_key_id(api_key: &str) -> Option<Uuid> {
+    let (id, _secret) = api_key.split_once('.')?;
+    Uuid::parse_str(id).ok()
+}
+
+const KDF_PREFIX: &str = "pbkdf2_sha256";
+const KDF_ROUNDS: u32 = 10_000;
+
+// Stored as pbkdf2_sha256$<rounds>$<salt>$<hash>.
+pub fn hash_api_key(api_key: &str) -> String {
+    let mut rng = rand::thread_rng();
+    let salt: [u8; 16] = rng.gen();
+    let hash = derive_key(api_key, &salt, KDF_ROUNDS);
+    format!(
+        "{}${}${}${}",
+        KDF_PREFIX,
+        KDF_ROUNDS,
+        CUSTOM_ENGINE.encode(&salt),
+        CUSTOM_ENGINE.encode(&hash)
+    )
+}
+
+fn derive_key(api_key: &str, salt: &[u8], rounds: u32) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(api_key.as_bytes(), salt, rounds, &mut out);
+    out
+}
+
+// Rows written before salting hold a bare uppercase hex SHA-256.
+pub fn is_legacy_hash(stored: &str) -> bool {
+    !stored.starts_with(KDF_PREFIX)
+}
+
+pub fn verify_api_key(api_key: &str, stored: &str) -> bool {
+    if is_legacy_hash(stored) {
+        let mut hasher = Sha256::new();
+        hasher.update(api_key);
+        let legacy = format!("{:X}", hasher.finalize());
+        return legacy.as_bytes().ct_eq(stored.as_bytes()).into();
+    }
+
+    let parts: Vec<&str> = stored.split('$').collect();
+    if parts.len() != 4 {
+        return false;
+    }
+    let rounds: u32 = match parts[1].parse() {
+        Ok(rounds) => rounds,
+        Err(_) => return false,
+    };
+    let (salt, expected) = match (CUSTOM_ENGINE.decode(parts[2]), CUSTOM_ENGINE.decode(parts[3])) {
+        (Ok(salt), Ok(expected)) => (salt, expected),
+        _ => return false,
+    };
+    derive_key(api_key, &salt, rounds)[..]
+        .ct_eq(&expected[..])
+        .into()
+}
+
 pub fn get_local_user() -> User {
     User {
         id: DbUuid(uuid!("00000000-0000-0000-0000-000000000000")),
-        name: "local".into(),
-        api_key: "".into(), //This isn't important because local calls skip the user auth layer
+        name: "local".into(), //Local calls skip the user auth layer, the CLI gets a keychain key.
         perm_superuser: true,
         perm_load_llm: false,
         perm_unload_llm: false,
@@ -151,3 +364,52 @@ pub fn get_local_user() -> User {
         perm_bare_model: false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permissions(names: &[&str]) -> Permissions {
+        let mut permissions = Permissions::default();
+        for name in names {
+            permissions.set(name, true).unwrap();
+        }
+        permissions
+    }
+
+    #[test]
+    fn granted_permissions_must_be_requested() {
+        let requested = permissions(&["session", "load_llm", "view_llms"]);
+        assert!(permissions(&[]).within(&requested));
+        assert!(permissions(&["session"]).within(&requested));
+        assert!(requested.within(&requested));
+        assert!(!permissions(&["session", "superuser"]).within(&requested));
+        assert!(!permissions(&["bare_model"]).within(&requested));
+    }
+
+    #[test]
+    fn every_permission_is_checked() {
+        for name in PERMISSION_NAMES {
+            let one = permissions(&[name]);
+            assert!(one.within(&one));
+            assert!(!one.within(&Permissions::default()), "{} not checked", name);
+        }
+    }
+
+    #[test]
+    fn api_keys_carry_their_id() {
+        let (key, api_key) = ApiKey::new(DbUuid(Uuid::new_v4()), "test".into(), None);
+        assert_eq!(api_key_id(&api_key), Some(key.id.0));
+        assert!(verify_api_key(&api_key, &key.key_hash));
+        assert_eq!(api_key_id(&generate_api_key()), None);
+        assert_eq!(api_key_id("not-a-uuid.secret"), None);
+    }
+
+    #[test]
+    fn permission_names_take_the_prefix() {
+        let permissions = permissions(&["perm_session"]);
+        assert_eq!(permissions.get("session"), Ok(true));
+        assert_eq!(permissions.get("perm_load_llm"), Ok(false));
+        assert!(permissions.get("root").is_err());
+    }
+}
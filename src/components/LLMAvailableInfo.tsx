@@ -11,22 +11,26 @@ import {
   Card,
   CardContent,
 } from '@mui/material/';
-import {LLMAvailable} from '../interfaces';
+import {LLMAvailable, UpgradeInfo} from '../interfaces';
 
 type LLMAvailableInfoProps = {
   llm: LLMAvailable
   alreadyLoaded: boolean,
+  upgrade?: UpgradeInfo,
 }
 
 const LLMAvailableInfo: React.FC<LLMAvailableInfoProps> = ({
   llm,
-  alreadyLoaded
+  alreadyLoaded,
+  upgrade
 }) => {
 
   // Use this for enabling the LLM
   const [checked, setChecked] = React.useState(alreadyLoaded);
   const [openModal, setOpenModal] = useState(false);
   const [deleted, setDeleted] = useState(false);
+  const [deleteError, setDeleteError] = useState('');
+  const [upgrading, setUpgrading] = useState(false);
 
   const handleOpenModal = () => {
     setOpenModal(true);
@@ -38,9 +42,24 @@ const LLMAvailableInfo: React.FC<LLMAvailableInfoProps> = ({
 
   const handleConfirmDelete = async () => {
     setOpenModal(false);
-    const result = await invoke('delete_llm', {uuid: llm.uuid});
-    console.log("deleted", llm.id, result);
-    setDeleted(true);
+    try {
+      const result = await invoke('delete_llm', {uuid: llm.uuid});
+      console.log("deleted", llm.id, result);
+      setDeleted(true);
+    } catch (err) {
+      // Running models have to be turned off first.
+      setDeleteError(String(err));
+    }
+  };
+
+  const handleUpgrade = async (keepOld: boolean) => {
+    try {
+      const result = await invoke('upgrade_llm', {uuid: llm.uuid, keepOld: keepOld});
+      console.log("upgrading", llm.id, result);
+      setUpgrading(true);
+    } catch (err) {
+      setDeleteError(String(err));
+    }
   };
 
   const handleToggle = async () => {
@@ -70,7 +89,15 @@ const LLMAvailableInfo: React.FC<LLMAvailableInfoProps> = ({
 
         <Link href={"/history/" + llm.id}>Last Called: {llm.lastCalled ? llm.lastCalled.toString() : "Never"}</Link>
         <Typography variant="body2"><small>Downloaded: {llm.downloaded}</small></Typography>
+        {upgrade && (upgrading ?
+          <Typography variant="body2">Upgrading to version {upgrade.available_version}, see Downloads for progress.</Typography>
+          : <Typography variant="body2">
+            Version {upgrade.available_version} is available.
+            <Button onClick={() => handleUpgrade(false)}>Upgrade</Button>
+            <Button onClick={() => handleUpgrade(true)}>Install alongside</Button>
+          </Typography>)}
         <Button variant="contained" onClick={handleOpenModal} color="error">Delete</Button>
+        {deleteError && <Typography className="error" color="error">{deleteError}</Typography>}
 
         <Modal
           open={openModal}
@@ -11,17 +11,30 @@ import {
   CardContent,
   Typography,
   Box,
+  Select,
+  MenuItem,
 } from '@mui/material';
 import {ModalBox} from '../theme';
-import {LLMRegistry, LLMRegistryEntry, LLMDownloadState, fromLLMRegistryEntry} from '../interfaces';
+import {LLMRegistry, LLMRegistryEntry, LLMDownloadState, HuggingFaceFile, fromLLMRegistryEntry} from '../interfaces';
 import {deleteRegistryEntry} from '../registryHelpers';
 import {Store} from "tauri-plugin-store-api";
 import React, {useEffect, useState, useRef} from 'react';
 
+const downloadStatusText = (event: any): string => {
+  if (event.queued)
+    return 'Queued, waiting for other downloads to finish.';
+  if (!event.bytes_per_second)
+    return '';
+  const speed = `${(event.bytes_per_second / 1e6).toFixed(1)} MB/s`;
+  if (event.eta_seconds === undefined || event.eta_seconds === null)
+    return speed;
+  return `${speed}, ${Math.floor(event.eta_seconds / 60)}m ${event.eta_seconds % 60}s left`;
+}
+
 interface LLMDownloadableInfoProps {
   llm: LLMRegistryEntry,
   registry: LLMRegistry,
-  beginDownload: () => void;
+  beginDownload: (quantization?: string) => void;
   completeDownload: () => void;
 }
 
@@ -31,25 +44,35 @@ const LLMDownloadableInfo: React.FC<LLMDownloadableInfoProps> = ({llm, registry,
 
   const [downloadProgress, setDownloadProgress] = useState('');
   const [deleted, setDeleted] = useState(false);
-  const downloadRef = useRef(downloadProgress);
+  // Counts progress events, a queued download's progress doesn't move.
+  const updateCount = useRef(0);
+  const [downloadStatus, setDownloadStatus] = useState('');
   const [downloadError, setDownloadError] = useState(false);
   const [openDelete, setOpenDelete] = useState(false);
+  // Hugging Face entries can be downloaded in any quantization the repo has.
+  const [quantizations, setQuantizations] = useState<HuggingFaceFile[]>([]);
+  const [quantization, setQuantization] = useState(llm.huggingface?.quantization ?? '');
   const downloadClick = async () => {
     console.log("downloading llm reg", llm);
 
 
     setDownloadError(false);
     setDownloadProgress('0');
+    setDownloadStatus('');
 
     // const result = await invoke('download_llm', {llmReg: fromLLMRegistryEntry(llm)});
     // const backendUuid = (result as any).data.uuid;
-    beginDownload();
+    beginDownload(quantization || undefined);
 
   }
 
   useEffect(() => {
-    downloadRef.current = downloadProgress;
-  }, [downloadProgress]);
+    if (!llm.huggingface || llm.huggingface.filename)
+      return
+    invoke('list_quantizations', {llmReg: fromLLMRegistryEntry(llm)})
+      .then((result: any) => setQuantizations(result.data))
+      .catch((err) => console.error("Couldn't list quantizations", err));
+  }, [llm.id]);
 
   const handleOpenDelete = () => {
     setOpenDelete(true);
@@ -69,14 +92,14 @@ const LLMDownloadableInfo: React.FC<LLMDownloadableInfoProps> = ({llm, registry,
   useEffect(() => {
     let unlisten: (() => void) | undefined;
 
-    const errorCheck = (time: string) => {
+    const errorCheck = (count: number) => {
       setTimeout(() => {
-        if (downloadRef.current === time) {
+        if (updateCount.current === count) {
           setDownloadError(true)
         }
       }, 5000);
     }
-    errorCheck(downloadProgress);
+    errorCheck(updateCount.current);
 
     (async () => {
       unlisten = await listen('downloads', (event: any) => {
@@ -100,10 +123,12 @@ const LLMDownloadableInfo: React.FC<LLMDownloadableInfoProps> = ({llm, registry,
 
 
         setDownloadProgress(event.payload.event.progress);
+        setDownloadStatus(downloadStatusText(event.payload.event));
         setDownloadError(false);
 
         // Set a timer to set error to true if no updates after 5 seconds
-        errorCheck(event.payload.event.progress);
+        updateCount.current += 1;
+        errorCheck(updateCount.current);
       });
     })();
 
@@ -130,11 +155,30 @@ const LLMDownloadableInfo: React.FC<LLMDownloadableInfoProps> = ({llm, registry,
                 <Button variant="contained" onClick={downloadClick} >Retry</Button>
               </Box>)
               :
-              (downloadProgress ?
-                <LinearProgress sx={{width: '100%'}} variant="determinate" value={parseInt(downloadProgress)} />
-                : <LinearProgress sx={{width: '100%'}} variant="indeterminate" />))
+              (<Box sx={{width: '100%'}}>
+                {downloadProgress ?
+                  <LinearProgress sx={{width: '100%'}} variant="determinate" value={parseInt(downloadProgress)} />
+                  : <LinearProgress sx={{width: '100%'}} variant="indeterminate" />}
+                {downloadStatus && <Typography variant="body2">{downloadStatus}</Typography>}
+              </Box>))
             : llm.downloadState === LLMDownloadState.Downloaded ? <Button variant="contained" onClick={downloadClick}>Redownload</Button> : <Button variant="contained" onClick={downloadClick} >Download</Button>
         } />
+        {quantizations.length > 0 && (
+          <Box>
+            <Typography variant="body1"><b>Quantization:</b></Typography>
+            <Select
+              value={quantization}
+              onChange={(event) => {setQuantization(event.target.value)}}
+              name="quantization"
+            >
+              {quantizations.map((file) => (
+                <MenuItem key={file.filename} value={file.quantization ?? ''}>
+                  {file.quantization ?? file.filename}{file.size ? ` (${(file.size / 1e9).toFixed(1)} GB)` : ''}
+                </MenuItem>
+              ))}
+            </Select>
+          </Box>
+        )}
         <Typography variant="body1"><b>Requirements:</b> {llm.requirements}</Typography>
         <Typography variant="body1"><b>Capabilities:</b> {JSON.stringify(llm.capabilities)}</Typography>
         <Button variant="contained" onClick={handleOpenDelete} color="error">Delete</Button>
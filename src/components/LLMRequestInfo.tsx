@@ -19,16 +19,29 @@ interface UserRequestInfoProps {
 export function UserRequestInfo(props: UserRequestInfoProps) {
   const {request} = props;
   const [completed, setCompleted] = useState(false);
+  const [note, setNote] = useState('');
+  // Permission requests can be accepted with some of the permissions switched off.
+  const [granted, setGranted] = useState<{[perm: string]: boolean}>(
+    request.type === UserRequestType.Permission ? {...(request as UserPermissionRequest).request.requestedPermissions as any} : {});
+
+  const grantedPermissions = () => {
+    if (request.type !== UserRequestType.Permission) {
+      return null;
+    }
+    // permLoadLlm -> perm_load_llm
+    return Object.fromEntries(Object.entries(granted).map(([perm, value]) =>
+      [perm.replace(/[A-Z]/g, (c) => '_' + c.toLowerCase()), value]));
+  }
 
   const handleAcceptRequest = async () => {
-    const result = await invoke('accept_request', {requestId: request.id});
+    const result = await invoke('accept_request', {requestId: request.id, note: note || null, grantedPermissions: grantedPermissions()});
     console.log("accepted", request.id, result);
     setCompleted(true);
 
   }
 
   const handleRejectRequest = async () => {
-    const result = await invoke('reject_request', {requestId: request.id});
+    const result = await invoke('reject_request', {requestId: request.id, note: note || null});
     console.log("rejected", request.id, result);
     setCompleted(true);
 
@@ -60,6 +73,9 @@ export function UserRequestInfo(props: UserRequestInfoProps) {
                     <TableRow key={index}>
                       <TableCell>{value ? <b>{perm}</b> : perm}</TableCell>
                       <TableCell>{value ? <b>Yes</b> : "No"}</TableCell>
+                      <TableCell>
+                        {value ? <Switch checked={!!granted[perm]} onChange={(e) => setGranted({...granted, [perm]: e.target.checked})} /> : null}
+                      </TableCell>
                     </TableRow>
                   ))}
                 </TableBody>
@@ -104,6 +120,12 @@ export function UserRequestInfo(props: UserRequestInfoProps) {
         <Typography variant="subtitle2">{request.timestamp.toString()}</Typography>
         <Typography>Requests the following:</Typography>
         {inner()}
+        <TextField
+          label="Note for the program (e.g. why it was rejected)"
+          fullWidth
+          value={note}
+          onChange={(e) => setNote(e.target.value)}
+        />
         <Button variant="contained" onClick={handleAcceptRequest}>Accept</Button>
         <Button variant="contained" color="error" onClick={handleRejectRequest}>Reject</Button>
       </CardContent>
@@ -62,9 +62,20 @@ enum LLMRegistryEntryConnector {
 interface LLMRegistry {
   id: string,
   url: string,
+  trust?: string,
   models: {[id: string]: LLMRegistryEntry},
 }
 
+// What list_registries returns for registries kept by the backend.
+interface RegistryListing {
+  name: string,
+  location: string,
+  trust: string,
+  last_refreshed: string | null,
+  model_count: number,
+  models: {[id: string]: any},
+}
+
 interface LLMRegistryRegistry {
   [url: string]: LLMRegistry
 }
@@ -75,12 +86,42 @@ enum LLMDownloadState {
   Downloaded,
 }
 
+// Resolved into url, size and sha256 by the backend when downloading.
+interface HuggingFaceSource {
+  repo: string;
+  revision?: string;
+  filename?: string;
+  quantization?: string;
+}
+
+interface HuggingFaceFile {
+  filename: string;
+  url: string;
+  size?: number;
+  sha256?: string;
+  quantization?: string;
+}
+
 interface LLMRegistryEntry extends LLM {
 
   connectorType: LLMRegistryEntryConnector;
   downloadState: LLMDownloadState;
   backendUuid: string;
+  sha256?: string;
+  size?: number;
+  huggingface?: HuggingFaceSource;
+  version?: string;
+
+}
 
+// An installed LLM with a newer version in some registry.
+interface UpgradeInfo {
+  llm_uuid: string;
+  id: string;
+  name: string;
+  installed_version?: string;
+  available_version?: string;
+  registry: string;
 }
 export const produceEmptyRegistryEntry = (): LLMRegistryEntry => {
   return {
@@ -164,6 +205,27 @@ function toLLMRegistryEntryExternal(remoteData: LLMRegistryEntry): LLMRegistryEn
 
   });
 
+  // Optional, so they aren't in the empty entry's keys.
+  if (typeof remoteData.sha256 === 'string') {
+    entry.sha256 = remoteData.sha256.replace(/[^0-9a-fA-F]/g, '');
+  }
+  if (typeof remoteData.size === 'number') {
+    entry.size = remoteData.size;
+  }
+  if (typeof remoteData.version === 'string') {
+    entry.version = remoteData.version.replace(/[^\w-.]/g, '');
+  }
+  if (remoteData.huggingface && typeof remoteData.huggingface.repo === 'string') {
+    const hf = remoteData.huggingface;
+    const clean = (value?: string) => typeof value === 'string' ? value.replace(/[^\w-. \/]/g, '') : undefined;
+    entry.huggingface = {
+      repo: clean(hf.repo) as string,
+      revision: clean(hf.revision),
+      filename: clean(hf.filename),
+      quantization: clean(hf.quantization),
+    };
+  }
+
   return {
     ...entry,
     backendUuid: "",  // uuid populated later when download starts
@@ -202,6 +264,10 @@ function fromLLMRegistryEntry(frontendEntry: LLMRegistryEntry): any {
     user_parameters: keysToSnakeCaseUnsafe(frontendEntry.userParameters),
     session_parameters: keysToSnakeCaseUnsafe(frontendEntry.sessionParameters),
     user_session_parameters: keysToSnakeCaseUnsafe(frontendEntry.userSessionParameters),
+    sha256: frontendEntry.sha256,
+    size: frontendEntry.size,
+    huggingface: frontendEntry.huggingface,
+    version: frontendEntry.version,
   };
   console.log("backend entry:", backendEntry);
 
@@ -237,6 +303,7 @@ interface BaseUserRequest {
   originator: string,
   complete: boolean,
   accepted: boolean,
+  decisionNote: string | null,
   requester: string, // This is a uuid
   [addlInfo: string]: unknown
 }
@@ -308,6 +375,7 @@ function toUserRequest(request: any): UserRequest {
     originator: request.originator || '',
     complete: request.complete || false,
     accepted: request.accepted || false,
+    decisionNote: request.decision_note || null,
     requester: request.requester || ''
   };
 
@@ -423,7 +491,7 @@ const keysToSnakeCaseUnsafe = function (o: any) {
 
 
 type DownloadEventType =
-  | {type: "DownloadProgress"; progress: string}
+  | {type: "DownloadProgress"; progress: string; bytes_per_second: number; eta_seconds?: number; queued: boolean}
   | {type: "DownloadCompletion"}
   | {type: "DownloadError"; message: string}
   | {type: "ChannelClose"};
@@ -583,7 +651,11 @@ export type {
   UserUnloadRequest,
   LLMRegistry,
   LLMRegistryRegistry,
+  RegistryListing,
   LLMRegistryEntry,
+  HuggingFaceSource,
+  HuggingFaceFile,
+  UpgradeInfo,
   LLMHistoryItem,
   LLMEventType,
   LLMEventPayload,
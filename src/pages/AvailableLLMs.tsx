@@ -3,7 +3,7 @@
 import React, {useEffect, useContext, useState} from 'react';
 import {invoke} from '@tauri-apps/api/tauri';
 import Link from '@mui/material/Link';
-import {LLMRunning, LLMAvailable, toLLMRunning, toLLMAvailable} from '../interfaces';
+import {LLMRunning, LLMAvailable, UpgradeInfo, toLLMRunning, toLLMAvailable} from '../interfaces';
 import LLMAvailableInfo from '../components/LLMAvailableInfo';
 import Switch from '@mui/material/Switch';
 import {ErrorContext} from '../context';
@@ -16,6 +16,7 @@ import {
 function AvailableLLMs() {
   const [availableLLMs, setAvailableLLMs] = useState<LLMAvailable[]>([]);
   const [activeLlms, setActiveLlms] = useState<LLMRunning[]>([]);
+  const [upgrades, setUpgrades] = useState<UpgradeInfo[]>([]);
   const errorContext = useContext(ErrorContext);
 
   useEffect(() => {
@@ -33,6 +34,10 @@ function AvailableLLMs() {
     };
 
     fetchAvailableLLMs();
+    // Only a hint, so failing to reach a registry isn't an error here.
+    invoke<{data: UpgradeInfo[]}>('list_upgrades', {refresh: false})
+      .then((result) => setUpgrades(result.data))
+      .catch((err) => console.error("Couldn't check for upgrades", err));
   }, []);
 
 
@@ -42,7 +47,7 @@ function AvailableLLMs() {
     <Box>
       <Typography variant="h2">Available Large Language Models</Typography>
       {availableLLMs.map((llm) => (
-        <LLMAvailableInfo llm={llm} alreadyLoaded={Object.entries(activeLlms).map((ent) => ent[1].uuid).includes(llm.uuid)} key={llm.id} />
+        <LLMAvailableInfo llm={llm} alreadyLoaded={Object.entries(activeLlms).map((ent) => ent[1].uuid).includes(llm.uuid)} upgrade={upgrades.find((up) => up.llm_uuid === llm.uuid)} key={llm.id} />
       ))}
     </Box>
   );
@@ -30,7 +30,7 @@ import {Store} from "tauri-plugin-store-api";
 import {fetch} from '@tauri-apps/api/http';
 import {listen} from '@tauri-apps/api/event'
 import {invoke} from '@tauri-apps/api/tauri';
-import {validateRegistryEntry, addRegistry, getRegistries, addRegistryEntry, downloadLLM, regSetDownloaded} from '../registryHelpers';
+import {validateRegistryEntry, addRegistry, removeRegistry, getRegistries, addRegistryEntry, downloadLLM, regSetDownloaded} from '../registryHelpers';
 
 import {LLMRegistryRegistry, LLMRegistry, LLMRegistryEntry, toLLMRegistryEntry, LLMDownloadState, LLMRegistryEntryConnector, LLMAvailable, toLLMAvailable, produceEmptyRegistryEntry} from '../interfaces';
 import LLMDownloadableInfo from '../components/LLMDownloadableInfo';
@@ -41,7 +41,7 @@ const REGISTRIES_STORAGE_KEY = "registries15";
 
 const NEW_REG_HELPER_TEXT = {
   id: 'id/name of the registry',
-  url: 'url of the models.json file'
+  url: 'url or local path of the models.json file'
 };
 
 const NEW_REGISTRY_HELPER_TEXT = {
@@ -103,6 +103,7 @@ function DownloadableLLMs() {
 
   const [newRegistryEntry, setNewRegistryEntry] = useState<LLMRegistryEntry>(produceEmptyRegistryEntry());
   const [newRegistry, setNewRegistry] = useState<{id: string, url: string}>({id: '', url: ''});
+  const [newRegistryTrusted, setNewRegistryTrusted] = useState(false);
   const [newRegistryEntryErrors, setNewRegistryEntryErrors] = useState<{[key: string]: string}>({});
   const [newRegistryErrors, setNewRegistryErrors] = useState<{[key: string]: string}>({});
   //
@@ -126,11 +127,22 @@ function DownloadableLLMs() {
   }, [newRegistryEntryErrors]);
 
   const handleAddRegistry = useCallback(async () => {
-    addRegistry(newRegistry.id, newRegistry.url).then((registries: LLMRegistryRegistry) => {
+    addRegistry(newRegistry.id, newRegistry.url, newRegistryTrusted).then((registries: LLMRegistryRegistry) => {
       setRegistries(registries);
       setNewRegistry({id: '', url: ''});
+      setNewRegistryTrusted(false);
+      refreshData(false);
+    }).catch((err) => {
+      setNewRegistryErrors({url: String(err)});
     });
-  }, [newRegistry]);
+  }, [newRegistry, newRegistryTrusted]);
+
+  const handleRemoveRegistry = useCallback(async (registry: LLMRegistry) => {
+    removeRegistry(registry).then((registries: LLMRegistryRegistry) => {
+      setRegistries(registries);
+      refreshData(false);
+    });
+  }, []);
 
   const handleAddRegistryEntry = async () => {
     if (validateNewRegistryEntry()) {
@@ -310,9 +322,9 @@ function DownloadableLLMs() {
 
 
 
-  const beginDownload = useCallback(async (llm: LLMRegistryEntry, regUrl: string) => {
+  const beginDownload = useCallback(async (llm: LLMRegistryEntry, regUrl: string, quantization?: string) => {
     // We should be able to do this with just saving then refresh_data
-    await downloadLLM(llm, regUrl);
+    await downloadLLM(llm, regUrl, quantization);
 
     refreshData(false);
   }, []);
@@ -601,7 +613,7 @@ function DownloadableLLMs() {
           key={pair[0].id}
           llm={pair[0]}
           registry={pair[1]}
-          beginDownload={() => {beginDownload(pair[0], pair[1].url)}}
+          beginDownload={(quantization?: string) => {beginDownload(pair[0], pair[1].url, quantization)}}
           completeDownload={() => {completeDownload(pair[0].id, pair[1].url)}}
         />
       ))}
@@ -622,10 +634,25 @@ function DownloadableLLMs() {
                   onChange={handleRegistryInputChange}
                 />
               )}
+              <FormControlLabel
+                control={<Checkbox checked={newRegistryTrusted} onChange={(event) => setNewRegistryTrusted(event.target.checked)} />}
+                label="Trusted (wins when registries list the same model id)"
+              />
               <Button variant="contained" color="primary" onClick={handleAddRegistry}>
                 Submit
               </Button>
 
+              <Divider sx={{my: 2}} />
+              <Typography variant="h6">Registries</Typography>
+              {Object.values(registries as LLMRegistryRegistry).filter((reg) => reg.url !== 'local' && reg.url !== 'shared').map((reg) => (
+                <Box key={reg.url} sx={{display: 'flex', alignItems: 'center', justifyContent: 'space-between'}}>
+                  <Typography>{reg.id} ({reg.trust || 'untrusted'}) — {reg.url}</Typography>
+                  <Button size="small" color="secondary" onClick={() => handleRemoveRegistry(reg)}>
+                    Remove
+                  </Button>
+                </Box>
+              ))}
+
             </CardContent>
           </Card>
         </ModalBox>
@@ -28,6 +28,34 @@ function Settings() {
   const [loading, setLoading] = useState(false);
   const [preferredActive, setPreferredActive] = useState(3);
   const [dedupDownloads, setDedupDownloads] = useState(true);
+  const [huggingfaceEndpoint, setHuggingfaceEndpoint] = useState('');
+  const [maxConcurrentDownloads, setMaxConcurrentDownloads] = useState(2);
+  // In MB/s, empty for no limit.
+  const [downloadLimit, setDownloadLimit] = useState('');
+  // In hours, empty to keep requests until they're decided.
+  const [requestTtl, setRequestTtl] = useState('');
+  const [autoApprovalRules, setAutoApprovalRules] = useState('[]');
+  // Listener settings restart the API, so we only send them when they change.
+  const [listenerSettings, setListenerSettings] = useState<any>({});
+  const [savedListenerSettings, setSavedListenerSettings] = useState<any>({});
+
+  const loadListenerSettings = (settings: any) => {
+    const listener = {
+      api_bind_address: settings.api_bind_address,
+      api_port: settings.api_port,
+      enable_tcp_listener: settings.enable_tcp_listener,
+      enable_socket_listener: settings.enable_socket_listener,
+      socket_path: settings.socket_path,
+      tls_cert_path: settings.tls_cert_path,
+      tls_key_path: settings.tls_key_path,
+    };
+    setListenerSettings(listener);
+    setSavedListenerSettings(listener);
+  };
+
+  const setListenerSetting = (key: string, value: any) => {
+    setListenerSettings({...listenerSettings, [key]: value});
+  };
 
   useEffect(() => {
     invoke('get_user_settings').then((settings: any) => {
@@ -36,11 +64,19 @@ function Settings() {
       setNBatch(settings.n_batch);
       setPreferredActive(settings.preferred_active_sessions);
       setDedupDownloads(settings.dedup_downloads);
+      setHuggingfaceEndpoint(settings.huggingface_endpoint);
+      setMaxConcurrentDownloads(settings.max_concurrent_downloads);
+      setDownloadLimit(settings.download_bytes_per_second ? String(settings.download_bytes_per_second / 1e6) : '');
+      setRequestTtl(settings.request_ttl_seconds ? String(settings.request_ttl_seconds / 3600) : '');
+      setAutoApprovalRules(JSON.stringify(settings.auto_approval_rules, null, 2));
+      loadListenerSettings(settings);
     });
   }, []);
 
   const handleSave = () => {
     setLoading(true);
+    const changedListenerKeys = Object.keys(listenerSettings)
+      .filter((key) => listenerSettings[key] !== savedListenerSettings[key]);
     Promise.all([
       openaiKey ? invoke('set_user_setting', {key: 'openai_key', value: openaiKey}) : Promise.resolve(),
       invoke('set_user_setting', {key: 'use_gpu', value: useGpu}),
@@ -48,7 +84,17 @@ function Settings() {
       invoke('set_user_setting', {key: 'n_batch', value: nBatch}),
       invoke('set_user_setting', {key: 'preferred_active_sessions', value: preferredActive}),
       invoke('set_user_setting', {key: 'dedup_downloads', value: dedupDownloads}),
+      invoke('set_user_setting', {key: 'huggingface_endpoint', value: huggingfaceEndpoint}),
+      invoke('set_user_setting', {key: 'max_concurrent_downloads', value: maxConcurrentDownloads}),
+      invoke('set_user_setting', {key: 'download_bytes_per_second', value: downloadLimit ? Math.round(parseFloat(downloadLimit) * 1e6) : null}),
+      invoke('set_user_setting', {key: 'request_ttl_seconds', value: requestTtl ? Math.round(parseFloat(requestTtl) * 3600) : null}),
+      // Parsed inside the chain so invalid JSON ends up in catch.
+      Promise.resolve().then(() => invoke('set_user_setting', {key: 'auto_approval_rules', value: JSON.parse(autoApprovalRules)})),
+      ...changedListenerKeys
+        .map((key) => invoke('set_user_setting', {key: key, value: listenerSettings[key] === '' ? null : listenerSettings[key]})),
     ])
+      // Restart once for the whole save rather than once per changed key.
+      .then(() => changedListenerKeys.length ? invoke('restart_listeners') : undefined)
       .then(() => invoke('get_user_settings'))
       .then((settings: any) => {
         setOpenaiKey('');
@@ -57,6 +103,12 @@ function Settings() {
         setNBatch(settings.n_batch);
         setPreferredActive(settings.preferred_active_sessions);
         setDedupDownloads(settings.dedup_downloads);
+        setHuggingfaceEndpoint(settings.huggingface_endpoint);
+        setMaxConcurrentDownloads(settings.max_concurrent_downloads);
+        setDownloadLimit(settings.download_bytes_per_second ? String(settings.download_bytes_per_second / 1e6) : '');
+        setRequestTtl(settings.request_ttl_seconds ? String(settings.request_ttl_seconds / 3600) : '');
+        setAutoApprovalRules(JSON.stringify(settings.auto_approval_rules, null, 2));
+        loadListenerSettings(settings);
         setLoading(false);
       })
       .catch((err) => {
@@ -103,6 +155,73 @@ function Settings() {
           control={<Switch checked={dedupDownloads} onChange={(e) => setDedupDownloads(e.target.checked)} />}
           label="Dedup Downloads (if a new LLM downlaods from the same URL as an existing LLM, will skip download and use the same model file)"
         />
+        <TextField
+          label="Hugging Face Endpoint (hub url or local mirror directory)"
+          value={huggingfaceEndpoint}
+          onChange={(e) => setHuggingfaceEndpoint(e.target.value)}
+        />
+        <TextField
+          label="Max Concurrent Downloads (more are queued)"
+          type="number"
+          value={maxConcurrentDownloads}
+          onChange={(e) => setMaxConcurrentDownloads(parseInt(e.target.value))}
+        />
+        <TextField
+          label="Download Bandwidth Limit in MB/s (shared by all downloads, empty for none)"
+          type="number"
+          value={downloadLimit}
+          onChange={(e) => setDownloadLimit(e.target.value)}
+        />
+        <Typography variant="h5">Requests</Typography>
+        <TextField
+          label="Request Expiry in hours (pending requests are rejected after this, empty for never)"
+          type="number"
+          value={requestTtl}
+          onChange={(e) => setRequestTtl(e.target.value)}
+        />
+        <TextField
+          label='Auto-Approval Rules (JSON, e.g. [{"request_type": "load", "user_permissions": ["session"], "downloaded_only": true}])'
+          multiline
+          minRows={3}
+          value={autoApprovalRules}
+          onChange={(e) => setAutoApprovalRules(e.target.value)}
+        />
+        <Typography variant="h5">API Listeners</Typography>
+        <Typography variant="body1">Changes here restart the API listeners.</Typography>
+        <FormControlLabel
+          control={<Switch checked={!!listenerSettings.enable_tcp_listener} onChange={(e) => setListenerSetting('enable_tcp_listener', e.target.checked)} />}
+          label="Enable TCP listener"
+        />
+        <TextField
+          label="Bind Address (use 0.0.0.0 to expose pantry on every network interface)"
+          value={listenerSettings.api_bind_address ?? ''}
+          onChange={(e) => setListenerSetting('api_bind_address', e.target.value)}
+        />
+        <TextField
+          label="Port"
+          type="number"
+          value={listenerSettings.api_port ?? ''}
+          onChange={(e) => setListenerSetting('api_port', parseInt(e.target.value))}
+        />
+        <TextField
+          label="TLS Certificate Path (PEM, optional)"
+          value={listenerSettings.tls_cert_path ?? ''}
+          onChange={(e) => setListenerSetting('tls_cert_path', e.target.value)}
+        />
+        <TextField
+          label="TLS Key Path (PKCS #8 PEM, optional)"
+          value={listenerSettings.tls_key_path ?? ''}
+          onChange={(e) => setListenerSetting('tls_key_path', e.target.value)}
+        />
+        <FormControlLabel
+          control={<Switch checked={!!listenerSettings.enable_socket_listener} onChange={(e) => setListenerSetting('enable_socket_listener', e.target.checked)} />}
+          label="Enable unix socket listener"
+        />
+        <TextField
+          label="Socket Path (empty for the default)"
+          value={listenerSettings.socket_path ?? ''}
+          onChange={(e) => setListenerSetting('socket_path', e.target.value)}
+        />
         <IconButton onClick={handleSave} disabled={loading}>
           {loading ? <CircularProgress size={24} /> : <SaveIcon />}
         </IconButton>
@@ -1,96 +1,57 @@
-import {LLMRegistryEntry, LLMRegistryEntryConnector, LLMDownloadState, LLMRegistryRegistry, LLMRegistry, toLLMRegistryEntryExternal, LLMAvailable, toLLMAvailable} from "./interfaces";
+import {LLMRegistryEntry, LLMRegistryEntryConnector, LLMDownloadState, LLMRegistryRegistry, LLMRegistry, RegistryListing, toLLMRegistryEntryExternal, LLMAvailable, toLLMAvailable} from "./interfaces";
 import {Store} from "tauri-plugin-store-api";
 import {produceEmptyRegistryEntry, fromLLMRegistryEntry} from "./interfaces";
 import {invoke} from '@tauri-apps/api/tauri';
 
-const LLM_INFO_SOURCE = "https://raw.githubusercontent.com/JuliaMerz/pantry/master/models/index.json";
 const REGISTRIES_STORAGE_KEY = "registries23";
 
 const store = new Store(".local.dat");
 
+// Remote registries (and their offline cache) live in the backend, the store only keeps
+// the local/shared registries and per model download state.
 export async function getRegistries(forceRemoteRefresh: boolean): Promise<LLMRegistryRegistry> {
-  return store.get(REGISTRIES_STORAGE_KEY).
-    then(async (registries: any) => {
-      // This means we don't ahve any registries stored. Start with default.
-      if (!registries) {
-        // would do setRegistries([]) but it's the default
-        const local: LLMRegistry = {
-          id: "local",
-          url: "local",
-          models: {}
-        }
-        const shared: LLMRegistry = {
-          id: "shared",
-          url: "shared",
-          models: {}
-        }
-        await store.set(REGISTRIES_STORAGE_KEY, {local: local, shared: shared})
-        await store.save()
-        let defaultReg: LLMRegistry = {
-          id: 'default',
-          url: LLM_INFO_SOURCE,
-          models: {}
-        }
-        await addRegistry(defaultReg.id, defaultReg.url);
-        //We should hit the else case this time
-        return getRegistries(false);
-      } else {
-        let regs = registries as LLMRegistryRegistry;
-
-        // If force refresh, we want to redownload all models
-        if (forceRemoteRefresh) {
-
-          for (let url in regs) {
-            if (url === 'local' || url === 'shared') {
-              continue;
-            }
-            let response = await fetch(url);
-            const remoteData = await response.json() as any;
-            const models = remoteData.models as {[id: string]: LLMRegistryEntry};
-
-            // Convert each model to an LLMRegistryEntry and add it to the registryEntries array
-            // FOR BACKEND MODELS WE ASSUME UNIQUE IDs. THIS DOES NOT HOLD TRUE FOR LOCAL/SHARED
-            // this makes sense when you consider that backend comes from one service
-            // whereas local/shared comes from many people
-            Object.entries(models).forEach(([key, model], index) => {
-              const registryEntry: LLMRegistryEntry = toLLMRegistryEntryExternal(model);
-              regs[url].models[key] = registryEntry;
-            });
-
-          }
-          await store.set(REGISTRIES_STORAGE_KEY, regs);
-          await store.save();
-
-        }
-
-        console.log("Returned registries", regs)
-
-        return regs;
-      }
-    }).catch(async (err: any) => {
-      // would do setRegistries([]) but it's the default
-      const local: LLMRegistry = {
-        id: "local",
-        url: "local",
-        models: {}
-      }
-      const shared: LLMRegistry = {
-        id: "shared",
-        url: "shared",
-        models: {}
-      }
-      await store.set(REGISTRIES_STORAGE_KEY, {local: local, shared: shared})
-      await store.save()
-      let defaultReg: LLMRegistry = {
-        id: 'default',
-        url: LLM_INFO_SOURCE,
-        models: {}
+  let stored: LLMRegistryRegistry = {};
+  try {
+    stored = ((await store.get(REGISTRIES_STORAGE_KEY)) as LLMRegistryRegistry) || {};
+  } catch (err) {
+    console.log("Failed to read stored registries", err);
+  }
+
+  const regs: LLMRegistryRegistry = {
+    local: stored.local || {id: "local", url: "local", models: {}},
+    shared: stored.shared || {id: "shared", url: "shared", models: {}},
+  };
+
+  const result = await invoke<{data: RegistryListing[]}>('list_registries', {refresh: forceRemoteRefresh});
+  for (const remote of result.data) {
+    const previous = stored[remote.location];
+    const reg: LLMRegistry = {
+      id: remote.name,
+      url: remote.location,
+      trust: remote.trust,
+      models: {},
+    };
+
+    // FOR BACKEND MODELS WE ASSUME UNIQUE IDs. THIS DOES NOT HOLD TRUE FOR LOCAL/SHARED
+    // this makes sense when you consider that backend comes from one service
+    // whereas local/shared comes from many people
+    Object.entries(remote.models).forEach(([key, model]) => {
+      const registryEntry: LLMRegistryEntry = toLLMRegistryEntryExternal(model as LLMRegistryEntry);
+      const known = previous ? previous.models[key] : undefined;
+      if (known) {
+        registryEntry.backendUuid = known.backendUuid;
+        registryEntry.downloadState = known.downloadState;
       }
-      await addRegistry(defaultReg.id, defaultReg.url);
-      //We should hit the else case this time
-      return getRegistries(false);
+      reg.models[key] = registryEntry;
     });
+    regs[remote.location] = reg;
+  }
+
+  await store.set(REGISTRIES_STORAGE_KEY, regs);
+  await store.save();
 
+  console.log("Returned registries", regs)
+  return regs;
 }
 
 
@@ -119,44 +80,15 @@ export const validateRegistryEntry = (entry: LLMRegistryEntry) => {
 const capitalizeFirstLetter = (string: string) => string.charAt(0).toUpperCase() + string.slice(1);
 
 
-export async function addRegistry(id: string, url: string): Promise<LLMRegistryRegistry> {
-  const newReg: LLMRegistry = {
-    id: id,
-    url: url,
-    models: {},
-  };
-
-  // Fetch data from the new URL and extract models
-  const response = await fetch(url);
-  const remoteData = await response.json() as any;
-  const models = remoteData.models as {[key: string]: LLMRegistryEntry};
-
-
-  // Convert each model to an LLMRegistryEntry and add it to the registryEntries array
-  for (const id in models) {
-    const registryEntry: LLMRegistryEntry = toLLMRegistryEntryExternal(models[id]);
-    newReg.models[id] = registryEntry;
-  }
-
-
-  // Save the updated registryEntries to the store
-
-  // Save the updated registries list
-  const registries: LLMRegistryRegistry = await getRegistries(false);
-  if (!registries[url]) {
-    registries[url] = newReg;
-  } else {
-    // This registry already exists, we need to either
-    if (registries[url].id !== id)
-      throw Error("Registry url already exists, but type doesn't match up. Aborting.")
-    registries[url] = newReg;
-  }
-
-  console.log("Updating store registries");
-  await store.set(REGISTRIES_STORAGE_KEY, registries);
-  await store.save();
+export async function addRegistry(id: string, url: string, trusted: boolean = false): Promise<LLMRegistryRegistry> {
+  // The backend fetches the registry once, so a bad url fails here.
+  await invoke('add_registry', {name: id, location: url, trust: trusted ? 'trusted' : 'untrusted'});
+  return getRegistries(false);
+}
 
-  return registries;
+export async function removeRegistry(registry: LLMRegistry): Promise<LLMRegistryRegistry> {
+  await invoke('remove_registry', {name: registry.id});
+  return getRegistries(false);
 }
 
 export async function addRegistryEntry(model: LLMRegistryEntry, location: string): Promise<string> {
@@ -176,8 +108,8 @@ export async function addRegistryEntry(model: LLMRegistryEntry, location: string
 }
 
 
-export async function downloadLLM(llm: LLMRegistryEntry, regUrl: string) {
-  const result = await invoke('download_llm', {llmReg: fromLLMRegistryEntry(llm)});
+export async function downloadLLM(llm: LLMRegistryEntry, regUrl: string, quantization?: string) {
+  const result = await invoke('download_llm', {llmReg: fromLLMRegistryEntry(llm), quantization: quantization ?? null});
   const backendUuid = (result as any).data.uuid;
 
   return getRegistries(false).then((regs) => {